@@ -1,3 +1,19 @@
 pub const EIP712_DOMAIN_TYPE_NAME: &'static str = "EIP712Domain";
 
 pub const CIP23_DOMAIN_TYPE_NAME: &'static str = "CIP23Domain";
+
+// bits of the type descriptor byte of a field definition
+/// Set when the field is an array, followed by its levels
+pub const FLAG_ARRAY: u8 = 0x80;
+/// Set when a size byte follows, only for int, uint and fixed bytes
+pub const FLAG_SIZE: u8 = 0x40;
+/// Low bits holding the type id
+pub const TYPE_ID_MASK: u8 = 0x0F;
+
+// record tags of a struct definition stream
+/// Starts a struct, followed by its length-prefixed name
+pub const STRUCT_RECORD_NAME: u8 = 0x00;
+/// Names the primary type, followed by its length-prefixed name
+pub const STRUCT_RECORD_PRIMARY_TYPE: u8 = 0x01;
+/// Adds a field to the current struct, followed by its field definition
+pub const STRUCT_RECORD_FIELD: u8 = 0xFF;
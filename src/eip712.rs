@@ -1,19 +1,26 @@
 use crate::{
-    CIP23_DOMAIN_TYPE_NAME,
-    parser::{TypeSchema, build_schema},
+    CIP23_DOMAIN_TYPE_NAME, EIP712_DOMAIN_TYPE_NAME,
+    format::write_hex_prefixed,
+    parser::{
+        EncodeOptions, SchemaNode, SchemaNodeKind, TypeSchema, at_path, build_schema,
+        is_hashed_primitive, join_path, read_presence, zero_data_items,
+    },
     types::Eip712StructDefinitions,
     utils::*,
 };
+#[cfg(feature = "std")]
+use crate::{Resolver, TypedData, parser::build_value, types::build_resolver_from_struct_defs};
 use alloc::{
     borrow::ToOwned,
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     format,
     string::{String, ToString},
     vec,
     vec::Vec,
 };
-use alloy_primitives::{Address, B256, Keccak256, utils::keccak256};
+use alloy_primitives::{Address, B256, Keccak256, U256, utils::keccak256};
 use alloy_sol_types::{Eip712Domain, SolValue};
+use core::cell::RefCell;
 
 pub fn encode_types_without_sub_type(
     struct_defs: &Eip712StructDefinitions,
@@ -38,13 +45,44 @@ pub fn encode_types_without_sub_type(
     Ok(res)
 }
 
-// return sorted sub custom types
+// return sorted sub custom types, the type itself is excluded even if it references itself.
+// the BTreeSet keeps the names in byte-wise order, which is the canonical EIP-712 order
 pub fn find_sub_custom_types(
     struct_defs: &Eip712StructDefinitions,
     type_name: &String,
 ) -> Result<Vec<String>, String> {
-    let mut res = vec![];
+    let mut found = BTreeSet::new();
+    collect_sub_custom_types(struct_defs, type_name, &mut found)?;
+    found.remove(type_name);
 
+    Ok(found.into_iter().collect())
+}
+
+// the custom types referenced by the fields of `type_name` itself, deduped, in field order
+pub fn direct_custom_types(
+    struct_defs: &Eip712StructDefinitions,
+    type_name: &String,
+) -> Result<Vec<String>, String> {
+    let field_defs = struct_defs
+        .get(type_name)
+        .ok_or(format!("{} field defs not found", type_name))?;
+    let mut res: Vec<String> = Vec::new();
+    for custom_type in field_defs
+        .iter()
+        .filter_map(|f| f.field_type.custom_type_name())
+    {
+        if !res.iter().any(|t| t == custom_type) {
+            res.push(custom_type.to_string());
+        }
+    }
+    Ok(res)
+}
+
+fn collect_sub_custom_types(
+    struct_defs: &Eip712StructDefinitions,
+    type_name: &String,
+    found: &mut BTreeSet<String>,
+) -> Result<(), String> {
     let field_defs = struct_defs
         .get(type_name)
         .ok_or(format!("{} field defs not found", type_name))?;
@@ -54,20 +92,16 @@ pub fn find_sub_custom_types(
         }
 
         let custom_type = f.field_type.type_string();
-
-        let sub_custom_types = find_sub_custom_types(struct_defs, &custom_type)?;
-        res.extend(sub_custom_types);
-
-        res.push(custom_type);
+        // visit every type once, so self or mutually referencing types terminate
+        if found.insert(custom_type.clone()) {
+            collect_sub_custom_types(struct_defs, &custom_type, found)?;
+        }
     }
 
-    // sort and removes consecutive repeated elements
-    res.sort();
-    res.dedup();
-
-    Ok(res)
+    Ok(())
 }
 
+// the primary type goes first, then the referenced types sorted by name
 pub fn encode_type(
     struct_types: &BTreeMap<String, String>,
     struct_defs: &Eip712StructDefinitions,
@@ -76,6 +110,7 @@ pub fn encode_type(
     let mut type_str = struct_types.get(type_name).ok_or("not found")?.to_owned();
 
     let sub_customs = find_sub_custom_types(struct_defs, type_name)?;
+    debug_assert!(sub_customs.windows(2).all(|w| w[0] < w[1]));
 
     for custom in &sub_customs {
         let custom_type = struct_types.get(custom).ok_or("not found")?;
@@ -85,6 +120,21 @@ pub fn encode_type(
     Ok(type_str)
 }
 
+// keccak256(encodeType(primaryType)) with the primary and sorted sub type strings fed to the
+// hasher one by one instead of being concatenated first
+pub fn type_hash_streaming(
+    struct_types: &BTreeMap<String, String>,
+    struct_defs: &Eip712StructDefinitions,
+    type_name: &String,
+) -> Result<B256, String> {
+    let mut hasher = Keccak256::new();
+    hasher.update(struct_types.get(type_name).ok_or("not found")?);
+    for custom in &find_sub_custom_types(struct_defs, type_name)? {
+        hasher.update(struct_types.get(custom).ok_or("not found")?);
+    }
+    Ok(hasher.finalize())
+}
+
 pub fn encode_all_struct_type(
     struct_defs: &Eip712StructDefinitions,
 ) -> Result<BTreeMap<String, String>, String> {
@@ -99,98 +149,184 @@ pub fn encode_all_struct_type(
     Ok(res)
 }
 
-pub fn encode_data(
-    schema: &TypeSchema,
+// typeHash = keccak256(encodeType(primaryType))
+pub fn type_hash(
+    struct_defs: &Eip712StructDefinitions,
+    primary_type: &String,
+) -> Result<B256, String> {
+    let struct_types = encode_types_without_sub_type(struct_defs)?;
+    let type_str = encode_type(&struct_types, struct_defs, primary_type)?;
+    Ok(keccak256(type_str.as_bytes()))
+}
+
+/// Stable cache key of the shape of a message, keccak256(encodeType(primaryType)).
+/// It only depends on the types reachable from `primary_type` and their field order,
+/// never on the field values
+pub fn schema_fingerprint(
+    struct_defs: &Eip712StructDefinitions,
+    primary_type: &String,
+) -> Result<B256, String> {
+    type_hash(struct_defs, primary_type)
+}
+
+/// `schema_fingerprint` as a 0x prefixed lowercase hex string
+pub fn schema_fingerprint_hex(
+    struct_defs: &Eip712StructDefinitions,
+    primary_type: &String,
+) -> Result<String, String> {
+    let fingerprint = schema_fingerprint(struct_defs, primary_type)?;
+    let mut res = String::new();
+    write_hex_prefixed(&mut res, fingerprint.as_slice());
+    Ok(res)
+}
+
+pub fn encode_data<S: SchemaNode>(
+    schema: &S,
     struct_types: &BTreeMap<String, String>,
     data: &mut impl Iterator<Item = Vec<u8>>,
 ) -> Result<Vec<u8>, String> {
-    let res = match schema {
-        TypeSchema::Primitive { name, size } => {
-            let raw = data.next().ok_or("build value data.next failed")?;
-            match name.as_str() {
-                "bool" => {
-                    let b = raw[0] == 1;
-                    b.abi_encode()
-                }
-                "int" => {
-                    if size.is_none() {
-                        return Err("size info lacked".into());
-                    }
-                    let size = size.unwrap() as usize;
-                    if raw.len() <= 16 && size <= 16 {
-                        let val = parse_i128(&raw, size)?;
-                        val.abi_encode()
-                    } else {
-                        let val = parse_i256(&raw, size)?;
-                        val.abi_encode()
-                    }
-                }
-                "uint" => {
-                    if size.is_none() {
-                        return Err("size info lacked".into());
-                    }
-                    if raw.len() <= 16 {
-                        let val = parse_u128(&raw)?;
-                        val.abi_encode()
-                    } else {
-                        let val = parse_u256(&raw)?;
-                        val.abi_encode()
-                    }
-                }
-                "address" => {
-                    if raw.len() != 20 {
-                        return Err("invalid address len".into());
-                    }
-                    let addr = Address::from_slice(&raw);
-                    addr.abi_encode()
+    encode_data_with_options(schema, struct_types, data, &EncodeOptions::default())
+}
+
+pub fn encode_data_with_options<S: SchemaNode>(
+    schema: &S,
+    struct_types: &BTreeMap<String, String>,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+    opts: &EncodeOptions,
+) -> Result<Vec<u8>, String> {
+    encode_data_at(schema, struct_types, data, opts, "", &mut ())
+}
+
+/// `(path, word)` pairs of the encoded members
+#[cfg(feature = "trace")]
+pub type TracedWords = Vec<(String, Vec<u8>)>;
+
+/// Same as `encode_data`, also returns the `(path, word)` pairs of every member, a struct
+/// member's word is its struct hash. Nested members come before the member containing them
+#[cfg(feature = "trace")]
+pub fn encode_data_traced<S: SchemaNode>(
+    schema: &S,
+    struct_types: &BTreeMap<String, String>,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+) -> Result<(Vec<u8>, TracedWords), String> {
+    let mut words = vec![];
+    let encoded = encode_data_at(
+        schema,
+        struct_types,
+        data,
+        &EncodeOptions::default(),
+        "",
+        &mut words,
+    )?;
+    Ok((encoded, words))
+}
+
+// records the member words, the unit tracer is a no-op so the untraced path stays lean
+trait EncodeTrace {
+    fn word(&mut self, path: &str, word: &[u8]);
+}
+
+impl EncodeTrace for () {
+    fn word(&mut self, _path: &str, _word: &[u8]) {}
+}
+
+#[cfg(feature = "trace")]
+impl EncodeTrace for TracedWords {
+    fn word(&mut self, path: &str, word: &[u8]) {
+        self.push((path.to_string(), word.to_vec()));
+    }
+}
+
+// encode a member and hash it if it is a struct, ie: the 32 bytes word of an array item or field
+fn encode_member<S: SchemaNode>(
+    schema: &S,
+    struct_types: &BTreeMap<String, String>,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+    opts: &EncodeOptions,
+    path: &str,
+    trace: &mut impl EncodeTrace,
+) -> Result<Vec<u8>, String> {
+    let mut value = encode_data_at(schema, struct_types, data, opts, path, trace)?;
+    if let SchemaNodeKind::Struct { name, .. } = schema.kind() {
+        let type_str = struct_types.get(name).ok_or("not found")?;
+        value = hash_struct(type_str, &value).to_vec();
+    }
+    trace.word(path, &value);
+    Ok(value)
+}
+
+// errors are prefixed with the path of the failing field, eg: from.wallets[2]: invalid address len
+fn encode_data_at<S: SchemaNode>(
+    schema: &S,
+    struct_types: &BTreeMap<String, String>,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+    opts: &EncodeOptions,
+    path: &str,
+    trace: &mut impl EncodeTrace,
+) -> Result<Vec<u8>, String> {
+    let res = match schema.kind() {
+        SchemaNodeKind::Primitive { name, size } => {
+            let raw = data
+                .next()
+                .ok_or_else(|| at_path(path, "build value data.next failed"))?;
+            if opts.prehashed.contains(path) {
+                if !is_hashed_primitive(name, size) {
+                    return Err(at_path(path, "pre-hashed value not allowed"));
                 }
-                "bytes" => {
-                    if let Some(s) = size {
-                        if raw.len() != *s as usize {
-                            return Err("invalid fixed bytes len".into());
-                        }
-                        let mut buf = [0u8; 32];
-                        buf[0..*s as usize].copy_from_slice(&raw);
-                        let fixed_b = B256::from(buf);
-                        fixed_b.abi_encode()
-                    } else {
-                        keccak256(raw).to_vec()
-                    }
+                if raw.len() != 32 {
+                    return Err(at_path(path, "invalid pre-hashed value len"));
                 }
-                "string" => keccak256(raw).to_vec(),
-                _ => unreachable!(),
+                raw
+            } else {
+                encode_primitive(name, size, &raw, opts).map_err(|err| at_path(path, &err))?
             }
         }
-        TypeSchema::Array { item } => {
-            let len_v = data.next().ok_or("build value data.next failed")?;
+        SchemaNodeKind::Array { item } => {
+            let len_v = data
+                .next()
+                .ok_or_else(|| at_path(path, "build value data.next failed"))?;
             if len_v.len() != 1 {
-                return Err("invalid array size len".to_string());
+                return Err(at_path(path, "invalid array size len"));
             }
             let len = len_v[0];
+            // an empty array is encoded as keccak256 of the empty concatenation, ie: keccak256([])
             let mut arr = vec![];
 
-            for _ in 0..len {
-                let mut tmp_value = encode_data(item, struct_types, data)?;
-
-                if let TypeSchema::Struct { name, fields: _ } = item.as_ref() {
-                    let type_str = struct_types.get(name).ok_or("not found")?;
-                    tmp_value = hash_struct(type_str, &tmp_value).to_vec();
-                }
-                arr.extend(tmp_value);
+            for i in 0..len {
+                let item_path = format!("{}[{}]", path, i);
+                arr.extend(encode_member(
+                    item,
+                    struct_types,
+                    data,
+                    opts,
+                    &item_path,
+                    trace,
+                )?);
             }
 
             keccak256(arr).to_vec()
         }
-        TypeSchema::Struct { name: _, fields } => {
+        SchemaNodeKind::Struct { field_count, .. } => {
             let mut encoded_data = vec![];
-            for f in fields {
-                let mut f_data = encode_data(&f.ty, struct_types, data)?;
-
-                if let TypeSchema::Struct { name, fields: _ } = &f.ty {
-                    let type_str = struct_types.get(name).ok_or("not found")?;
-                    f_data = hash_struct(type_str, &f_data).to_vec();
-                }
-
+            for index in 0..field_count {
+                let (f_name, f_ty) = schema.field(index).ok_or("field not found")?;
+                let f_path = join_path(path, f_name);
+                let f_data = if opts.optional.contains(&f_path) && !read_presence(data, &f_path)? {
+                    let mut zero_items = vec![];
+                    zero_data_items(f_ty, &mut zero_items);
+                    let default_opts = EncodeOptions::default();
+                    let mut zero_items = zero_items.into_iter();
+                    encode_member(
+                        f_ty,
+                        struct_types,
+                        &mut zero_items,
+                        &default_opts,
+                        &f_path,
+                        trace,
+                    )?
+                } else {
+                    encode_member(f_ty, struct_types, data, opts, &f_path, trace)?
+                };
                 encoded_data.extend(f_data);
             }
 
@@ -200,7 +336,110 @@ pub fn encode_data(
     Ok(res)
 }
 
-pub fn hash_struct(type_str: &String, encoded_data: &Vec<u8>) -> B256 {
+fn encode_primitive(
+    name: &str,
+    size: Option<u8>,
+    raw: &[u8],
+    opts: &EncodeOptions,
+) -> Result<Vec<u8>, String> {
+    let res = match name {
+        "bool" => {
+            let b = parse_bool(raw)?;
+            b.abi_encode()
+        }
+        "int" => {
+            let size = size.ok_or("size info lacked")? as usize;
+            // the declared size picks the path, a short value is zero padded to `size` before
+            // its sign is taken, so int136..int256 always go through the 256 bits parser
+            if size <= 16 {
+                let val = parse_i128(raw, size)?;
+                val.abi_encode()
+            } else {
+                let val = parse_i256(raw, size)?;
+                val.abi_encode()
+            }
+        }
+        "uint" => {
+            let size = size.ok_or("size info lacked")? as usize;
+            let val = parse_u256_checked(raw, size)?;
+            val.abi_encode()
+        }
+        "address" => {
+            if raw.len() != 20 {
+                return Err("invalid address len".into());
+            }
+            let addr = Address::from_slice(raw);
+            addr.abi_encode()
+        }
+        "bytes" => {
+            if let Some(s) = size {
+                if s == 0 || s > 32 {
+                    return Err(format!("invalid fixed bytes size: {}", s));
+                }
+                if raw.len() != s as usize {
+                    return Err(format!(
+                        "invalid fixed bytes len: {}, expected bytes{}",
+                        raw.len(),
+                        s
+                    ));
+                }
+                // bytesN is right padded to a 32 bytes word
+                let mut buf = [0u8; 32];
+                buf[0..s as usize].copy_from_slice(raw);
+                let fixed_b = B256::from(buf);
+                fixed_b.abi_encode()
+            } else {
+                opts.check_dynamic_len(raw.len())?;
+                keccak256(raw).to_vec()
+            }
+        }
+        "string" => {
+            opts.check_dynamic_len(raw.len())?;
+            keccak256(raw).to_vec()
+        }
+        _ => return Err(format!("unknown primitive type: {}", name)),
+    };
+    Ok(res)
+}
+
+// nesting levels of a schema, each level buffers its own 32 bytes words
+fn schema_levels(schema: &TypeSchema) -> usize {
+    match schema {
+        TypeSchema::Primitive { .. } => 1,
+        TypeSchema::Array { item } => 1 + schema_levels(item),
+        TypeSchema::Struct { name: _, fields } => {
+            1 + fields
+                .iter()
+                .map(|f| schema_levels(&f.ty))
+                .max()
+                .unwrap_or(0)
+        }
+    }
+}
+
+// upper bound of the bytes encode_data allocates, computed from the data item sizes only.
+// at every nesting level each 32 bytes word (primitive, array element or struct hash) is built
+// from at least one distinct data item, so a level never holds more words than there are items
+pub fn estimate_encoding_size(
+    schema: &TypeSchema,
+    data_lengths: &[usize],
+) -> Result<usize, String> {
+    let overflow = || "encoding size estimate overflow".to_string();
+
+    let words_size = data_lengths
+        .len()
+        .checked_mul(32)
+        .and_then(|size| size.checked_mul(schema_levels(schema)))
+        .ok_or_else(overflow)?;
+    let raw_size = data_lengths
+        .iter()
+        .try_fold(0usize, |acc, len| acc.checked_add(*len))
+        .ok_or_else(overflow)?;
+
+    words_size.checked_add(raw_size).ok_or_else(overflow)
+}
+
+pub fn hash_struct(type_str: &str, encoded_data: &[u8]) -> B256 {
     let type_hash = keccak256(type_str.as_bytes());
     let mut hasher = Keccak256::new();
     hasher.update(type_hash);
@@ -215,11 +454,24 @@ pub fn eip712_signing_hash(
     domain: &Eip712Domain,
 ) -> Result<B256, String> {
     let struct_types = encode_all_struct_type(struct_defs)?;
-    let domain_separator = domain_separator_hash(&struct_types, domain)?;
-
     let schema = build_schema(struct_defs, primary_type)?;
+    let domain_separator = registered_domain_separator(struct_defs, &struct_types, domain)?;
+    signing_hash_with(&struct_types, &schema, primary_type, data, domain_separator)
+}
+
+fn signing_hash_with(
+    struct_types: &BTreeMap<String, String>,
+    schema: &TypeSchema,
+    primary_type: &String,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+    domain_separator: B256,
+) -> Result<B256, String> {
     let type_str = struct_types.get(primary_type).ok_or("type str not found")?;
-    let encoded_data = encode_data(&schema, &struct_types, data)?;
+    // on error the index of the last pulled data item is reported, ie: the item that failed to
+    // decode, or the last one before the stream ran out
+    let mut items = CountingItems::new(data);
+    let encoded_data = encode_data(schema, struct_types, &mut items)
+        .map_err(|err| format!("item {}: {}", items.consumed().saturating_sub(1), err))?;
     let struct_hash = hash_struct(type_str, &encoded_data);
 
     let mut buf = [0u8; 66];
@@ -231,6 +483,108 @@ pub fn eip712_signing_hash(
     Ok(keccak256(buf))
 }
 
+// the domain fields, a missing field is distinct from an empty one
+type DomainKey = (
+    Option<String>,
+    Option<String>,
+    Option<U256>,
+    Option<Address>,
+    Option<B256>,
+);
+
+fn domain_key(domain: &Eip712Domain) -> DomainKey {
+    (
+        domain.name.as_ref().map(|name| name.to_string()),
+        domain.version.as_ref().map(|version| version.to_string()),
+        domain.chain_id,
+        domain.verifying_contract,
+        domain.salt,
+    )
+}
+
+/// Type strings and schemas of a type set computed once, to hash several messages sharing the
+/// same types, eg: in a batch signing flow. The domain is given per message and its separator
+/// is cached by the domain fields
+pub struct PreparedTypes {
+    struct_defs: Eip712StructDefinitions,
+    struct_types: BTreeMap<String, String>,
+    schemas: BTreeMap<String, TypeSchema>,
+    separators: RefCell<BTreeMap<DomainKey, B256>>,
+}
+
+impl PreparedTypes {
+    pub fn new(struct_defs: &Eip712StructDefinitions) -> Result<Self, String> {
+        let struct_types = encode_all_struct_type(struct_defs)?;
+        let mut schemas = BTreeMap::new();
+        for type_name in struct_defs.keys() {
+            schemas.insert(type_name.clone(), build_schema(struct_defs, type_name)?);
+        }
+        Ok(PreparedTypes {
+            struct_defs: struct_defs.clone(),
+            struct_types,
+            schemas,
+            separators: RefCell::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn struct_types(&self) -> &BTreeMap<String, String> {
+        &self.struct_types
+    }
+
+    /// Number of distinct domains whose separator is cached
+    pub fn cached_domains(&self) -> usize {
+        self.separators.borrow().len()
+    }
+
+    /// The separator of `domain` as registered in the type set, computed once per domain
+    pub fn domain_separator(&self, domain: &Eip712Domain) -> Result<B256, String> {
+        let key = domain_key(domain);
+        if let Some(separator) = self.separators.borrow().get(&key) {
+            return Ok(*separator);
+        }
+        let separator = registered_domain_separator(&self.struct_defs, &self.struct_types, domain)?;
+        self.separators.borrow_mut().insert(key, separator);
+        Ok(separator)
+    }
+
+    /// Same as `eip712_signing_hash` without recomputing the type strings, schema and domain
+    /// separator
+    pub fn hash(
+        &self,
+        primary_type: &String,
+        data: &mut impl Iterator<Item = Vec<u8>>,
+        domain: &Eip712Domain,
+    ) -> Result<B256, String> {
+        let schema = self
+            .schemas
+            .get(primary_type)
+            .ok_or("build_schema not found")?;
+        signing_hash_with(
+            &self.struct_types,
+            schema,
+            primary_type,
+            data,
+            self.domain_separator(domain)?,
+        )
+    }
+}
+
+// compute the domain separator from the registered domain struct and its encoded values, without
+// hashing a message, `domain_type_name` is usually EIP712Domain or CIP23Domain
+pub fn compute_domain_separator(
+    struct_defs: &Eip712StructDefinitions,
+    domain_values: &mut impl Iterator<Item = Vec<u8>>,
+    domain_type_name: &String,
+) -> Result<B256, String> {
+    let struct_types = encode_all_struct_type(struct_defs)?;
+    let schema = build_schema(struct_defs, domain_type_name)?;
+    let type_str = struct_types
+        .get(domain_type_name)
+        .ok_or("domain type str not found")?;
+    let encoded_data = encode_data(&schema, &struct_types, domain_values)?;
+    Ok(hash_struct(type_str, &encoded_data))
+}
+
 // compute domain separator hash according to CIP-23 if possible
 pub fn domain_separator_hash(
     struct_types: &BTreeMap<String, String>,
@@ -244,10 +598,126 @@ pub fn domain_separator_hash(
     }
 }
 
+// compute the domain separator with the domain fields as registered in `struct_defs`, any subset
+// and order of the standard fields is allowed, CIP23Domain is used first if registered.
+// Without a registered domain struct alloy's separator is used
+pub fn registered_domain_separator(
+    struct_defs: &Eip712StructDefinitions,
+    struct_types: &BTreeMap<String, String>,
+    domain: &Eip712Domain,
+) -> Result<B256, String> {
+    let domain_type_name = if struct_defs.contains_key(CIP23_DOMAIN_TYPE_NAME) {
+        CIP23_DOMAIN_TYPE_NAME
+    } else if struct_defs.contains_key(EIP712_DOMAIN_TYPE_NAME) {
+        EIP712_DOMAIN_TYPE_NAME
+    } else {
+        return Ok(domain.separator());
+    };
+    let field_defs = &struct_defs[domain_type_name];
+    let type_str = struct_types
+        .get(domain_type_name)
+        .ok_or("domain type str not found")?;
+
+    let missing = |name: &str| format!("domain field not set: {}", name);
+    let mut encoded_data = Vec::with_capacity(field_defs.len() * 32);
+    for def in field_defs {
+        let word = match def.name.as_str() {
+            "name" => keccak256(
+                domain
+                    .name
+                    .as_ref()
+                    .ok_or_else(|| missing("name"))?
+                    .as_bytes(),
+            ),
+            "version" => keccak256(
+                domain
+                    .version
+                    .as_ref()
+                    .ok_or_else(|| missing("version"))?
+                    .as_bytes(),
+            ),
+            "chainId" => B256::from(domain.chain_id.ok_or_else(|| missing("chainId"))?),
+            "verifyingContract" => domain
+                .verifying_contract
+                .ok_or_else(|| missing("verifyingContract"))?
+                .into_word(),
+            "salt" => domain.salt.ok_or_else(|| missing("salt"))?,
+            _ => return Err(format!("invalid domain field: {}", def.name)),
+        };
+        encoded_data.extend_from_slice(word.as_slice());
+    }
+    Ok(hash_struct(type_str, &encoded_data))
+}
+
+// compute the signing hash of a json message with alloy's encoder, for callers that already
+// have a Resolver
+#[cfg(feature = "std")]
+pub fn hash_with_resolver(
+    resolver: Resolver,
+    domain: Eip712Domain,
+    primary_type: &str,
+    message: serde_json::Value,
+) -> Result<B256, String> {
+    let typed_data = TypedData {
+        domain,
+        resolver,
+        primary_type: primary_type.to_string(),
+        message,
+    };
+    typed_data
+        .eip712_signing_hash()
+        .map_err(|err| format!("alloy signing hash failed: {}", err))
+}
+
+/// Rebuild the message as an alloy `TypedData`, the bridge to alloy's own signing and
+/// verification
+#[cfg(feature = "std")]
+pub fn to_typed_data(
+    struct_defs: &Eip712StructDefinitions,
+    data: &[Vec<u8>],
+    primary_type: &String,
+    domain: &Eip712Domain,
+) -> Result<TypedData, String> {
+    let schema = build_schema(struct_defs, primary_type)?;
+    let message = build_value(&schema, &mut data.iter().cloned())?;
+    let resolver = build_resolver_from_struct_defs(struct_defs)?;
+    Ok(TypedData {
+        domain: domain.clone(),
+        resolver,
+        primary_type: primary_type.clone(),
+        message,
+    })
+}
+
+// compute the signing hash with our encoder and with alloy's TypedData, error if they differ
+#[cfg(feature = "std")]
+pub fn verify_against_alloy(
+    struct_defs: &Eip712StructDefinitions,
+    data: &[Vec<u8>],
+    primary_type: &String,
+    domain: &Eip712Domain,
+) -> Result<B256, String> {
+    let hash = eip712_signing_hash(struct_defs, &mut data.iter().cloned(), primary_type, domain)?;
+
+    let typed_data = to_typed_data(struct_defs, data, primary_type, domain)?;
+    let alloy_hash = typed_data
+        .eip712_signing_hash()
+        .map_err(|err| format!("alloy signing hash failed: {}", err))?;
+
+    if hash != alloy_hash {
+        return Err(format!(
+            "signing hash mismatch, ours: {}, alloy: {}",
+            hash, alloy_hash
+        ));
+    }
+    Ok(hash)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        parser::{build_value, build_value_with_options, value_to_data_items},
         test_utils::*,
         types::{Eip712FieldDefinition, build_struct_defs_from_resolver},
     };
@@ -406,6 +876,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_encode_type_ordering_matches_alloy() {
+        let json = r#"{
+            "types": {
+                "EIP712Domain": [{ "name": "name", "type": "string" }],
+                "Z": [
+                    { "name": "m", "type": "M" },
+                    { "name": "a", "type": "A[]" },
+                    { "name": "zz", "type": "Zz" }
+                ],
+                "Zz": [{ "name": "b", "type": "B" }],
+                "M": [
+                    { "name": "a", "type": "A" },
+                    { "name": "value", "type": "uint8" }
+                ],
+                "A": [
+                    { "name": "b", "type": "B[2]" },
+                    { "name": "ab", "type": "AB" }
+                ],
+                "AB": [{ "name": "b", "type": "B" }],
+                "B": [{ "name": "name", "type": "string" }],
+                "Node": [
+                    { "name": "value", "type": "uint256" },
+                    { "name": "children", "type": "Node[]" },
+                    { "name": "m", "type": "M" }
+                ]
+            },
+            "primaryType": "Z",
+            "domain": { "name": "Order" },
+            "message": {}
+        }"#;
+        let typed_data: TypedData = serde_json::from_str(json).unwrap();
+        let struct_defs =
+            build_struct_defs_from_resolver(&typed_data.resolver).expect("build struct defs");
+        let struct_types = encode_types_without_sub_type(&struct_defs).expect("success");
+
+        for primary_type in ["Z", "Zz", "M", "A", "AB", "B", "Node"] {
+            let mut typed = typed_data.clone();
+            typed.primary_type = primary_type.to_string();
+
+            let type_str =
+                encode_type(&struct_types, &struct_defs, &primary_type.to_string()).unwrap();
+            assert_eq!(type_str, typed.encode_type().unwrap());
+        }
+
+        assert_eq!(
+            encode_type(&struct_types, &struct_defs, &"Z".to_string()).unwrap(),
+            "Z(M m,A[] a,Zz zz)A(B[2] b,AB ab)AB(B b)B(string name)M(A a,uint8 value)Zz(B b)"
+        );
+        assert_eq!(
+            encode_type(&struct_types, &struct_defs, &"Node".to_string()).unwrap(),
+            "Node(uint256 value,Node[] children,M m)A(B[2] b,AB ab)AB(B b)B(string name)M(A a,uint8 value)"
+        );
+    }
+
     #[test]
     fn test_encode_data_basic() {
         let typed_data = get_raw_mail_typed_data().unwrap();
@@ -430,10 +955,8 @@ mod tests {
         let encoded_data = encode_data(&schema, &struct_type_map, &mut mail_data.into_iter());
         assert!(encoded_data.is_ok());
 
-        assert_eq!(
-            hex::encode(encoded_data.unwrap()),
-            hex::encode(typed_data.encode_data().unwrap())
-        );
+        let expected = typed_data.encode_data().unwrap();
+        assert_eq!(compare_encoded(&encoded_data.unwrap(), &expected), None);
 
         // check eip712 hash is match
         let mail_data = prepare_mail_data();
@@ -449,6 +972,233 @@ mod tests {
         assert_eq!(maybe_hash.unwrap(), typed_data_hash);
     }
 
+    #[cfg(feature = "trace")]
+    #[test]
+    fn test_encode_data_traced() {
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let struct_defs = prepare_mail_struct_defs();
+        let mail_data = prepare_mail_data();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).unwrap();
+
+        let (encoded, words) = encode_data_traced(
+            &schema,
+            &struct_type_map,
+            &mut mail_data.clone().into_iter(),
+        )
+        .unwrap();
+        assert_eq!(encoded, typed_data.encode_data().unwrap());
+
+        let paths: Vec<_> = words.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "from.name",
+                "from.wallets[0]",
+                "from.wallets[1]",
+                "from.wallets",
+                "from",
+                "to.name",
+                "to.wallets[0]",
+                "to.wallets[1]",
+                "to.wallets[2]",
+                "to.wallets",
+                "to",
+                "contents",
+                "timestamp",
+                "amount",
+                "payback",
+            ]
+        );
+        assert!(words.iter().all(|(_, word)| word.len() == 32));
+
+        // the top level words are the encoding itself
+        let top: Vec<u8> = words
+            .iter()
+            .filter(|(path, _)| !path.contains('.') && !path.contains('['))
+            .flat_map(|(_, word)| word.clone())
+            .collect();
+        assert_eq!(top, encoded);
+
+        let word = |path: &str| &words.iter().find(|(p, _)| p == path).unwrap().1;
+        assert_eq!(word("contents"), &keccak256(&mail_data[9]).to_vec());
+        assert_eq!(&word("to.wallets[1]")[12..], mail_data[7].as_slice());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_hash_with_resolver() {
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let struct_defs = prepare_mail_struct_defs();
+        let resolver = build_resolver_from_struct_defs(&struct_defs).unwrap();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let message = build_value(&schema, &mut prepare_mail_data().into_iter()).unwrap();
+
+        let hash =
+            hash_with_resolver(resolver, typed_data.domain().clone(), "Mail", message).unwrap();
+        let native_hash = eip712_signing_hash(
+            &struct_defs,
+            &mut prepare_mail_data().into_iter(),
+            &"Mail".to_string(),
+            typed_data.domain(),
+        )
+        .unwrap();
+        assert_eq!(hash, native_hash);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_compute_domain_separator() {
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let struct_defs = prepare_mail_struct_defs();
+        let domain_type = "EIP712Domain".to_string();
+        let schema = build_schema(&struct_defs, &domain_type).unwrap();
+        let domain_json = serde_json::to_value(typed_data.domain()).unwrap();
+        let domain_values = value_to_data_items(&schema, &domain_json).unwrap();
+
+        let separator =
+            compute_domain_separator(&struct_defs, &mut domain_values.into_iter(), &domain_type)
+                .unwrap();
+        assert_eq!(separator, typed_data.domain().separator());
+
+        let err = compute_domain_separator(
+            &struct_defs,
+            &mut core::iter::empty(),
+            &"Missing".to_string(),
+        )
+        .unwrap_err();
+        assert!(!err.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_verify_against_alloy() {
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let struct_defs = prepare_mail_struct_defs();
+        let mail_data = prepare_mail_data();
+
+        let hash = verify_against_alloy(
+            &struct_defs,
+            &mail_data,
+            &"Mail".to_string(),
+            typed_data.domain(),
+        );
+        assert_eq!(hash.unwrap(), typed_data.eip712_signing_hash().unwrap());
+
+        // truncated data fails before comparing
+        let hash = verify_against_alloy(
+            &struct_defs,
+            &mail_data[..5],
+            &"Mail".to_string(),
+            typed_data.domain(),
+        );
+        assert!(hash.is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_encode_data_leading_zero_ints() {
+        let json = r#"{
+            "types": {
+                "EIP712Domain": [{ "name": "name", "type": "string" }],
+                "Test": [
+                    { "name": "amount", "type": "uint32" },
+                    { "name": "delta", "type": "int32" },
+                    { "name": "big", "type": "uint256" }
+                ]
+            },
+            "primaryType": "Test",
+            "domain": { "name": "Zeros" },
+            "message": { "amount": 1000000, "delta": 1000000, "big": 1000000 }
+        }"#;
+        let typed_data: TypedData = serde_json::from_str(json).unwrap();
+        let struct_defs =
+            build_struct_defs_from_resolver(&typed_data.resolver).expect("build struct defs");
+        let primary_type = "Test".to_string();
+        let schema = build_schema(&struct_defs, &primary_type).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).expect("success");
+
+        let minimal = hex::decode("0f4240").unwrap();
+        let padded = hex::decode("000f4240").unwrap();
+        let full = hex::decode("00000000000000000000000000000000000000000000000000000000000f4240")
+            .unwrap();
+        let forms = [
+            vec![minimal.clone(), minimal.clone(), minimal.clone()],
+            vec![padded.clone(), padded.clone(), padded],
+            vec![minimal.clone(), minimal, full],
+        ];
+
+        for raw_data in forms {
+            let encoded_data =
+                encode_data(&schema, &struct_type_map, &mut raw_data.clone().into_iter()).unwrap();
+            assert_eq!(
+                hex::encode(encoded_data),
+                hex::encode(typed_data.encode_data().unwrap())
+            );
+
+            let message = build_value(&schema, &mut raw_data.clone().into_iter()).unwrap();
+            assert_eq!(message["amount"], 1000000);
+            assert_eq!(message["delta"], 1000000);
+
+            let hash =
+                verify_against_alloy(&struct_defs, &raw_data, &primary_type, typed_data.domain());
+            assert_eq!(hash.unwrap(), typed_data.eip712_signing_hash().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_encode_data_counting_items() {
+        let struct_defs = prepare_mail_struct_defs();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).expect("success");
+
+        let mut data = prepare_mail_data();
+        data.push(hex::decode("ff").unwrap());
+        let mut items = CountingItems::new(data.into_iter());
+        encode_data(&schema, &struct_type_map, &mut items).unwrap();
+        assert_eq!(items.consumed(), 13);
+        // the trailing item is left untouched
+        assert_eq!(items.into_inner().count(), 1);
+    }
+
+    #[test]
+    fn test_hash_struct_slices() {
+        let type_str = "Person(string name,address[] wallets)";
+        let encoded_data = [0x11u8; 64];
+
+        let mut buf = keccak256(type_str.as_bytes()).to_vec();
+        buf.extend_from_slice(&encoded_data);
+        let expected = keccak256(buf);
+
+        assert_eq!(hash_struct(type_str, &encoded_data), expected);
+        assert_eq!(hash_struct(type_str, &encoded_data[..]), expected);
+        // owned values still coerce
+        let owned_type_str = type_str.to_string();
+        let owned_data = encoded_data.to_vec();
+        assert_eq!(hash_struct(&owned_type_str, &owned_data), expected);
+    }
+
+    #[test]
+    fn test_estimate_encoding_size() {
+        let struct_defs = prepare_mail_struct_defs();
+        let mail_data = prepare_mail_data();
+        let primary_name = "Mail".to_string();
+
+        let schema = build_schema(&struct_defs, &primary_name).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).expect("success");
+
+        let data_lengths: Vec<usize> = mail_data.iter().map(|item| item.len()).collect();
+        let estimate = estimate_encoding_size(&schema, &data_lengths).unwrap();
+
+        let encoded_data =
+            encode_data(&schema, &struct_type_map, &mut mail_data.into_iter()).unwrap();
+        assert!(estimate >= encoded_data.len());
+        assert_eq!(estimate, 13 * 32 * 4 + data_lengths.iter().sum::<usize>());
+
+        assert_eq!(estimate_encoding_size(&schema, &[]).unwrap(), 0);
+        assert!(estimate_encoding_size(&schema, &[usize::MAX]).is_err());
+    }
+
     #[test]
     fn test_encode_data_sign() {
         let json = include_str!("../res/05-signed_ints-data.json");
@@ -480,10 +1230,1006 @@ mod tests {
         // check encode_data is correct
         let encoded_data = encode_data(&schema, &struct_type_map, &mut raw_data.into_iter());
         assert!(encoded_data.is_ok());
+        let expected = typed_data.encode_data().unwrap();
+        assert_eq!(compare_encoded(&encoded_data.unwrap(), &expected), None);
+    }
+
+    #[test]
+    fn test_encode_data_wide_signed_ints() {
+        use alloy_primitives::I256;
+
+        let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+            "domain": { "name": "Wide Ints", "chainId": 1 },
+            "message": {
+                "neg136": "-1",
+                "neg192": "-3138550867693340381917894711603833208051177722232017256448",
+                "neg256": "-123456789012345678901234567890",
+                "min256": I256::MIN.to_string(),
+                "short256": "127",
+            },
+            "primaryType": "Test",
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "chainId", "type": "uint256" }
+                ],
+                "Test": [
+                    { "name": "neg136", "type": "int136" },
+                    { "name": "neg192", "type": "int192" },
+                    { "name": "neg256", "type": "int256" },
+                    { "name": "min256", "type": "int256" },
+                    { "name": "short256", "type": "int256" }
+                ]
+            }
+        }))
+        .unwrap();
+        let struct_defs = build_struct_defs_from_resolver(&typed_data.resolver).unwrap();
+
+        // two's complement at the declared width
+        let int_bytes = |val: &str, size: usize| {
+            let word = I256::from_dec_str(val).unwrap().to_be_bytes::<32>();
+            word[32 - size..].to_vec()
+        };
+        let raw_data = vec![
+            int_bytes("-1", 17),
+            int_bytes(
+                "-3138550867693340381917894711603833208051177722232017256448",
+                24,
+            ),
+            int_bytes("-123456789012345678901234567890", 32),
+            I256::MIN.to_be_bytes::<32>().to_vec(),
+            // a short value of a 32 bytes field is zero padded, ie: positive
+            hex::decode("7f").unwrap(),
+        ];
+
+        let schema = build_schema(&struct_defs, &"Test".to_string()).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).unwrap();
+        let encoded_data =
+            encode_data(&schema, &struct_type_map, &mut raw_data.into_iter()).unwrap();
+        assert_eq!(
+            hex::encode(&encoded_data),
+            hex::encode(typed_data.encode_data().unwrap())
+        );
+        assert_eq!(&encoded_data[..32], [0xff; 32].as_slice());
+    }
+
+    #[test]
+    fn test_eip712_signing_hash_error_item_index() {
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let struct_defs = prepare_mail_struct_defs();
+
+        // the second wallet of `from` is one byte short
+        let mut mail_data = prepare_mail_data();
+        mail_data[3].pop();
+        let err = eip712_signing_hash(
+            &struct_defs,
+            &mut mail_data.into_iter(),
+            &"Mail".to_string(),
+            typed_data.domain(),
+        )
+        .unwrap_err();
+        assert_eq!(err, "item 3: from.wallets[1]: invalid address len");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_minimal_domain() {
+        let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+            "domain": {
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": { "value": "42" },
+            "primaryType": "Test",
+            "types": {
+                "EIP712Domain": [
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" }
+                ],
+                "Test": [{ "name": "value", "type": "uint256" }]
+            }
+        }))
+        .unwrap();
+        let mut struct_defs = build_struct_defs_from_resolver(&typed_data.resolver).unwrap();
+        let struct_types = encode_all_struct_type(&struct_defs).unwrap();
+        assert_eq!(
+            struct_types["EIP712Domain"],
+            "EIP712Domain(uint256 chainId,address verifyingContract)"
+        );
+
+        let domain = typed_data.domain();
+        let separator = registered_domain_separator(&struct_defs, &struct_types, domain).unwrap();
+        assert_eq!(separator, domain.separator());
+        let hash = eip712_signing_hash(
+            &struct_defs,
+            &mut vec![vec![42u8]].into_iter(),
+            &"Test".to_string(),
+            domain,
+        )
+        .unwrap();
+        assert_eq!(hash, typed_data.eip712_signing_hash().unwrap());
+
+        // the registered order is kept
+        struct_defs.get_mut("EIP712Domain").unwrap().reverse();
+        let struct_types = encode_all_struct_type(&struct_defs).unwrap();
+        let separator = registered_domain_separator(&struct_defs, &struct_types, domain).unwrap();
+        let domain_values = vec![domain.verifying_contract.unwrap().to_vec(), vec![1u8]];
+        let expected = compute_domain_separator(
+            &struct_defs,
+            &mut domain_values.into_iter(),
+            &"EIP712Domain".to_string(),
+        )
+        .unwrap();
+        assert_eq!(separator, expected);
+        assert_ne!(separator, domain.separator());
+
+        // a registered field without a value
+        struct_defs
+            .get_mut("EIP712Domain")
+            .unwrap()
+            .extend(get_domain_struct_def().into_iter().take(1));
+        let struct_types = encode_all_struct_type(&struct_defs).unwrap();
+        let err = registered_domain_separator(&struct_defs, &struct_types, domain).unwrap_err();
+        assert_eq!(err, "domain field not set: name");
+    }
+
+    #[test]
+    fn test_encode_data_borrowed_schema() {
+        use crate::parser::build_schema_ref;
+
+        // one owned String per struct, field and primitive node
+        fn owned_names(schema: &TypeSchema) -> usize {
+            match schema {
+                TypeSchema::Primitive { .. } => 1,
+                TypeSchema::Array { item } => owned_names(item),
+                TypeSchema::Struct { fields, .. } => {
+                    1 + fields.iter().map(|f| 1 + owned_names(&f.ty)).sum::<usize>()
+                }
+            }
+        }
+
+        let struct_defs = prepare_mail_struct_defs();
+        let struct_type_map = encode_all_struct_type(&struct_defs).unwrap();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let schema_ref = build_schema_ref(&struct_defs, "Mail").unwrap();
+        assert_eq!(owned_names(&schema), 21);
+
+        let encoded = encode_data(
+            &schema,
+            &struct_type_map,
+            &mut prepare_mail_data().into_iter(),
+        )
+        .unwrap();
+        let encoded_ref = encode_data(
+            &schema_ref,
+            &struct_type_map,
+            &mut prepare_mail_data().into_iter(),
+        )
+        .unwrap();
+        assert_eq!(encoded, encoded_ref);
+
+        // errors keep their path
+        let mut mail_data = prepare_mail_data();
+        mail_data[3].pop();
+        let err =
+            encode_data(&schema_ref, &struct_type_map, &mut mail_data.into_iter()).unwrap_err();
+        assert_eq!(err, "from.wallets[1]: invalid address len");
+        assert!(build_schema_ref(&struct_defs, "Missing").is_err());
+    }
+
+    #[test]
+    fn test_encode_data_fixed_bytes_arrays() {
+        let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+            "domain": { "name": "Fixed Bytes Arrays", "chainId": 1 },
+            "message": {
+                "hashes": [
+                    "0x1111111111111111111111111111111111111111111111111111111111111111",
+                    "0x00000000000000000000000000000000000000000000000000000000000000ff"
+                ],
+                "selectors": ["0xa9059cbb", "0x095ea7b3", "0x00000001"],
+                "empty": []
+            },
+            "primaryType": "Test",
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "chainId", "type": "uint256" }
+                ],
+                "Test": [
+                    { "name": "hashes", "type": "bytes32[]" },
+                    { "name": "selectors", "type": "bytes4[]" },
+                    { "name": "empty", "type": "bytes32[]" }
+                ]
+            }
+        }))
+        .unwrap();
+        let struct_defs = build_struct_defs_from_resolver(&typed_data.resolver).unwrap();
+
+        let raw_data = vec![
+            vec![2],
+            vec![0x11; 32],
+            hex::decode("00000000000000000000000000000000000000000000000000000000000000ff")
+                .unwrap(),
+            vec![3],
+            hex::decode("a9059cbb").unwrap(),
+            hex::decode("095ea7b3").unwrap(),
+            hex::decode("00000001").unwrap(),
+            vec![0],
+        ];
+
+        let schema = build_schema(&struct_defs, &"Test".to_string()).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).unwrap();
+        let encoded_data =
+            encode_data(&schema, &struct_type_map, &mut raw_data.into_iter()).unwrap();
+        assert_eq!(
+            hex::encode(&encoded_data),
+            hex::encode(typed_data.encode_data().unwrap())
+        );
+
+        // each bytes4 item is right padded to a full word before hashing
+        let mut words = vec![];
+        for selector in ["a9059cbb", "095ea7b3", "00000001"] {
+            let mut word = [0u8; 32];
+            word[..4].copy_from_slice(&hex::decode(selector).unwrap());
+            words.extend_from_slice(&word);
+        }
+        assert_eq!(&encoded_data[32..64], keccak256(&words).as_slice());
+        assert_eq!(&encoded_data[64..], keccak256([]).as_slice());
+    }
+
+    #[test]
+    fn test_encode_data_empty_primitives() {
+        let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+            "domain": { "name": "Empty Values", "chainId": 1 },
+            "message": { "text": "", "blob": "0x" },
+            "primaryType": "Test",
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "chainId", "type": "uint256" }
+                ],
+                "Test": [
+                    { "name": "text", "type": "string" },
+                    { "name": "blob", "type": "bytes" }
+                ]
+            }
+        }))
+        .unwrap();
+        let struct_defs = build_struct_defs_from_resolver(&typed_data.resolver).unwrap();
+        let schema = build_schema(&struct_defs, &"Test".to_string()).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).unwrap();
+
+        let raw_data: Vec<Vec<u8>> = vec![vec![], vec![]];
+        let encoded_data =
+            encode_data(&schema, &struct_type_map, &mut raw_data.into_iter()).unwrap();
+        assert_eq!(encoded_data, typed_data.encode_data().unwrap());
+        assert_eq!(&encoded_data[..32], keccak256([]).as_slice());
+        assert_eq!(&encoded_data[32..], keccak256([]).as_slice());
+
+        // an empty address or bool is an error, not a panic
+        let empty = || vec![Vec::<u8>::new()].into_iter();
+        let address = TypeSchema::Primitive {
+            name: "address".to_string(),
+            size: None,
+        };
+        assert_eq!(
+            encode_data(&address, &struct_type_map, &mut empty()).unwrap_err(),
+            "invalid address len"
+        );
+        let bool_schema = TypeSchema::Primitive {
+            name: "bool".to_string(),
+            size: None,
+        };
+        assert_eq!(
+            encode_data(&bool_schema, &struct_type_map, &mut empty()).unwrap_err(),
+            "invalid bool len"
+        );
+    }
+
+    #[test]
+    fn test_encode_data_optional_field() {
+        let struct_defs = prepare_mail_struct_defs();
+        let struct_type_map = encode_all_struct_type(&struct_defs).unwrap();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let expected = encode_data(
+            &schema,
+            &struct_type_map,
+            &mut prepare_mail_data().into_iter(),
+        )
+        .unwrap();
+        let mut empty_contents = prepare_mail_data();
+        empty_contents[9].clear();
+        let expected_absent =
+            encode_data(&schema, &struct_type_map, &mut empty_contents.into_iter()).unwrap();
+
+        let mut opts = EncodeOptions::default();
+        opts.optional.insert("contents");
+        let encode = |mail_data: Vec<Vec<u8>>| {
+            encode_data_with_options(&schema, &struct_type_map, &mut mail_data.into_iter(), &opts)
+        };
+
+        // present, the marker precedes the value
+        let mut mail_data = prepare_mail_data();
+        mail_data.insert(9, vec![1]);
+        assert_eq!(encode(mail_data).unwrap(), expected);
+
+        // absent, encoded as an empty string
+        let mut mail_data = prepare_mail_data();
+        mail_data[9] = vec![0];
+        assert_eq!(encode(mail_data).unwrap(), expected_absent);
+
+        let mut mail_data = prepare_mail_data();
+        mail_data[9] = vec![2];
+        assert_eq!(
+            encode(mail_data).unwrap_err(),
+            "contents: invalid presence marker"
+        );
+    }
+
+    #[test]
+    fn test_encode_data_error_path() {
+        let struct_defs = prepare_mail_struct_defs();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).expect("success");
+
+        // the second wallet of `from` is one byte short
+        let mut mail_data = prepare_mail_data();
+        mail_data[3].pop();
+        let err = encode_data(
+            &schema,
+            &struct_type_map,
+            &mut mail_data.clone().into_iter(),
+        )
+        .unwrap_err();
+        assert_eq!(err, "from.wallets[1]: invalid address len");
+        let err = build_value(&schema, &mut mail_data.into_iter()).unwrap_err();
+        assert_eq!(err, "from.wallets[1]: invalid address len");
+
+        // data exhausted in the middle of `to`
+        let mut mail_data = prepare_mail_data();
+        mail_data.truncate(7);
+        let err = encode_data(&schema, &struct_type_map, &mut mail_data.into_iter()).unwrap_err();
+        assert_eq!(err, "to.wallets[1]: build value data.next failed");
+    }
+
+    #[test]
+    fn test_type_hash() {
+        let struct_defs = prepare_mail_struct_defs();
+        let hash = type_hash(&struct_defs, &"Mail".to_string()).unwrap();
+        assert_eq!(
+            hash,
+            keccak256(
+                "Mail(Person from,Person to,string contents,uint64 timestamp,uint256 amount,uint256 payback)Person(string name,address[] wallets)"
+            )
+        );
+        assert!(type_hash(&struct_defs, &"Missing".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_compare_encoded() {
+        let ours = [[0u8; 32], [1u8; 32], [2u8; 32]].concat();
+        assert_eq!(compare_encoded(&ours, &ours), None);
+
+        let mut theirs = ours.clone();
+        theirs[40] = 0xff;
+        assert_eq!(compare_encoded(&ours, &theirs), Some(32));
+        assert_eq!(compare_encoded(&ours, &ours[..64]), Some(64));
+        assert_eq!(compare_encoded(&[], &ours), Some(0));
+    }
+
+    #[test]
+    fn test_direct_custom_types() {
+        let struct_defs = prepare_mail_struct_defs();
+        let mail = "Mail".to_string();
+        assert_eq!(
+            direct_custom_types(&struct_defs, &mail).unwrap(),
+            vec!["Person"]
+        );
+        assert!(
+            direct_custom_types(&struct_defs, &"Person".to_string())
+                .unwrap()
+                .is_empty()
+        );
+        assert!(direct_custom_types(&struct_defs, &"Missing".to_string()).is_err());
+
+        // Mail -> Person -> Wallet -> Asset
+        let mut struct_defs = prepare_mail_struct_defs();
+        let custom = |type_name: &str, name: &str| {
+            Eip712FieldDefinition::new(
+                Eip712FieldType::Custom(type_name.to_string()),
+                name.to_string(),
+            )
+        };
+        struct_defs
+            .get_mut("Person")
+            .unwrap()
+            .push(custom("Wallet", "main"));
+        struct_defs.insert("Wallet".to_string(), vec![custom("Asset", "asset")]);
+        struct_defs.insert(
+            "Asset".to_string(),
+            vec![Eip712FieldDefinition::new(
+                Eip712FieldType::Address,
+                "token".to_string(),
+            )],
+        );
+        assert_eq!(
+            direct_custom_types(&struct_defs, &mail).unwrap(),
+            vec!["Person"]
+        );
+        assert_eq!(
+            direct_custom_types(&struct_defs, &"Person".to_string()).unwrap(),
+            vec!["Wallet"]
+        );
+        assert_eq!(
+            find_sub_custom_types(&struct_defs, &mail).unwrap(),
+            vec!["Asset", "Person", "Wallet"]
+        );
+    }
+
+    #[test]
+    fn test_struct_types_insertion_order() {
+        let types_json = [
+            (
+                "Mail",
+                r#"[{ "name": "from", "type": "Person" }, { "name": "to", "type": "Person[]" }, { "name": "attachment", "type": "Asset" }]"#,
+            ),
+            (
+                "Person",
+                r#"[{ "name": "name", "type": "string" }, { "name": "wallet", "type": "Wallet" }]"#,
+            ),
+            (
+                "Wallet",
+                r#"[{ "name": "addr", "type": "address" }, { "name": "asset", "type": "Asset" }]"#,
+            ),
+            ("Asset", r#"[{ "name": "id", "type": "uint256" }]"#),
+            ("EIP712Domain", r#"[{ "name": "name", "type": "string" }]"#),
+        ];
+        let struct_defs_in = |order: &[usize]| {
+            // the types are listed in the json in this order
+            let types: Vec<String> = order
+                .iter()
+                .map(|i| format!(r#""{}": {}"#, types_json[*i].0, types_json[*i].1))
+                .collect();
+            let json = format!(
+                r#"{{ "domain": {{ "name": "Order" }}, "message": {{}}, "primaryType": "Mail", "types": {{ {} }} }}"#,
+                types.join(", ")
+            );
+            let typed_data: TypedData = serde_json::from_str(&json).unwrap();
+            build_struct_defs_from_resolver(&typed_data.resolver).unwrap()
+        };
+
+        let reference = struct_defs_in(&[0, 1, 2, 3, 4]);
+        let reference_types = encode_all_struct_type(&reference).unwrap();
+        assert_eq!(
+            reference_types["Mail"],
+            "Mail(Person from,Person[] to,Asset attachment)Asset(uint256 id)\
+             Person(string name,Wallet wallet)Wallet(address addr,Asset asset)"
+        );
+        let reference_hash = type_hash(&reference, &"Mail".to_string()).unwrap();
+
+        for order in [[4, 3, 2, 1, 0], [2, 0, 4, 1, 3], [3, 1, 4, 0, 2]] {
+            let struct_defs = struct_defs_in(&order);
+            assert_eq!(
+                encode_all_struct_type(&struct_defs).unwrap(),
+                reference_types
+            );
+            assert_eq!(
+                type_hash(&struct_defs, &"Mail".to_string()).unwrap(),
+                reference_hash
+            );
+        }
+
+        // same for definitions inserted directly in another order
+        let mut reversed = Eip712StructDefinitions::new();
+        for (name, fields) in reference.iter().rev() {
+            reversed.insert(name.clone(), fields.clone());
+        }
+        assert_eq!(encode_all_struct_type(&reversed).unwrap(), reference_types);
+    }
+
+    #[test]
+    fn test_referenced_type_as_primary() {
+        // Person is referenced by Mail, as the primary type only its own dependencies count
+        let struct_defs = prepare_mail_struct_defs();
+        let person = "Person".to_string();
+        let struct_types = encode_types_without_sub_type(&struct_defs).unwrap();
+        let person_type = encode_type(&struct_types, &struct_defs, &person).unwrap();
+        assert_eq!(person_type, "Person(string name,address[] wallets)");
+        assert_eq!(
+            encode_all_struct_type(&struct_defs).unwrap()["Person"],
+            person_type
+        );
+
+        let mut typed_data = get_raw_mail_typed_data().unwrap();
+        typed_data.primary_type = person.clone();
+        typed_data.message = serde_json::json!({
+            "name": "Cow",
+            "wallets": [
+                "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826",
+                "0xDeaDbeefdEAdbeefdEadbEEFdeadbeEFdEaDbeeF"
+            ]
+        });
+        assert_eq!(typed_data.encode_type().unwrap(), person_type);
+        assert_eq!(
+            type_hash(&struct_defs, &person).unwrap(),
+            typed_data.type_hash().unwrap()
+        );
+
+        let person_data = prepare_mail_data()[..4].to_vec();
+        let hash = eip712_signing_hash(
+            &struct_defs,
+            &mut person_data.into_iter(),
+            &person,
+            typed_data.domain(),
+        )
+        .unwrap();
+        assert_eq!(hash, typed_data.eip712_signing_hash().unwrap());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_to_typed_data() {
+        let struct_defs = prepare_mail_struct_defs();
+        let typed = get_raw_mail_typed_data().unwrap();
+        let primary_type = "Mail".to_string();
+        let data = prepare_mail_data();
+
+        let typed_data = to_typed_data(&struct_defs, &data, &primary_type, typed.domain()).unwrap();
+        assert_eq!(typed_data.primary_type, primary_type);
+        assert_eq!(typed_data.message["from"]["name"], "Cow");
+
+        let hash = eip712_signing_hash(
+            &struct_defs,
+            &mut data.into_iter(),
+            &primary_type,
+            typed.domain(),
+        )
+        .unwrap();
+        assert_eq!(typed_data.eip712_signing_hash().unwrap(), hash);
+    }
+
+    #[test]
+    fn test_type_hash_streaming() {
+        let struct_defs = prepare_avatar_mail_struct_defs();
+        let struct_types = encode_types_without_sub_type(&struct_defs).unwrap();
+        for type_name in struct_defs.keys() {
+            let type_str = encode_type(&struct_types, &struct_defs, type_name).unwrap();
+            assert_eq!(
+                type_hash_streaming(&struct_types, &struct_defs, type_name).unwrap(),
+                keccak256(type_str.as_bytes())
+            );
+        }
+        assert!(type_hash_streaming(&struct_types, &struct_defs, &"Missing".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_prepared_types() {
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let domain = typed_data.domain();
+        let struct_defs = prepare_mail_struct_defs();
+        let prepared = PreparedTypes::new(&struct_defs).unwrap();
+        let mail = "Mail".to_string();
+
+        let mut other_mail = prepare_mail_data();
+        other_mail[9] = b"Hello, Alice!".to_vec();
+        for mail_data in [prepare_mail_data(), other_mail] {
+            let hash = prepared
+                .hash(&mail, &mut mail_data.clone().into_iter(), domain)
+                .unwrap();
+            let expected =
+                eip712_signing_hash(&struct_defs, &mut mail_data.into_iter(), &mail, domain)
+                    .unwrap();
+            assert_eq!(hash, expected);
+        }
+        assert_eq!(
+            prepared.struct_types(),
+            &encode_all_struct_type(&struct_defs).unwrap()
+        );
+        assert!(
+            prepared
+                .hash(&"Missing".to_string(), &mut core::iter::empty(), domain)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_prepared_types_domains() {
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let struct_defs = prepare_mail_struct_defs();
+        let prepared = PreparedTypes::new(&struct_defs).unwrap();
+        let mail = "Mail".to_string();
+
+        let mut other_chain = typed_data.domain().clone();
+        other_chain.chain_id = Some(U256::from(2));
+        let domains = [typed_data.domain().clone(), other_chain];
+
+        let mut hashes = vec![];
+        for _ in 0..2 {
+            for domain in &domains {
+                let hash = prepared
+                    .hash(&mail, &mut prepare_mail_data().into_iter(), domain)
+                    .unwrap();
+                let expected = eip712_signing_hash(
+                    &struct_defs,
+                    &mut prepare_mail_data().into_iter(),
+                    &mail,
+                    domain,
+                )
+                .unwrap();
+                assert_eq!(hash, expected);
+                hashes.push(hash);
+            }
+        }
+        assert_ne!(hashes[0], hashes[1]);
+        assert_eq!(hashes[..2], hashes[2..]);
+        assert_eq!(prepared.cached_domains(), 2);
+
+        // a missing name differs from an empty one
+        let mut unnamed = domains[0].clone();
+        unnamed.name = None;
+        let mut empty_name = domains[0].clone();
+        empty_name.name = Some("".into());
+        assert_ne!(domain_key(&unnamed), domain_key(&empty_name));
+    }
+
+    #[test]
+    fn test_schema_fingerprint() {
+        let mail = "Mail".to_string();
+        let fingerprint = schema_fingerprint(&prepare_mail_struct_defs(), &mail).unwrap();
         assert_eq!(
-            hex::encode(encoded_data.unwrap()),
+            fingerprint,
+            schema_fingerprint(&prepare_mail_struct_defs(), &mail).unwrap()
+        );
+        assert_eq!(
+            schema_fingerprint_hex(&prepare_mail_struct_defs(), &mail).unwrap(),
+            format!("0x{}", hex::encode(fingerprint))
+        );
+
+        // unrelated types do not change it
+        let mut struct_defs = prepare_mail_struct_defs();
+        struct_defs.insert("Other".to_string(), get_domain_struct_def());
+        assert_eq!(
+            schema_fingerprint(&struct_defs, &mail).unwrap(),
+            fingerprint
+        );
+
+        let mut struct_defs = prepare_mail_struct_defs();
+        struct_defs.get_mut("Person").unwrap().reverse();
+        assert_ne!(
+            schema_fingerprint(&struct_defs, &mail).unwrap(),
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn test_encode_malformed_primitives() {
+        let struct_type_map = BTreeMap::new();
+        let unknown = TypeSchema::Primitive {
+            name: "fixed".to_string(),
+            size: None,
+        };
+        assert_eq!(
+            encode_data(
+                &unknown,
+                &struct_type_map,
+                &mut vec![vec![0x01]].into_iter()
+            )
+            .unwrap_err(),
+            "unknown primitive type: fixed"
+        );
+        let int_without_size = TypeSchema::Primitive {
+            name: "int".to_string(),
+            size: None,
+        };
+        assert_eq!(
+            encode_data(
+                &int_without_size,
+                &struct_type_map,
+                &mut vec![vec![0x01]].into_iter()
+            )
+            .unwrap_err(),
+            "size info lacked"
+        );
+        // a hand built schema may carry an int size no field type accepts
+        for (size, err) in [
+            (0, "i128 size should be in 1..=16"),
+            (33, "int size should be in 1..=32"),
+        ] {
+            let int_schema = TypeSchema::Primitive {
+                name: "int".to_string(),
+                size: Some(size),
+            };
+            assert_eq!(
+                encode_data(
+                    &int_schema,
+                    &struct_type_map,
+                    &mut vec![vec![0x01]].into_iter()
+                )
+                .unwrap_err(),
+                err
+            );
+        }
+        let bool_schema = TypeSchema::Primitive {
+            name: "bool".to_string(),
+            size: None,
+        };
+        assert_eq!(
+            encode_data(
+                &bool_schema,
+                &struct_type_map,
+                &mut vec![vec![]].into_iter()
+            )
+            .unwrap_err(),
+            "invalid bool len"
+        );
+        assert_eq!(
+            encode_data(
+                &bool_schema,
+                &struct_type_map,
+                &mut vec![vec![2]].into_iter()
+            )
+            .unwrap_err(),
+            "invalid bool value"
+        );
+        assert_eq!(
+            encode_data(
+                &bool_schema,
+                &struct_type_map,
+                &mut vec![vec![0, 1]].into_iter()
+            )
+            .unwrap_err(),
+            "invalid bool len"
+        );
+    }
+
+    #[test]
+    fn test_encode_fixed_bytes_padding() {
+        let struct_type_map = BTreeMap::new();
+        let schema = |size| TypeSchema::Primitive {
+            name: "bytes".to_string(),
+            size: Some(size),
+        };
+
+        let encoded = encode_data(
+            &schema(1),
+            &struct_type_map,
+            &mut vec![vec![0xff]].into_iter(),
+        )
+        .unwrap();
+        let mut expected = [0u8; 32];
+        expected[0] = 0xff;
+        assert_eq!(encoded, expected);
+
+        let encoded = encode_data(
+            &schema(4),
+            &struct_type_map,
+            &mut vec![vec![0xde, 0xad, 0xbe, 0xef]].into_iter(),
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(encoded),
+            "deadbeef00000000000000000000000000000000000000000000000000000000"
+        );
+
+        let err = encode_data(
+            &schema(4),
+            &struct_type_map,
+            &mut vec![vec![0xde, 0xad, 0xbe]].into_iter(),
+        )
+        .unwrap_err();
+        assert_eq!(err, "invalid fixed bytes len: 3, expected bytes4");
+        let err = encode_data(
+            &schema(33),
+            &struct_type_map,
+            &mut vec![vec![0; 33]].into_iter(),
+        )
+        .unwrap_err();
+        assert_eq!(err, "invalid fixed bytes size: 33");
+    }
+
+    #[test]
+    fn test_encode_data_prehashed() {
+        let struct_defs = prepare_avatar_mail_struct_defs();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).expect("success");
+        let data = prepare_avatar_mail_data();
+        let full = encode_data(&schema, &struct_type_map, &mut data.clone().into_iter()).unwrap();
+
+        // `to.avatar` and `contents` sent as their hash
+        let mut opts = EncodeOptions::default();
+        opts.prehashed.insert("to.avatar");
+        opts.prehashed.insert("contents");
+        let avatar_index = 4;
+        let contents_index = 6;
+        let mut hashed_data = data.clone();
+        for i in [avatar_index, contents_index] {
+            hashed_data[i] = keccak256(&data[i]).to_vec();
+        }
+        let hashed = encode_data_with_options(
+            &schema,
+            &struct_type_map,
+            &mut hashed_data.clone().into_iter(),
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(hashed, full);
+
+        // the content can not be rebuilt from its hash
+        let err =
+            build_value_with_options(&schema, &mut hashed_data.into_iter(), &opts).unwrap_err();
+        assert_eq!(err, "to.avatar: pre-hashed value can not be rebuilt");
+
+        let mut opts = EncodeOptions::default();
+        opts.prehashed.insert("from.badge");
+        let err = encode_data_with_options(&schema, &struct_type_map, &mut data.into_iter(), &opts)
+            .unwrap_err();
+        assert_eq!(err, "from.badge: pre-hashed value not allowed");
+    }
+
+    #[test]
+    fn test_encode_data_max_dynamic_len() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        struct_defs.insert(
+            "Note".to_string(),
+            vec![
+                Eip712FieldDefinition {
+                    name: "text".to_string(),
+                    field_type: Eip712FieldType::String,
+                    array_levels: vec![],
+                },
+                Eip712FieldDefinition {
+                    name: "blob".to_string(),
+                    field_type: Eip712FieldType::DynamicBytes,
+                    array_levels: vec![],
+                },
+            ],
+        );
+        let schema = build_schema(&struct_defs, &"Note".to_string()).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).expect("success");
+        let opts = EncodeOptions {
+            max_dynamic_len: Some(1024),
+            ..Default::default()
+        };
+
+        let long = vec![vec![b'a'; 10 * 1024], vec![0x01; 4]];
+        // unlimited by default
+        assert!(encode_data(&schema, &struct_type_map, &mut long.clone().into_iter()).is_ok());
+        let err = encode_data_with_options(
+            &schema,
+            &struct_type_map,
+            &mut long.clone().into_iter(),
+            &opts,
+        )
+        .unwrap_err();
+        assert_eq!(err, "text: value too large: 10240 > 1024");
+        let err = build_value_with_options(&schema, &mut long.into_iter(), &opts).unwrap_err();
+        assert_eq!(err, "text: value too large: 10240 > 1024");
+
+        let short = vec![vec![b'a'; 1024], vec![0x01; 1025]];
+        let err =
+            encode_data_with_options(&schema, &struct_type_map, &mut short.into_iter(), &opts)
+                .unwrap_err();
+        assert_eq!(err, "blob: value too large: 1025 > 1024");
+    }
+
+    #[test]
+    fn test_encode_data_oversized_uint() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        for (name, size) in [("U8", 1u8), ("U128", 16), ("U256", 32)] {
+            struct_defs.insert(
+                name.to_string(),
+                vec![Eip712FieldDefinition {
+                    name: "value".to_string(),
+                    field_type: Eip712FieldType::Uint(size),
+                    array_levels: vec![],
+                }],
+            );
+            let schema = build_schema(&struct_defs, &name.to_string()).unwrap();
+            let struct_type_map = encode_all_struct_type(&struct_defs).expect("success");
+
+            let fit = vec![vec![0x01; size as usize]];
+            assert!(encode_data(&schema, &struct_type_map, &mut fit.into_iter()).is_ok());
+
+            // a 17 bytes value for a uint8 field must be rejected too
+            for len in [size as usize + 1, 17, 33] {
+                if len <= size as usize {
+                    continue;
+                }
+                let oversized = vec![vec![0x01; len]];
+                let err =
+                    encode_data(&schema, &struct_type_map, &mut oversized.into_iter()).unwrap_err();
+                assert!(err.contains(&format!("uint{}", size as usize * 8)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_empty_arrays() {
+        let json = include_str!("../res/13-empty_arrays-data.json");
+        let typed_data: TypedData = serde_json::from_str(json).unwrap();
+
+        let struct_defs =
+            build_struct_defs_from_resolver(&typed_data.resolver).expect("build struct defs");
+
+        let raw_data = vec![
+            hex::decode("00").unwrap(), // test1: []
+            hex::decode("03").unwrap(), // test2
+            hex::decode("02").unwrap(),
+            b"one".to_vec(),
+            b"two".to_vec(),
+            hex::decode("00").unwrap(),
+            hex::decode("02").unwrap(),
+            b"three".to_vec(),
+            b"four".to_vec(),
+            hex::decode("01").unwrap(), // test3
+            hex::decode("01").unwrap(),
+            hex::decode("00").unwrap(),
+            hex::decode("03").unwrap(),
+            hex::decode("01").unwrap(),
+            hex::decode("01").unwrap(), // test4
+            hex::decode("00").unwrap(),
+            hex::decode("02").unwrap(),
+            hex::decode("00").unwrap(), // test5: []
+        ];
+
+        let primary_type = "Struct".to_string();
+        let schema = build_schema(&struct_defs, &primary_type).unwrap();
+
+        let message = build_value(&schema, &mut raw_data.clone().into_iter()).unwrap();
+        assert_eq!(message["test1"], serde_json::json!([]));
+        assert_eq!(message["test5"], serde_json::json!([]));
+        assert_eq!(message, typed_data.message);
+
+        let struct_type_map = encode_all_struct_type(&struct_defs).expect("success");
+        let encoded_data =
+            encode_data(&schema, &struct_type_map, &mut raw_data.clone().into_iter()).unwrap();
+        assert_eq!(
+            hex::encode(encoded_data),
             hex::encode(typed_data.encode_data().unwrap())
         );
+
+        let hash = eip712_signing_hash(
+            &struct_defs,
+            &mut raw_data.into_iter(),
+            &primary_type,
+            typed_data.domain(),
+        );
+        assert_eq!(hash.unwrap(), typed_data.eip712_signing_hash().unwrap());
+    }
+
+    #[test]
+    fn test_encode_empty_address_array() {
+        let json = r#"{
+            "types": {
+                "EIP712Domain": [{ "name": "name", "type": "string" }],
+                "Test": [{ "name": "owners", "type": "address[]" }]
+            },
+            "primaryType": "Test",
+            "domain": { "name": "Empty" },
+            "message": { "owners": [] }
+        }"#;
+        let typed_data: TypedData = serde_json::from_str(json).unwrap();
+        let struct_defs =
+            build_struct_defs_from_resolver(&typed_data.resolver).expect("build struct defs");
+        let primary_type = "Test".to_string();
+
+        let raw_data = vec![hex::decode("00").unwrap()];
+        let schema = build_schema(&struct_defs, &primary_type).unwrap();
+        let struct_type_map = encode_all_struct_type(&struct_defs).expect("success");
+        let encoded_data =
+            encode_data(&schema, &struct_type_map, &mut raw_data.clone().into_iter()).unwrap();
+        assert_eq!(encoded_data, keccak256([]).to_vec());
+
+        let message = build_value(&schema, &mut raw_data.clone().into_iter()).unwrap();
+        assert_eq!(message["owners"], serde_json::json!([]));
+
+        let hash = eip712_signing_hash(
+            &struct_defs,
+            &mut raw_data.into_iter(),
+            &primary_type,
+            typed_data.domain(),
+        );
+        assert_eq!(hash.unwrap(), typed_data.eip712_signing_hash().unwrap());
     }
 
     #[test]
@@ -510,10 +2256,8 @@ mod tests {
         // check encode_data is correct
         let encoded_data = encode_data(&schema, &struct_type_map, &mut raw_data.into_iter());
         assert!(encoded_data.is_ok());
-        assert_eq!(
-            hex::encode(encoded_data.unwrap()),
-            hex::encode(typed_data.encode_data().unwrap())
-        );
+        let expected = typed_data.encode_data().unwrap();
+        assert_eq!(compare_encoded(&encoded_data.unwrap(), &expected), None);
     }
 
     #[test]
@@ -1,4 +1,42 @@
-use alloc::{string::String, vec::Vec};
+use crate::{
+    Eip712Domain,
+    eip712::eip712_signing_hash,
+    format::{format_amount, format_datetime, write_hex_prefixed},
+    parser::{
+        CheckedFieldTransform, DomainSummary, TypeSchema, UIField, UIFieldKind, UIOptions,
+        build_schema, build_ui_fields_with_checked_transform, build_ui_fields_with_options,
+        domain_summary,
+    },
+    types::Eip712StructDefinitions,
+    utils::parse_utf8_string,
+};
+use alloc::{
+    borrow::Cow,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use alloy_primitives::{B256, keccak256};
+use bytes::{Buf, Bytes, TryGetError};
+use core::cell::RefCell;
+
+// filter tags, same as the P2 values of the Ledger EIP-712 filtering APDU
+const TAG_ACTIVATION: u8 = 0x00;
+const TAG_DISCARDED_FILTER_PATH: u8 = 0x01;
+const TAG_MESSAGE_INFO: u8 = 0x0F;
+const TAG_TRUSTED_NAME: u8 = 0xFB;
+const TAG_DATE_TIME: u8 = 0xFC;
+const TAG_AMOUNT_JOIN_TOKEN: u8 = 0xFD;
+const TAG_AMOUNT_JOIN_VALUE: u8 = 0xFE;
+const TAG_RAW_FIELD: u8 = 0xFF;
+
+/// First filter descriptor layout, without the discarded flag, only activation, message info and
+/// raw field filters exist
+pub const FILTER_VERSION_LEGACY: u8 = 0;
+/// Current layout, every filter carries a discarded flag after its tag
+pub const FILTER_VERSION_CURRENT: u8 = 1;
 
 /// EIP-712 filtering operation type
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -48,3 +86,1886 @@ pub struct Eip712FilterParams {
     /// Whether this filter is discarded
     pub discarded: bool,
 }
+
+fn get_u8(buf: &mut Bytes) -> Result<u8, &'static str> {
+    buf.try_get_u8()
+        .map_err(|_: TryGetError| "unexpected end of filter data")
+}
+
+// a u8 length followed by that many bytes
+fn get_len_prefixed(buf: &mut Bytes) -> Result<Vec<u8>, &'static str> {
+    let len = get_u8(buf)? as usize;
+    if buf.remaining() < len {
+        return Err("unexpected end of filter data");
+    }
+    let mut data = vec![0u8; len];
+    buf.copy_to_slice(&mut data);
+    Ok(data)
+}
+
+fn get_string(buf: &mut Bytes) -> Result<String, &'static str> {
+    parse_utf8_string(&get_len_prefixed(buf)?)
+}
+
+fn put_len_prefixed(bytes: &mut Vec<u8>, data: &[u8]) -> Result<(), &'static str> {
+    let len = u8::try_from(data.len()).map_err(|_| "filter data too long")?;
+    bytes.push(len);
+    bytes.extend_from_slice(data);
+    Ok(())
+}
+
+impl Eip712FilterParams {
+    // each filter is encoded as: tag u8 | discarded u8 (0 or 1) | payload,
+    // strings and signatures in the payload are prefixed with their u8 length.
+    // the legacy layout has no discarded flag and only knows the activation, message info and
+    // raw field tags
+    pub(crate) fn from_buf(buf: &mut Bytes, version: u8) -> Result<Self, &'static str> {
+        let tag = get_u8(buf)?;
+        let discarded = match version {
+            FILTER_VERSION_LEGACY => {
+                if !matches!(tag, TAG_ACTIVATION | TAG_MESSAGE_INFO | TAG_RAW_FIELD) {
+                    return Err("unknown filter tag");
+                }
+                false
+            }
+            FILTER_VERSION_CURRENT => match get_u8(buf)? {
+                0 => false,
+                1 => true,
+                _ => return Err("invalid filter discarded flag"),
+            },
+            _ => return Err("unknown filter version"),
+        };
+        let filter_type = match tag {
+            TAG_ACTIVATION => Eip712FilterType::Activation,
+            TAG_DISCARDED_FILTER_PATH => Eip712FilterType::DiscardedFilterPath(get_string(buf)?),
+            TAG_MESSAGE_INFO => Eip712FilterType::MessageInfo {
+                display_name: get_string(buf)?,
+                filters_count: get_u8(buf)?,
+                signature: get_len_prefixed(buf)?,
+            },
+            TAG_TRUSTED_NAME => Eip712FilterType::TrustedName {
+                display_name: get_string(buf)?,
+                name_types: get_len_prefixed(buf)?,
+                name_sources: get_len_prefixed(buf)?,
+                signature: get_len_prefixed(buf)?,
+            },
+            TAG_DATE_TIME => Eip712FilterType::DateTime {
+                display_name: get_string(buf)?,
+                signature: get_len_prefixed(buf)?,
+            },
+            TAG_AMOUNT_JOIN_TOKEN => Eip712FilterType::AmountJoinToken {
+                token_index: get_u8(buf)?,
+                signature: get_len_prefixed(buf)?,
+            },
+            TAG_AMOUNT_JOIN_VALUE => Eip712FilterType::AmountJoinValue {
+                display_name: get_string(buf)?,
+                token_index: get_u8(buf)?,
+                signature: get_len_prefixed(buf)?,
+            },
+            TAG_RAW_FIELD => Eip712FilterType::RawField {
+                display_name: get_string(buf)?,
+                signature: get_len_prefixed(buf)?,
+            },
+            _ => return Err("unknown filter tag"),
+        };
+        Ok(Eip712FilterParams {
+            filter_type,
+            discarded,
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        Eip712FilterParams::from_bytes_with_version(bytes, FILTER_VERSION_CURRENT)
+    }
+
+    /// Whether the filter is a discarded filter path, an unsigned marker naming the path of the
+    /// discarded filter following it. It hides nothing by itself and is not counted by the
+    /// message info filters count
+    pub fn is_discarded_path(&self) -> bool {
+        matches!(self.filter_type, Eip712FilterType::DiscardedFilterPath(_))
+    }
+
+    // length of the filter encoded in the `version` layout, the inverse of from_buf
+    pub fn encoded_len(&self, version: u8) -> usize {
+        let header = if version == FILTER_VERSION_LEGACY {
+            1
+        } else {
+            2
+        };
+        let payload = match &self.filter_type {
+            Eip712FilterType::Activation => 0,
+            Eip712FilterType::DiscardedFilterPath(path) => 1 + path.len(),
+            Eip712FilterType::MessageInfo {
+                display_name,
+                signature,
+                ..
+            } => 1 + display_name.len() + 1 + 1 + signature.len(),
+            Eip712FilterType::TrustedName {
+                display_name,
+                name_types,
+                name_sources,
+                signature,
+            } => 4 + display_name.len() + name_types.len() + name_sources.len() + signature.len(),
+            Eip712FilterType::DateTime {
+                display_name,
+                signature,
+            }
+            | Eip712FilterType::RawField {
+                display_name,
+                signature,
+            } => 2 + display_name.len() + signature.len(),
+            Eip712FilterType::AmountJoinToken { signature, .. } => 1 + 1 + signature.len(),
+            Eip712FilterType::AmountJoinValue {
+                display_name,
+                signature,
+                ..
+            } => 1 + display_name.len() + 1 + 1 + signature.len(),
+        };
+        header + payload
+    }
+
+    pub fn from_bytes_with_version(bytes: &[u8], version: u8) -> Result<Self, &'static str> {
+        let mut buf = Bytes::copy_from_slice(bytes);
+        let filter = Eip712FilterParams::from_buf(&mut buf, version)?;
+        if buf.has_remaining() {
+            return Err("extra data after filter");
+        }
+        Ok(filter)
+    }
+
+    // the inverse of from_buf, strings and signatures longer than 255 bytes can not be encoded
+    pub fn to_bytes(&self, version: u8) -> Result<Vec<u8>, &'static str> {
+        let mut bytes = Vec::with_capacity(self.encoded_len(version));
+        let tag = match &self.filter_type {
+            Eip712FilterType::Activation => TAG_ACTIVATION,
+            Eip712FilterType::DiscardedFilterPath(_) => TAG_DISCARDED_FILTER_PATH,
+            Eip712FilterType::MessageInfo { .. } => TAG_MESSAGE_INFO,
+            Eip712FilterType::TrustedName { .. } => TAG_TRUSTED_NAME,
+            Eip712FilterType::DateTime { .. } => TAG_DATE_TIME,
+            Eip712FilterType::AmountJoinToken { .. } => TAG_AMOUNT_JOIN_TOKEN,
+            Eip712FilterType::AmountJoinValue { .. } => TAG_AMOUNT_JOIN_VALUE,
+            Eip712FilterType::RawField { .. } => TAG_RAW_FIELD,
+        };
+        bytes.push(tag);
+        match version {
+            FILTER_VERSION_LEGACY => {
+                if self.discarded
+                    || !matches!(tag, TAG_ACTIVATION | TAG_MESSAGE_INFO | TAG_RAW_FIELD)
+                {
+                    return Err("filter not supported by the legacy layout");
+                }
+            }
+            FILTER_VERSION_CURRENT => bytes.push(self.discarded as u8),
+            _ => return Err("unknown filter version"),
+        }
+
+        match &self.filter_type {
+            Eip712FilterType::Activation => {}
+            Eip712FilterType::DiscardedFilterPath(path) => {
+                put_len_prefixed(&mut bytes, path.as_bytes())?;
+            }
+            Eip712FilterType::MessageInfo {
+                display_name,
+                filters_count,
+                signature,
+            } => {
+                put_len_prefixed(&mut bytes, display_name.as_bytes())?;
+                bytes.push(*filters_count);
+                put_len_prefixed(&mut bytes, signature)?;
+            }
+            Eip712FilterType::TrustedName {
+                display_name,
+                name_types,
+                name_sources,
+                signature,
+            } => {
+                put_len_prefixed(&mut bytes, display_name.as_bytes())?;
+                put_len_prefixed(&mut bytes, name_types)?;
+                put_len_prefixed(&mut bytes, name_sources)?;
+                put_len_prefixed(&mut bytes, signature)?;
+            }
+            Eip712FilterType::DateTime {
+                display_name,
+                signature,
+            }
+            | Eip712FilterType::RawField {
+                display_name,
+                signature,
+            } => {
+                put_len_prefixed(&mut bytes, display_name.as_bytes())?;
+                put_len_prefixed(&mut bytes, signature)?;
+            }
+            Eip712FilterType::AmountJoinToken {
+                token_index,
+                signature,
+            } => {
+                bytes.push(*token_index);
+                put_len_prefixed(&mut bytes, signature)?;
+            }
+            Eip712FilterType::AmountJoinValue {
+                display_name,
+                token_index,
+                signature,
+            } => {
+                put_len_prefixed(&mut bytes, display_name.as_bytes())?;
+                bytes.push(*token_index);
+                put_len_prefixed(&mut bytes, signature)?;
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// A message info filter followed by its field filters, as received once per message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilterBundle {
+    /// Layout version the bundle was decoded with
+    pub version: u8,
+    pub message_info: Eip712FilterParams,
+    pub filters: Vec<Eip712FilterParams>,
+}
+
+impl FilterBundle {
+    /// Decode a bundle in the current layout
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut buf = Bytes::copy_from_slice(bytes);
+        FilterBundle::from_buf(&mut buf, FILTER_VERSION_CURRENT)
+    }
+
+    /// Decode a bundle prefixed with its layout version byte
+    pub fn from_versioned_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut buf = Bytes::copy_from_slice(bytes);
+        let version = get_u8(&mut buf)?;
+        FilterBundle::from_buf(&mut buf, version)
+    }
+
+    /// Number of field filters following the message info, discarded ones included but not the
+    /// discarded filter paths, the value the message info filters count must match
+    pub fn filter_count(&self) -> usize {
+        self.filters
+            .iter()
+            .filter(|filter| !filter.is_discarded_path())
+            .count()
+    }
+
+    /// Encoded length of the bundle, message info included, without the version prefix
+    pub fn byte_len(&self) -> usize {
+        self.filters
+            .iter()
+            .chain(core::iter::once(&self.message_info))
+            .map(|filter| filter.encoded_len(self.version))
+            .sum()
+    }
+
+    /// Encode the message info and the filters without the version prefix, the inverse of
+    /// `from_bytes`. Only a bundle in the current layout can be encoded this way, an older one
+    /// needs its version prefix, see `to_versioned_bytes`
+    pub fn to_bytes(&self) -> Result<Vec<u8>, &'static str> {
+        if self.version != FILTER_VERSION_CURRENT {
+            return Err("bundle layout needs its version prefix");
+        }
+        let mut bytes = Vec::with_capacity(self.byte_len());
+        self.encode_filters(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Encode the bundle prefixed with its layout version byte, the inverse of
+    /// `from_versioned_bytes`
+    pub fn to_versioned_bytes(&self) -> Result<Vec<u8>, &'static str> {
+        let mut bytes = Vec::with_capacity(1 + self.byte_len());
+        bytes.push(self.version);
+        self.encode_filters(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn encode_filters(&self, bytes: &mut Vec<u8>) -> Result<(), &'static str> {
+        bytes.extend(self.message_info.to_bytes(self.version)?);
+        for filter in &self.filters {
+            bytes.extend(filter.to_bytes(self.version)?);
+        }
+        Ok(())
+    }
+
+    /// Keccak-256 of `to_versioned_bytes`, a key for caching the descriptors of the bundle
+    /// alongside the schema fingerprint
+    pub fn fingerprint(&self) -> Result<B256, &'static str> {
+        Ok(keccak256(self.to_versioned_bytes()?))
+    }
+
+    fn from_buf(buf: &mut Bytes, version: u8) -> Result<Self, &'static str> {
+        let message_info = Eip712FilterParams::from_buf(buf, version)?;
+        let filters_count = match message_info.filter_type {
+            Eip712FilterType::MessageInfo { filters_count, .. } => filters_count,
+            _ => return Err("bundle must start with a message info filter"),
+        };
+
+        // the discarded filter paths are not counted, each one must precede a discarded filter
+        let mut filters: Vec<Eip712FilterParams> = Vec::with_capacity(filters_count as usize);
+        let mut counted = 0;
+        while counted < filters_count {
+            let filter = Eip712FilterParams::from_buf(buf, version)?;
+            if matches!(
+                filter.filter_type,
+                Eip712FilterType::MessageInfo { .. } | Eip712FilterType::Activation
+            ) {
+                return Err("unexpected filter in bundle");
+            }
+            let after_path = filters.last().is_some_and(|last| last.is_discarded_path());
+            if after_path && !filter.discarded {
+                return Err("discarded filter path must precede a discarded filter");
+            }
+            if !filter.is_discarded_path() {
+                counted += 1;
+            }
+            filters.push(filter);
+        }
+        if buf.has_remaining() {
+            return Err("extra data after filters");
+        }
+
+        Ok(FilterBundle {
+            version,
+            message_info,
+            filters,
+        })
+    }
+}
+
+/// Kind of name a trusted name filter accepts, same values as the Ledger trusted name types
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrustedNameType {
+    Account = 0x01,
+    Contract = 0x02,
+    Nft = 0x03,
+    Token = 0x04,
+    Wallet = 0x05,
+    ContextAddress = 0x06,
+}
+
+impl TryFrom<u8> for TrustedNameType {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let name_type = match value {
+            0x01 => TrustedNameType::Account,
+            0x02 => TrustedNameType::Contract,
+            0x03 => TrustedNameType::Nft,
+            0x04 => TrustedNameType::Token,
+            0x05 => TrustedNameType::Wallet,
+            0x06 => TrustedNameType::ContextAddress,
+            _ => return Err(format!("unknown name type: {}", value)),
+        };
+        Ok(name_type)
+    }
+}
+
+/// Where a trusted name comes from, same values as the Ledger trusted name sources
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrustedNameSource {
+    LocalAddressBook = 0x00,
+    CryptoAssetList = 0x01,
+    Ens = 0x02,
+    UnstoppableDomain = 0x03,
+    Freename = 0x04,
+    Dns = 0x05,
+    DynamicResolver = 0x06,
+}
+
+impl TryFrom<u8> for TrustedNameSource {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        let source = match value {
+            0x00 => TrustedNameSource::LocalAddressBook,
+            0x01 => TrustedNameSource::CryptoAssetList,
+            0x02 => TrustedNameSource::Ens,
+            0x03 => TrustedNameSource::UnstoppableDomain,
+            0x04 => TrustedNameSource::Freename,
+            0x05 => TrustedNameSource::Dns,
+            0x06 => TrustedNameSource::DynamicResolver,
+            _ => return Err(format!("unknown name source: {}", value)),
+        };
+        Ok(source)
+    }
+}
+
+/// A name resolved by the host for an address field
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrustedNameResolution {
+    pub name: String,
+    pub name_type: TrustedNameType,
+    pub source: TrustedNameSource,
+}
+
+/// A trusted name filter with validated name types and sources
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrustedName {
+    pub display_name: String,
+    pub name_types: Vec<TrustedNameType>,
+    pub name_sources: Vec<TrustedNameSource>,
+    pub signature: Vec<u8>,
+}
+
+impl TrustedName {
+    pub fn parse(filter_type: &Eip712FilterType) -> Result<Self, String> {
+        match filter_type {
+            Eip712FilterType::TrustedName {
+                display_name,
+                name_types,
+                name_sources,
+                signature,
+            } => Ok(TrustedName {
+                display_name: display_name.clone(),
+                name_types: name_types
+                    .iter()
+                    .map(|b| TrustedNameType::try_from(*b))
+                    .collect::<Result<_, _>>()?,
+                name_sources: name_sources
+                    .iter()
+                    .map(|b| TrustedNameSource::try_from(*b))
+                    .collect::<Result<_, _>>()?,
+                signature: signature.clone(),
+            }),
+            _ => Err("not a trusted name filter".into()),
+        }
+    }
+
+    /// The resolved name if both its type and its source are allowed by the filter
+    pub fn accept<'r>(&self, resolution: &'r TrustedNameResolution) -> Option<&'r str> {
+        let allowed = self.name_types.contains(&resolution.name_type)
+            && self.name_sources.contains(&resolution.source);
+        allowed.then_some(resolution.name.as_str())
+    }
+}
+
+/// How leaves without a filter are handled when building clear-sign fields
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClearSignPolicy {
+    /// Only filtered leaves are shown, with `reject_unfiltered` any uncovered leaf is an error
+    Strict { reject_unfiltered: bool },
+    /// Unfiltered leaves are shown raw, labelled by their path
+    #[default]
+    Permissive,
+}
+
+/// A field shown to the user during clear-signing
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClearSignField<'a> {
+    pub label: Cow<'a, str>,
+    pub path: String,
+    pub value: Cow<'a, str>,
+}
+
+/// Build the clear-sign fields from the UI leaves and the filters keyed by their field path
+///
+/// A leaf whose filter has the discarded flag is hidden and never reported as unfiltered. A path
+/// carries a single filter, so a discard never overrides another filter, and a discarded filter
+/// path is not a field filter: it hides nothing and is rejected
+pub fn build_clear_sign_fields<'a>(
+    ui_fields: Vec<UIField<'a>>,
+    filters: &'a BTreeMap<String, Eip712FilterParams>,
+    policy: ClearSignPolicy,
+) -> Result<Vec<ClearSignField<'a>>, String> {
+    let mut fields = Vec::new();
+    for ui_field in ui_fields {
+        let filter = match filters.get(&ui_field.path) {
+            Some(filter) => filter,
+            None => match policy {
+                ClearSignPolicy::Strict {
+                    reject_unfiltered: true,
+                } => {
+                    return Err(format!("unfiltered field: {}", ui_field.path));
+                }
+                ClearSignPolicy::Strict {
+                    reject_unfiltered: false,
+                } => continue,
+                ClearSignPolicy::Permissive => {
+                    fields.push(ClearSignField {
+                        label: Cow::Owned(ui_field.path.clone()),
+                        path: ui_field.path,
+                        value: ui_field.value,
+                    });
+                    continue;
+                }
+            },
+        };
+        if filter.discarded {
+            continue;
+        }
+
+        let label = match &filter.filter_type {
+            Eip712FilterType::TrustedName { display_name, .. }
+            | Eip712FilterType::DateTime { display_name, .. }
+            | Eip712FilterType::AmountJoinValue { display_name, .. }
+            | Eip712FilterType::RawField { display_name, .. } => display_name.as_str(),
+            // the token half of an amount-join is shown with its value
+            Eip712FilterType::AmountJoinToken { .. } => continue,
+            _ => return Err(format!("invalid field filter: {}", ui_field.path)),
+        };
+        fields.push(ClearSignField {
+            label: Cow::Borrowed(label),
+            path: ui_field.path,
+            value: ui_field.value,
+        });
+    }
+    Ok(fields)
+}
+
+/// Reorder the UI leaves to follow `filter_paths`, the leaves of an array filter path keep their
+/// message order and the unfiltered leaves go last. Every filter path must exist in `schema`
+pub fn order_ui_fields_by_filters<'a>(
+    ui_fields: Vec<UIField<'a>>,
+    schema: &TypeSchema,
+    filter_paths: &[String],
+) -> Result<Vec<UIField<'a>>, String> {
+    let mut remaining = ui_fields;
+    let mut fields = Vec::with_capacity(remaining.len());
+    for path in filter_paths {
+        if schema_at_filter_path(schema, path).is_none() {
+            return Err(format!("unknown filter path: {}", path));
+        }
+        let (matched, rest): (Vec<_>, Vec<_>) = remaining
+            .into_iter()
+            .partition(|ui_field| &ui_field.path == path);
+        fields.extend(matched);
+        remaining = rest;
+    }
+    fields.extend(remaining);
+    Ok(fields)
+}
+
+/// Token indices referenced by the amount-join filters, sorted and without duplicates, so the host
+/// can fetch the token metadata before the values are displayed
+pub fn amount_join_token_indices(filters: &[Eip712FilterParams]) -> Vec<u8> {
+    let mut indices: Vec<u8> = filters
+        .iter()
+        .filter_map(|filter| match filter.filter_type {
+            Eip712FilterType::AmountJoinToken { token_index, .. }
+            | Eip712FilterType::AmountJoinValue { token_index, .. } => Some(token_index),
+            _ => None,
+        })
+        .collect();
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+}
+
+// the schema node of a filter path, eg: from.wallets.[]
+fn schema_at_filter_path<'a>(schema: &'a TypeSchema, path: &str) -> Option<&'a TypeSchema> {
+    let mut node = schema;
+    for segment in path.split('.') {
+        node = match node {
+            TypeSchema::Array { item } if segment == "[]" => item,
+            TypeSchema::Struct { fields, .. } => {
+                &fields.iter().find(|field| field.name == segment)?.ty
+            }
+            _ => return None,
+        };
+    }
+    Some(node)
+}
+
+/// Paths of the amount-join token filters which supply a token address in `schema`,
+/// filters on a missing or non-address field are skipped
+pub fn referenced_token_fields(
+    filters: &BTreeMap<String, Eip712FilterParams>,
+    schema: &TypeSchema,
+) -> Vec<String> {
+    filters
+        .iter()
+        .filter(|(_, filter)| {
+            matches!(filter.filter_type, Eip712FilterType::AmountJoinToken { .. })
+        })
+        .filter(|(path, _)| {
+            matches!(
+                schema_at_filter_path(schema, path),
+                Some(TypeSchema::Primitive { name, .. }) if name == "address"
+            )
+        })
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// Host lookup of the trusted name of an address
+pub trait TrustedNameResolver {
+    fn resolve(&self, addr: &[u8; 20]) -> Option<TrustedNameResolution>;
+}
+
+/// Host check of a filter signature, `path` is the filtered field path and empty for the
+/// message info
+pub trait FilterVerifier {
+    fn verify(&self, path: &str, filter: &Eip712FilterType) -> bool;
+}
+
+/// Token metadata provided by the host for a token address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenInfo {
+    pub ticker: String,
+    pub decimals: u8,
+}
+
+/// Host inputs of `clear_sign_preview` besides the message and its filters
+pub struct ClearSignOptions<'a> {
+    pub verifier: &'a dyn FilterVerifier,
+    /// Field path of each bundle filter, in the bundle order, eg: to.wallets.[]. The entries of a
+    /// discarded filter path and of the discarded filter following it are ignored, the path is
+    /// the one carried by the discarded filter path
+    pub filter_paths: Vec<String>,
+    /// Tokens keyed by their address, an amount is formatted with the token whose address the
+    /// message holds at the amount-join token field of the same index
+    pub tokens: BTreeMap<[u8; 20], TokenInfo>,
+    pub policy: ClearSignPolicy,
+    pub ui: UIOptions,
+    /// Show the fields in the bundle filter order instead of message order
+    pub filter_order: bool,
+}
+
+/// What a trusted clear-sign flow shows: the message info title, the domain and the fields
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClearSignPreview {
+    pub title: String,
+    pub domain: DomainSummary,
+    pub fields: Vec<ClearSignField<'static>>,
+}
+
+// the token address of each amount of an amount-join token index, in message order
+type AmountTokens = BTreeMap<u8, Vec<Option<[u8; 20]>>>;
+
+// formats the filtered leaves, a value which can not be formatted is an error rather than its
+// raw display under a trusted label
+struct FilterFormatter<'f, R> {
+    schema: &'f TypeSchema,
+    filters: &'f BTreeMap<String, Eip712FilterParams>,
+    resolver: &'f R,
+    tokens: &'f BTreeMap<[u8; 20], TokenInfo>,
+    amount_tokens: &'f AmountTokens,
+    // the amounts formatted so far for each amount-join token index
+    amount_counts: RefCell<BTreeMap<u8, usize>>,
+}
+
+impl<R: TrustedNameResolver> CheckedFieldTransform for FilterFormatter<'_, R> {
+    fn try_transform(
+        &self,
+        path: &str,
+        raw: &[u8],
+        _default: &str,
+    ) -> Result<Option<String>, String> {
+        let Some(filter) = self.filters.get(path) else {
+            return Ok(None);
+        };
+        match &filter.filter_type {
+            // a discarded amount still counts, the next amounts keep their tokens
+            Eip712FilterType::AmountJoinValue { token_index, .. } => {
+                let nth = {
+                    let mut counts = self.amount_counts.borrow_mut();
+                    let count = counts.entry(*token_index).or_default();
+                    *count += 1;
+                    *count - 1
+                };
+                if filter.discarded {
+                    return Ok(None);
+                }
+                let (signed, size) = match schema_at_filter_path(self.schema, path) {
+                    Some(TypeSchema::Primitive {
+                        name,
+                        size: Some(size),
+                    }) if name == "int" || name == "uint" => (name == "int", *size as usize),
+                    _ => return Err(format!("amount-join value is not an integer: {}", path)),
+                };
+                let addr = self
+                    .amount_tokens
+                    .get(token_index)
+                    .and_then(|addrs| addrs.get(nth).copied().flatten())
+                    .ok_or_else(|| format!("no token address for amount: {}", path))?;
+                let token = self.tokens.get(&addr).ok_or_else(|| {
+                    let mut err = String::from("unknown token ");
+                    write_hex_prefixed(&mut err, &addr);
+                    err
+                })?;
+                let amount = format_amount(raw, size, signed, token.decimals, &token.ticker)
+                    .map_err(|err| format!("{}: {}", path, err))?;
+                Ok(Some(amount.text))
+            }
+            _ if filter.discarded => Ok(None),
+            Eip712FilterType::DateTime { .. } => format_datetime(raw)
+                .map(Some)
+                .map_err(|err| format!("{}: {}", path, err)),
+            Eip712FilterType::TrustedName { .. } => {
+                let addr: &[u8; 20] = raw
+                    .try_into()
+                    .map_err(|_| format!("trusted name is not an address: {}", path))?;
+                let trusted_name = TrustedName::parse(&filter.filter_type)
+                    .map_err(|err| format!("{}: {}", path, err))?;
+                // an unknown or unaccepted name shows the address itself
+                Ok(self.resolver.resolve(addr).and_then(|resolution| {
+                    trusted_name
+                        .accept(&resolution)
+                        .map(|name| name.to_string())
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// pairs each amount of an amount-join token index with a token, discarded ones included, in
+// message order like the device does: a token and an amount go together whichever comes first,
+// a later token replaces one still waiting for its amount. An amount left without a token is None
+fn collect_amount_tokens(
+    schema: &TypeSchema,
+    data: &[Vec<u8>],
+    filters: &BTreeMap<String, Eip712FilterParams>,
+    opts: &UIOptions,
+) -> Result<AmountTokens, String> {
+    let mut amount_tokens = AmountTokens::new();
+    // per index, a token waiting for its amount or the position of an amount waiting for its token
+    let mut waiting_tokens: BTreeMap<u8, [u8; 20]> = BTreeMap::new();
+    let mut waiting_amounts: BTreeMap<u8, usize> = BTreeMap::new();
+    let mut ref_data = data.iter().map(|v| v.as_slice());
+    for field in build_ui_fields_with_options(schema, &mut ref_data, "", opts)? {
+        // an absent field is not formatted, it joins nothing
+        if field.kind == UIFieldKind::Absent {
+            continue;
+        }
+        match filters.get(&field.path).map(|filter| &filter.filter_type) {
+            Some(Eip712FilterType::AmountJoinToken { token_index, .. }) => {
+                let UIFieldKind::Address(addr) = field.kind else {
+                    return Err(format!(
+                        "amount-join token is not an address: {}",
+                        field.path
+                    ));
+                };
+                match waiting_amounts.remove(token_index) {
+                    Some(pos) => amount_tokens.entry(*token_index).or_default()[pos] = Some(addr),
+                    None => {
+                        waiting_tokens.insert(*token_index, addr);
+                    }
+                }
+            }
+            Some(Eip712FilterType::AmountJoinValue { token_index, .. }) => {
+                let amounts = amount_tokens.entry(*token_index).or_default();
+                let token = waiting_tokens.remove(token_index);
+                if token.is_none() {
+                    waiting_amounts.insert(*token_index, amounts.len());
+                }
+                amounts.push(token);
+            }
+            _ => {}
+        }
+    }
+    Ok(amount_tokens)
+}
+
+/// Single entry point of a trusted clear-sign flow: checks the filter signatures and count,
+/// formats the filtered fields and returns them with the message info title, in message order
+/// unless `opts.filter_order` is set
+pub fn clear_sign_preview(
+    struct_defs: &Eip712StructDefinitions,
+    data: &[Vec<u8>],
+    primary_type: &String,
+    domain: &Eip712Domain,
+    bundle: &FilterBundle,
+    resolver: &impl TrustedNameResolver,
+    opts: &ClearSignOptions,
+) -> Result<ClearSignPreview, String> {
+    let (title, filters_count) = match &bundle.message_info.filter_type {
+        Eip712FilterType::MessageInfo {
+            display_name,
+            filters_count,
+            ..
+        } => (display_name, *filters_count as usize),
+        _ => return Err("bundle must start with a message info filter".into()),
+    };
+    if filters_count != bundle.filter_count() {
+        return Err("filters count mismatch".into());
+    }
+    if opts.filter_paths.len() != bundle.filters.len() {
+        return Err("filter paths count mismatch".into());
+    }
+    if !opts.verifier.verify("", &bundle.message_info.filter_type) {
+        return Err("invalid message info signature".into());
+    }
+
+    let mut filters: BTreeMap<String, Eip712FilterParams> = BTreeMap::new();
+    let mut ordered_paths = Vec::new();
+    // a discarded filter path is not signed, it only names the path of the next filter which
+    // must be a discarded one, verified against that path
+    let mut discarded_path: Option<&String> = None;
+    for (path, filter) in opts.filter_paths.iter().zip(&bundle.filters) {
+        if let Eip712FilterType::DiscardedFilterPath(next_path) = &filter.filter_type {
+            if discarded_path.replace(next_path).is_some() {
+                return Err("discarded filter path must precede a discarded filter".into());
+            }
+            continue;
+        }
+        let path = match discarded_path.take() {
+            Some(_) if !filter.discarded => {
+                return Err("discarded filter path must precede a discarded filter".into());
+            }
+            Some(next_path) => next_path,
+            None => path,
+        };
+        if !opts.verifier.verify(path, &filter.filter_type) {
+            return Err(format!("invalid filter signature: {}", path));
+        }
+        // a discard never overrides another filter on the same path
+        if filters.insert(path.clone(), filter.clone()).is_some() {
+            return Err(format!("duplicate filter path: {}", path));
+        }
+        ordered_paths.push(path.clone());
+    }
+    if discarded_path.is_some() {
+        return Err("discarded filter path must precede a discarded filter".into());
+    }
+
+    let schema = build_schema(struct_defs, primary_type)?;
+    // paired first, an amount may come before its token in the message
+    let amount_tokens = collect_amount_tokens(&schema, data, &filters, &opts.ui)?;
+    let formatter = FilterFormatter {
+        schema: &schema,
+        filters: &filters,
+        resolver,
+        tokens: &opts.tokens,
+        amount_tokens: &amount_tokens,
+        amount_counts: RefCell::new(BTreeMap::new()),
+    };
+    let mut ref_data = data.iter().map(|v| v.as_slice());
+    let mut ui_fields =
+        build_ui_fields_with_checked_transform(&schema, &mut ref_data, "", &opts.ui, &formatter)?;
+    if opts.filter_order {
+        ui_fields = order_ui_fields_by_filters(ui_fields, &schema, &ordered_paths)?;
+    }
+    let fields = build_clear_sign_fields(ui_fields, &filters, opts.policy)?
+        .into_iter()
+        .map(|field| ClearSignField {
+            label: Cow::Owned(field.label.into_owned()),
+            path: field.path,
+            value: Cow::Owned(field.value.into_owned()),
+        })
+        .collect();
+
+    Ok(ClearSignPreview {
+        title: title.clone(),
+        domain: domain_summary(domain, &opts.ui),
+        fields,
+    })
+}
+
+/// What the blind-signing fallback shows when no descriptor matches the message: a warning with
+/// the domain, the primary type and the signing hash instead of the fields
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlindSignSummary {
+    pub domain: DomainSummary,
+    pub primary_type: String,
+    /// The `0x` prefixed signing hash
+    pub hash: String,
+}
+
+/// The explicit no-descriptor path, no field is formatted
+pub fn blind_sign_summary(
+    struct_defs: &Eip712StructDefinitions,
+    data: &[Vec<u8>],
+    primary_type: &String,
+    domain: &Eip712Domain,
+) -> Result<BlindSignSummary, String> {
+    let signing_hash =
+        eip712_signing_hash(struct_defs, &mut data.iter().cloned(), primary_type, domain)?;
+    let mut hash = String::new();
+    write_hex_prefixed(&mut hash, signing_hash.as_slice());
+    Ok(BlindSignSummary {
+        domain: domain_summary(domain, &UIOptions::default()),
+        primary_type: primary_type.clone(),
+        hash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::{build_schema, build_ui_fields},
+        test_utils::{get_raw_mail_typed_data, prepare_mail_data, prepare_mail_struct_defs},
+        types::{Eip712FieldDefinition, Eip712FieldType},
+    };
+    use alloc::string::ToString;
+
+    fn raw_field(display_name: &str) -> Eip712FilterParams {
+        raw_field_with_sig(display_name, vec![])
+    }
+
+    fn raw_field_with_sig(display_name: &str, signature: Vec<u8>) -> Eip712FilterParams {
+        Eip712FilterParams {
+            filter_type: Eip712FilterType::RawField {
+                display_name: display_name.to_string(),
+                signature,
+            },
+            discarded: false,
+        }
+    }
+
+    fn mail_filters() -> BTreeMap<String, Eip712FilterParams> {
+        let mut filters = BTreeMap::new();
+        filters.insert("from.name".to_string(), raw_field("From"));
+        filters.insert("to.name".to_string(), raw_field("To"));
+        filters.insert("contents".to_string(), raw_field("Message"));
+        filters
+    }
+
+    fn mail_schema() -> TypeSchema {
+        let struct_defs = prepare_mail_struct_defs();
+        build_schema(&struct_defs, &"Mail".to_string()).unwrap()
+    }
+
+    fn mail_ui_fields<'a>(schema: &'a TypeSchema, data: &'a [Vec<u8>]) -> Vec<UIField<'a>> {
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        build_ui_fields(schema, &mut ref_data, "").unwrap()
+    }
+
+    fn bundle_bytes() -> Vec<u8> {
+        let mut bytes = vec![TAG_MESSAGE_INFO, 0];
+        bytes.push(4);
+        bytes.extend_from_slice(b"Mail");
+        bytes.extend_from_slice(&[2, 2, 0xaa, 0xbb]);
+
+        bytes.extend_from_slice(&[TAG_RAW_FIELD, 0, 4]);
+        bytes.extend_from_slice(b"From");
+        bytes.extend_from_slice(&[1, 0xcc]);
+
+        bytes.extend_from_slice(&[TAG_AMOUNT_JOIN_TOKEN, 1, 0, 1, 0xdd]);
+        bytes
+    }
+
+    // a raw field followed by two discarded filters, each named by a discarded filter path
+    fn trailing_discarded_bundle_bytes() -> Vec<u8> {
+        let mut bytes = vec![TAG_MESSAGE_INFO, 0, 4];
+        bytes.extend_from_slice(b"Mail");
+        bytes.extend_from_slice(&[3, 1, 0xaa]);
+
+        bytes.extend_from_slice(&[TAG_RAW_FIELD, 0, 4]);
+        bytes.extend_from_slice(b"From");
+        bytes.extend_from_slice(&[1, 0xcc]);
+
+        bytes.extend_from_slice(&[TAG_DISCARDED_FILTER_PATH, 0, 6]);
+        bytes.extend_from_slice(b"amount");
+        bytes.extend_from_slice(&[TAG_AMOUNT_JOIN_TOKEN, 1, 0, 1, 0xdd]);
+
+        bytes.extend_from_slice(&[TAG_DISCARDED_FILTER_PATH, 0, 7]);
+        bytes.extend_from_slice(b"payback");
+        bytes.extend_from_slice(&[TAG_RAW_FIELD, 1, 4]);
+        bytes.extend_from_slice(b"Back");
+        bytes.extend_from_slice(&[1, 0xee]);
+        bytes
+    }
+
+    #[test]
+    fn test_filter_bundle_from_bytes() {
+        let bundle = FilterBundle::from_bytes(&bundle_bytes()).unwrap();
+        assert_eq!(
+            bundle.message_info.filter_type,
+            Eip712FilterType::MessageInfo {
+                display_name: "Mail".to_string(),
+                filters_count: 2,
+                signature: vec![0xaa, 0xbb],
+            }
+        );
+        assert_eq!(bundle.filters[0], raw_field_with_sig("From", vec![0xcc]));
+        assert_eq!(
+            bundle.filters[1],
+            Eip712FilterParams {
+                filter_type: Eip712FilterType::AmountJoinToken {
+                    token_index: 0,
+                    signature: vec![0xdd],
+                },
+                discarded: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_filter_bundle_trailing_discarded() {
+        let bytes = trailing_discarded_bundle_bytes();
+        let bundle = FilterBundle::from_bytes(&bytes).unwrap();
+        assert_eq!(bundle.filters.len(), 5);
+        // the discarded filter paths are not counted, the discarded filters are
+        assert_eq!(bundle.filter_count(), 3);
+        assert_eq!(bundle.byte_len(), bytes.len());
+        assert_eq!(
+            bundle.filters[3].filter_type,
+            Eip712FilterType::DiscardedFilterPath("payback".to_string())
+        );
+        assert!(bundle.filters[4].discarded);
+
+        // a discarded filter path must be followed by a discarded filter
+        let mut dangling = bytes.clone();
+        dangling.extend_from_slice(&[TAG_DISCARDED_FILTER_PATH, 0, 1, b'x']);
+        assert_eq!(
+            FilterBundle::from_bytes(&dangling),
+            Err("extra data after filters")
+        );
+        let mut not_discarded = bytes[..bytes.len() - 9].to_vec();
+        not_discarded.extend_from_slice(&[TAG_RAW_FIELD, 0, 4]);
+        not_discarded.extend_from_slice(b"Back");
+        not_discarded.extend_from_slice(&[1, 0xee]);
+        assert_eq!(
+            FilterBundle::from_bytes(&not_discarded),
+            Err("discarded filter path must precede a discarded filter")
+        );
+    }
+
+    #[test]
+    fn test_filter_bundle_sizes() {
+        let bytes = bundle_bytes();
+        let bundle = FilterBundle::from_bytes(&bytes).unwrap();
+        assert_eq!(bundle.byte_len(), bytes.len());
+        assert_eq!(bundle.filter_count(), 2);
+        assert!(matches!(
+            bundle.message_info.filter_type,
+            Eip712FilterType::MessageInfo {
+                filters_count: 2,
+                ..
+            }
+        ));
+
+        let (bytes, _) = signed_mail_bundle();
+        let bundle = FilterBundle::from_bytes(&bytes).unwrap();
+        assert_eq!(bundle.byte_len(), bytes.len());
+        assert_eq!(bundle.filter_count(), 6);
+
+        // the legacy layout has no discarded flag
+        let mut legacy = vec![FILTER_VERSION_LEGACY, TAG_MESSAGE_INFO, 1, b'M', 1, 1, 0xaa];
+        legacy.extend_from_slice(&[TAG_RAW_FIELD, 1, b'F', 0]);
+        let bundle = FilterBundle::from_versioned_bytes(&legacy).unwrap();
+        assert_eq!(bundle.byte_len(), legacy.len() - 1);
+        assert_eq!(bundle.filter_count(), 1);
+    }
+
+    #[test]
+    fn test_filter_bundle_fingerprint() {
+        for bytes in [bundle_bytes(), signed_mail_bundle().0] {
+            let bundle = FilterBundle::from_bytes(&bytes).unwrap();
+            assert_eq!(bundle.to_bytes().unwrap(), bytes);
+            let reparsed = FilterBundle::from_bytes(&bundle.to_bytes().unwrap()).unwrap();
+            assert_eq!(reparsed.fingerprint(), bundle.fingerprint());
+        }
+
+        let mail = FilterBundle::from_bytes(&signed_mail_bundle().0).unwrap();
+        let mut renamed = mail.clone();
+        renamed.filters[0] = raw_field("Sender");
+        assert_ne!(renamed.fingerprint(), mail.fingerprint());
+
+        // the layout version is part of the key
+        let mut legacy = vec![FILTER_VERSION_LEGACY, TAG_MESSAGE_INFO, 1, b'M', 1, 1, 0xaa];
+        legacy.extend_from_slice(&[TAG_RAW_FIELD, 1, b'F', 0]);
+        let bundle = FilterBundle::from_versioned_bytes(&legacy).unwrap();
+        assert_eq!(bundle.to_versioned_bytes().unwrap(), legacy);
+        // the unversioned form would be decoded in the current layout
+        assert_eq!(
+            bundle.to_bytes(),
+            Err("bundle layout needs its version prefix")
+        );
+        let mut current = bundle.clone();
+        current.version = FILTER_VERSION_CURRENT;
+        assert_ne!(current.fingerprint(), bundle.fingerprint());
+
+        // a discarded filter has no legacy encoding
+        let mut discarded = bundle;
+        discarded.filters[0].discarded = true;
+        assert_eq!(
+            discarded.fingerprint(),
+            Err("filter not supported by the legacy layout")
+        );
+    }
+
+    #[test]
+    fn test_filter_bundle_invalid() {
+        let bytes = bundle_bytes();
+        for len in 0..bytes.len() {
+            assert_eq!(
+                FilterBundle::from_bytes(&bytes[..len]),
+                Err("unexpected end of filter data")
+            );
+        }
+
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert_eq!(
+            FilterBundle::from_bytes(&extra),
+            Err("extra data after filters")
+        );
+
+        // a bundle starts with the message info
+        assert_eq!(
+            FilterBundle::from_bytes(&bytes[11..]),
+            Err("bundle must start with a message info filter")
+        );
+    }
+
+    #[test]
+    fn test_clear_sign_strict() {
+        let schema = mail_schema();
+        let data = prepare_mail_data();
+        let filters = mail_filters();
+
+        let policy = ClearSignPolicy::Strict {
+            reject_unfiltered: false,
+        };
+        let fields =
+            build_clear_sign_fields(mail_ui_fields(&schema, &data), &filters, policy).unwrap();
+        let labels: Vec<_> = fields.iter().map(|f| f.label.as_ref()).collect();
+        assert_eq!(labels, ["From", "To", "Message"]);
+        assert_eq!(fields[0].value, "Cow");
+        assert_eq!(fields[2].value, "Hello, Bob!");
+
+        let policy = ClearSignPolicy::Strict {
+            reject_unfiltered: true,
+        };
+        let err =
+            build_clear_sign_fields(mail_ui_fields(&schema, &data), &filters, policy).unwrap_err();
+        assert_eq!(err, "unfiltered field: from.wallets.[]");
+    }
+
+    #[test]
+    fn test_clear_sign_discarded_path() {
+        let schema = mail_schema();
+        let data = prepare_mail_data();
+        let mut filters = mail_filters();
+        for path in [
+            "from.wallets.[]",
+            "to.wallets.[]",
+            "timestamp",
+            "amount",
+            "payback",
+        ] {
+            let mut filter = raw_field(path);
+            filter.discarded = true;
+            filters.insert(path.to_string(), filter);
+        }
+        filters.get_mut("to.name").unwrap().discarded = true;
+
+        // discarded leaves are hidden and not reported as unfiltered
+        let policy = ClearSignPolicy::Strict {
+            reject_unfiltered: true,
+        };
+        let fields =
+            build_clear_sign_fields(mail_ui_fields(&schema, &data), &filters, policy).unwrap();
+        let labels: Vec<_> = fields.iter().map(|f| f.label.as_ref()).collect();
+        assert_eq!(labels, ["From", "Message"]);
+
+        // a discarded filter path is not a field filter and hides nothing
+        filters.insert(
+            "amount".to_string(),
+            Eip712FilterParams {
+                filter_type: Eip712FilterType::DiscardedFilterPath("amount".to_string()),
+                discarded: false,
+            },
+        );
+        let err =
+            build_clear_sign_fields(mail_ui_fields(&schema, &data), &filters, policy).unwrap_err();
+        assert_eq!(err, "invalid field filter: amount");
+    }
+
+    #[test]
+    fn test_clear_sign_permissive() {
+        let schema = mail_schema();
+        let data = prepare_mail_data();
+        let mut filters = mail_filters();
+        filters.get_mut("to.name").unwrap().discarded = true;
+
+        let ui_fields = mail_ui_fields(&schema, &data);
+        let total = ui_fields.len();
+        let fields =
+            build_clear_sign_fields(ui_fields, &filters, ClearSignPolicy::Permissive).unwrap();
+        assert_eq!(fields.len(), total - 1);
+        assert_eq!(fields[0].label, "From");
+        assert_eq!(fields[1].label, "from.wallets.[]");
+        assert_eq!(
+            fields[1].value,
+            "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826"
+        );
+        assert!(fields.iter().all(|f| f.path != "to.name"));
+    }
+
+    fn amount_join_token(token_index: u8) -> Eip712FilterParams {
+        Eip712FilterParams {
+            filter_type: Eip712FilterType::AmountJoinToken {
+                token_index,
+                signature: vec![],
+            },
+            discarded: false,
+        }
+    }
+
+    #[test]
+    fn test_amount_join_token_indices() {
+        let value = Eip712FilterParams {
+            filter_type: Eip712FilterType::AmountJoinValue {
+                display_name: "Amount".to_string(),
+                token_index: 2,
+                signature: vec![],
+            },
+            discarded: false,
+        };
+        let filters = [
+            amount_join_token(1),
+            raw_field("From"),
+            value,
+            amount_join_token(0),
+            amount_join_token(1),
+        ];
+        assert_eq!(amount_join_token_indices(&filters), vec![0, 1, 2]);
+        assert!(amount_join_token_indices(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_referenced_token_fields() {
+        let schema = mail_schema();
+        let mut filters = mail_filters();
+        filters.insert("from.wallets.[]".to_string(), amount_join_token(0));
+        filters.insert("to.wallets.[]".to_string(), amount_join_token(1));
+        // not an address
+        filters.insert("contents".to_string(), amount_join_token(2));
+        filters.insert("to.unknown".to_string(), amount_join_token(3));
+
+        assert_eq!(
+            referenced_token_fields(&filters, &schema),
+            vec!["from.wallets.[]".to_string(), "to.wallets.[]".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_filter_bundle_versions() {
+        // the same message info and raw field in both layouts
+        let mut legacy_bytes = vec![FILTER_VERSION_LEGACY, TAG_MESSAGE_INFO, 4];
+        legacy_bytes.extend_from_slice(b"Mail");
+        legacy_bytes.extend_from_slice(&[1, 1, 0xaa, TAG_RAW_FIELD, 4]);
+        legacy_bytes.extend_from_slice(b"From");
+        legacy_bytes.extend_from_slice(&[1, 0xcc]);
+
+        let mut current = vec![FILTER_VERSION_CURRENT, TAG_MESSAGE_INFO, 0, 4];
+        current.extend_from_slice(b"Mail");
+        current.extend_from_slice(&[1, 1, 0xaa, TAG_RAW_FIELD, 0, 4]);
+        current.extend_from_slice(b"From");
+        current.extend_from_slice(&[1, 0xcc]);
+
+        let legacy = FilterBundle::from_versioned_bytes(&legacy_bytes).unwrap();
+        let current = FilterBundle::from_versioned_bytes(&current).unwrap();
+        // both layouts round-trip with their version prefix
+        for bundle in [&legacy, &current] {
+            let bytes = bundle.to_versioned_bytes().unwrap();
+            assert_eq!(&FilterBundle::from_versioned_bytes(&bytes).unwrap(), bundle);
+        }
+        assert_eq!(legacy.to_versioned_bytes().unwrap(), legacy_bytes);
+        assert_eq!(legacy.version, FILTER_VERSION_LEGACY);
+        assert_eq!(current.version, FILTER_VERSION_CURRENT);
+        assert_eq!(legacy.message_info, current.message_info);
+        assert_eq!(legacy.filters, current.filters);
+        assert_eq!(legacy.filters[0], raw_field_with_sig("From", vec![0xcc]));
+
+        // the unversioned form is the current layout
+        assert_eq!(
+            FilterBundle::from_bytes(&bundle_bytes()).unwrap().version,
+            FILTER_VERSION_CURRENT
+        );
+
+        let mut unknown = bundle_bytes();
+        unknown.insert(0, 7);
+        assert_eq!(
+            FilterBundle::from_versioned_bytes(&unknown),
+            Err("unknown filter version")
+        );
+        // amount-join filters do not exist in the legacy layout
+        assert_eq!(
+            Eip712FilterParams::from_bytes_with_version(
+                &[TAG_AMOUNT_JOIN_TOKEN, 0, 1, 0xdd],
+                FILTER_VERSION_LEGACY
+            ),
+            Err("unknown filter tag")
+        );
+    }
+
+    fn trusted_name_filter(name_types: Vec<u8>, name_sources: Vec<u8>) -> Eip712FilterType {
+        Eip712FilterType::TrustedName {
+            display_name: "To".to_string(),
+            name_types,
+            name_sources,
+            signature: vec![0xee],
+        }
+    }
+
+    #[test]
+    fn test_trusted_name_parse() {
+        let trusted_name =
+            TrustedName::parse(&trusted_name_filter(vec![1, 4], vec![0, 2])).unwrap();
+        assert_eq!(
+            trusted_name.name_types,
+            vec![TrustedNameType::Account, TrustedNameType::Token]
+        );
+        assert_eq!(
+            trusted_name.name_sources,
+            vec![TrustedNameSource::LocalAddressBook, TrustedNameSource::Ens]
+        );
+
+        assert_eq!(
+            TrustedName::parse(&trusted_name_filter(vec![1, 7], vec![0])).unwrap_err(),
+            "unknown name type: 7"
+        );
+        assert_eq!(
+            TrustedName::parse(&trusted_name_filter(vec![1], vec![0x10])).unwrap_err(),
+            "unknown name source: 16"
+        );
+        assert!(TrustedName::parse(&Eip712FilterType::Activation).is_err());
+    }
+
+    #[test]
+    fn test_trusted_name_accept() {
+        let trusted_name = TrustedName::parse(&trusted_name_filter(vec![1], vec![2])).unwrap();
+        let mut resolution = TrustedNameResolution {
+            name: "vitalik.eth".to_string(),
+            name_type: TrustedNameType::Account,
+            source: TrustedNameSource::Ens,
+        };
+        assert_eq!(trusted_name.accept(&resolution), Some("vitalik.eth"));
+
+        resolution.source = TrustedNameSource::Dns;
+        assert_eq!(trusted_name.accept(&resolution), None);
+        resolution.source = TrustedNameSource::Ens;
+        resolution.name_type = TrustedNameType::Contract;
+        assert_eq!(trusted_name.accept(&resolution), None);
+    }
+
+    // signs a filter with its path, the message info with an empty path
+    struct StubVerifier;
+
+    impl FilterVerifier for StubVerifier {
+        fn verify(&self, path: &str, filter: &Eip712FilterType) -> bool {
+            let signature = match filter {
+                Eip712FilterType::MessageInfo { signature, .. }
+                | Eip712FilterType::TrustedName { signature, .. }
+                | Eip712FilterType::DateTime { signature, .. }
+                | Eip712FilterType::AmountJoinToken { signature, .. }
+                | Eip712FilterType::AmountJoinValue { signature, .. }
+                | Eip712FilterType::RawField { signature, .. } => signature,
+                _ => return false,
+            };
+            *signature == stub_signature(path)
+        }
+    }
+
+    fn stub_signature(path: &str) -> Vec<u8> {
+        [b"sig:", path.as_bytes()].concat()
+    }
+
+    struct StubResolver;
+
+    impl TrustedNameResolver for StubResolver {
+        fn resolve(&self, addr: &[u8; 20]) -> Option<TrustedNameResolution> {
+            let source = match addr[0] {
+                0xbb => TrustedNameSource::LocalAddressBook,
+                0xb0 => TrustedNameSource::Ens,
+                _ => return None,
+            };
+            Some(TrustedNameResolution {
+                name: "bob.eth".to_string(),
+                name_type: TrustedNameType::Wallet,
+                source,
+            })
+        }
+    }
+
+    fn push_len_prefixed(bytes: &mut Vec<u8>, data: &[u8]) {
+        bytes.push(data.len() as u8);
+        bytes.extend_from_slice(data);
+    }
+
+    // offset of the message info filters count in the signed mail bundle
+    const MAIL_BUNDLE_COUNT_INDEX: usize = 7;
+
+    // the mail filters in the current layout, with their paths
+    fn signed_mail_bundle() -> (Vec<u8>, Vec<String>) {
+        let paths = [
+            "from.name",
+            "from.wallets.[]",
+            "to.wallets.[]",
+            "contents",
+            "timestamp",
+            "amount",
+        ];
+        let mut bytes = vec![TAG_MESSAGE_INFO, 0];
+        push_len_prefixed(&mut bytes, b"Mail");
+        bytes.push(paths.len() as u8);
+        push_len_prefixed(&mut bytes, &stub_signature(""));
+
+        bytes.extend_from_slice(&[TAG_RAW_FIELD, 0]);
+        push_len_prefixed(&mut bytes, b"From");
+        push_len_prefixed(&mut bytes, &stub_signature(paths[0]));
+
+        bytes.extend_from_slice(&[TAG_AMOUNT_JOIN_TOKEN, 0, 0]);
+        push_len_prefixed(&mut bytes, &stub_signature(paths[1]));
+
+        bytes.extend_from_slice(&[TAG_TRUSTED_NAME, 0]);
+        push_len_prefixed(&mut bytes, b"To");
+        push_len_prefixed(&mut bytes, &[TrustedNameType::Wallet as u8]);
+        push_len_prefixed(&mut bytes, &[TrustedNameSource::LocalAddressBook as u8]);
+        push_len_prefixed(&mut bytes, &stub_signature(paths[2]));
+
+        bytes.extend_from_slice(&[TAG_RAW_FIELD, 0]);
+        push_len_prefixed(&mut bytes, b"Message");
+        push_len_prefixed(&mut bytes, &stub_signature(paths[3]));
+
+        bytes.extend_from_slice(&[TAG_DATE_TIME, 0]);
+        push_len_prefixed(&mut bytes, b"Sent");
+        push_len_prefixed(&mut bytes, &stub_signature(paths[4]));
+
+        bytes.extend_from_slice(&[TAG_AMOUNT_JOIN_VALUE, 0]);
+        push_len_prefixed(&mut bytes, b"Amount");
+        bytes.push(0);
+        push_len_prefixed(&mut bytes, &stub_signature(paths[5]));
+
+        (bytes, paths.iter().map(|path| path.to_string()).collect())
+    }
+
+    fn clear_sign_options(filter_paths: Vec<String>) -> ClearSignOptions<'static> {
+        let mut tokens = BTreeMap::new();
+        tokens.insert(
+            [0xde, 0xad, 0xbe, 0xef].repeat(5).try_into().unwrap(),
+            TokenInfo {
+                ticker: "USDC".to_string(),
+                decimals: 6,
+            },
+        );
+        ClearSignOptions {
+            verifier: &StubVerifier,
+            filter_paths,
+            tokens,
+            policy: ClearSignPolicy::Strict {
+                reject_unfiltered: false,
+            },
+            ui: UIOptions::default(),
+            filter_order: false,
+        }
+    }
+
+    #[test]
+    fn test_clear_sign_preview() {
+        let struct_defs = prepare_mail_struct_defs();
+        let data = prepare_mail_data();
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let (bytes, paths) = signed_mail_bundle();
+        let bundle = FilterBundle::from_bytes(&bytes).unwrap();
+        let opts = clear_sign_options(paths);
+
+        let preview = clear_sign_preview(
+            &struct_defs,
+            &data,
+            &"Mail".to_string(),
+            typed_data.domain(),
+            &bundle,
+            &StubResolver,
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(preview.title, "Mail");
+        assert_eq!(preview.domain.name.as_deref(), Some("Simple Mail"));
+        let shown: Vec<_> = preview
+            .fields
+            .iter()
+            .map(|f| (f.label.as_ref(), f.value.as_ref()))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                ("From", "Cow"),
+                ("To", "bob.eth"),
+                // the ens source is not allowed by the filter
+                ("To", "0xb0bdabea57b0bdabea57b0bdabea57b0bdabea57"),
+                ("To", "0xb0b0b0b0b0b0b000000000000000000000000000"),
+                ("Message", "Hello, Bob!"),
+                ("Sent", "2021-10-01 07:20:00 UTC"),
+                ("Amount", "1 USDC"),
+            ]
+        );
+    }
+
+    fn signed_filter(path: &str, filter_type: Eip712FilterType) -> Eip712FilterParams {
+        let mut filter = Eip712FilterParams {
+            filter_type,
+            discarded: false,
+        };
+        match &mut filter.filter_type {
+            Eip712FilterType::MessageInfo { signature, .. }
+            | Eip712FilterType::DateTime { signature, .. }
+            | Eip712FilterType::AmountJoinToken { signature, .. }
+            | Eip712FilterType::AmountJoinValue { signature, .. } => {
+                *signature = stub_signature(path)
+            }
+            _ => unreachable!(),
+        }
+        filter
+    }
+
+    // a trade with its token, a signed amount and an expiry, filtered as such, the amount
+    // first when `delta_first` is set
+    fn trade_preview(
+        data: &[Vec<u8>],
+        tokens: BTreeMap<[u8; 20], TokenInfo>,
+        delta_first: bool,
+    ) -> Result<ClearSignPreview, String> {
+        let mut fields = vec![
+            Eip712FieldDefinition::new(Eip712FieldType::Address, "token".to_string()),
+            Eip712FieldDefinition::new(Eip712FieldType::Int(8), "delta".to_string()),
+            Eip712FieldDefinition::new(Eip712FieldType::Uint(32), "expiry".to_string()),
+        ];
+        let mut paths = ["token", "delta", "expiry"];
+        let mut filters = vec![
+            signed_filter(
+                "token",
+                Eip712FilterType::AmountJoinToken {
+                    token_index: 0,
+                    signature: vec![],
+                },
+            ),
+            signed_filter(
+                "delta",
+                Eip712FilterType::AmountJoinValue {
+                    display_name: "Delta".to_string(),
+                    token_index: 0,
+                    signature: vec![],
+                },
+            ),
+            signed_filter(
+                "expiry",
+                Eip712FilterType::DateTime {
+                    display_name: "Expiry".to_string(),
+                    signature: vec![],
+                },
+            ),
+        ];
+        if delta_first {
+            fields.swap(0, 1);
+            paths.swap(0, 1);
+            filters.swap(0, 1);
+        }
+        let mut struct_defs = Eip712StructDefinitions::new();
+        struct_defs.insert("Trade".to_string(), fields);
+        let bundle = FilterBundle {
+            version: FILTER_VERSION_CURRENT,
+            message_info: signed_filter(
+                "",
+                Eip712FilterType::MessageInfo {
+                    display_name: "Trade".to_string(),
+                    filters_count: 3,
+                    signature: vec![],
+                },
+            ),
+            filters,
+        };
+        let mut opts = clear_sign_options(paths.iter().map(|path| path.to_string()).collect());
+        opts.tokens = tokens;
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        clear_sign_preview(
+            &struct_defs,
+            data,
+            &"Trade".to_string(),
+            typed_data.domain(),
+            &bundle,
+            &StubResolver,
+            &opts,
+        )
+    }
+
+    #[test]
+    fn test_clear_sign_preview_amount_token() {
+        let usdc = [0xde, 0xad, 0xbe, 0xef].repeat(5);
+        let tokens = clear_sign_options(vec![]).tokens;
+        let data = vec![
+            usdc.clone(),
+            (-1_500_000i64).to_be_bytes().to_vec(),
+            vec![0x61, 0x56, 0xb6, 0xa0],
+        ];
+
+        // the amount keeps the sign and width of its field, it may come before its token
+        let mut delta_first = data.clone();
+        delta_first.swap(0, 1);
+        for (data, delta_first) in [(&data, false), (&delta_first, true)] {
+            let preview = trade_preview(data, tokens.clone(), delta_first).unwrap();
+            let shown: Vec<_> = preview
+                .fields
+                .iter()
+                .map(|f| (f.label.as_ref(), f.value.as_ref()))
+                .collect();
+            assert_eq!(
+                shown,
+                [
+                    ("Delta", "-1.5 USDC"),
+                    ("Expiry", "2021-10-01 07:20:00 UTC")
+                ]
+            );
+        }
+
+        // the token is found by the address in the message, not by its index
+        let mut other = data.clone();
+        other[0] = vec![0xcc; 20];
+        assert_eq!(
+            trade_preview(&other, tokens.clone(), false).unwrap_err(),
+            "unknown token 0xcccccccccccccccccccccccccccccccccccccccc"
+        );
+        assert_eq!(
+            trade_preview(&data, BTreeMap::new(), false).unwrap_err(),
+            "unknown token 0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef"
+        );
+
+        // a value which can not be formatted is not shown raw under its label
+        let mut late = data.clone();
+        late[2] = vec![0x01; 9];
+        assert_eq!(
+            trade_preview(&late, tokens, false).unwrap_err(),
+            "expiry: invalid timestamp"
+        );
+    }
+
+    #[test]
+    fn test_order_ui_fields_by_filters() {
+        let schema = mail_schema();
+        let data = prepare_mail_data();
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields = build_ui_fields(&schema, &mut ref_data, "").unwrap();
+
+        // the filters list contents before to.wallets, the reverse of the schema order
+        let paths = ["contents".to_string(), "to.wallets.[]".to_string()];
+        let count = ui_fields.len();
+        let ordered = order_ui_fields_by_filters(ui_fields, &schema, &paths).unwrap();
+        let ordered_paths: Vec<_> = ordered.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            ordered_paths[..5],
+            [
+                "contents",
+                "to.wallets.[]",
+                "to.wallets.[]",
+                "to.wallets.[]",
+                "from.name"
+            ]
+        );
+        assert_eq!(
+            ordered[1].value,
+            "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+        );
+        assert_eq!(ordered.len(), count);
+
+        let err =
+            order_ui_fields_by_filters(ordered, &schema, &["to.email".to_string()]).unwrap_err();
+        assert_eq!(err, "unknown filter path: to.email");
+
+        // the preview follows the filter order when asked
+        let struct_defs = prepare_mail_struct_defs();
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let (bytes, paths) = signed_mail_bundle();
+        let bundle = FilterBundle::from_bytes(&bytes).unwrap();
+        let mut opts = clear_sign_options(paths);
+        opts.filter_order = true;
+        let preview = clear_sign_preview(
+            &struct_defs,
+            &data,
+            &"Mail".to_string(),
+            typed_data.domain(),
+            &bundle,
+            &StubResolver,
+            &opts,
+        )
+        .unwrap();
+        let labels: Vec<_> = preview.fields.iter().map(|f| f.label.as_ref()).collect();
+        assert_eq!(
+            labels,
+            ["From", "To", "To", "To", "Message", "Sent", "Amount"]
+        );
+    }
+
+    #[test]
+    fn test_blind_sign_summary() {
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let summary = blind_sign_summary(
+            &prepare_mail_struct_defs(),
+            &prepare_mail_data(),
+            &"Mail".to_string(),
+            typed_data.domain(),
+        )
+        .unwrap();
+        assert_eq!(
+            summary.hash,
+            format!("{}", typed_data.eip712_signing_hash().unwrap())
+        );
+        assert_eq!(summary.primary_type, "Mail");
+        assert_eq!(summary.domain.name.as_deref(), Some("Simple Mail"));
+        assert_eq!(summary.domain.network, Some("Ethereum"));
+
+        assert!(
+            blind_sign_summary(
+                &prepare_mail_struct_defs(),
+                &prepare_mail_data()[..5],
+                &"Mail".to_string(),
+                typed_data.domain(),
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_clear_sign_preview_discarded() {
+        let struct_defs = prepare_mail_struct_defs();
+        let data = prepare_mail_data();
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let (mut bytes, mut paths) = signed_mail_bundle();
+
+        // a signed discarded filter hides payback, its path named by a discarded filter path
+        bytes.extend_from_slice(&[TAG_DISCARDED_FILTER_PATH, 0]);
+        push_len_prefixed(&mut bytes, b"payback");
+        paths.push(String::new());
+        bytes.extend_from_slice(&[TAG_RAW_FIELD, 1]);
+        push_len_prefixed(&mut bytes, b"Payback");
+        push_len_prefixed(&mut bytes, &stub_signature("payback"));
+        paths.push(String::new());
+        // the discarded filter is counted, not its path
+        bytes[MAIL_BUNDLE_COUNT_INDEX] = 7;
+
+        let bundle = FilterBundle::from_bytes(&bytes).unwrap();
+        assert_eq!(bundle.filters.len(), 8);
+        assert_eq!(bundle.filter_count(), 7);
+
+        let mut opts = clear_sign_options(paths);
+        opts.policy = ClearSignPolicy::Strict {
+            reject_unfiltered: true,
+        };
+        // to.name is the only leaf left unfiltered
+        let preview = |opts: &ClearSignOptions| {
+            clear_sign_preview(
+                &struct_defs,
+                &data,
+                &"Mail".to_string(),
+                typed_data.domain(),
+                &bundle,
+                &StubResolver,
+                opts,
+            )
+        };
+        assert_eq!(preview(&opts).unwrap_err(), "unfiltered field: to.name");
+
+        opts.policy = ClearSignPolicy::Strict {
+            reject_unfiltered: false,
+        };
+        let labels: Vec<_> = preview(&opts)
+            .unwrap()
+            .fields
+            .iter()
+            .map(|f| f.label.to_string())
+            .collect();
+        assert_eq!(
+            labels,
+            ["From", "To", "To", "To", "Message", "Sent", "Amount"]
+        );
+    }
+
+    #[test]
+    fn test_clear_sign_preview_injected_discard() {
+        let struct_defs = prepare_mail_struct_defs();
+        let data = prepare_mail_data();
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let (bytes, paths) = signed_mail_bundle();
+        let marker = Eip712FilterParams {
+            filter_type: Eip712FilterType::DiscardedFilterPath("amount".to_string()),
+            discarded: false,
+        };
+        let preview = |filters: Vec<Eip712FilterParams>, count: u8| {
+            let mut bundle = FilterBundle::from_bytes(&bytes).unwrap();
+            if let Eip712FilterType::MessageInfo { filters_count, .. } =
+                &mut bundle.message_info.filter_type
+            {
+                *filters_count = count;
+            }
+            bundle.filters.extend(filters);
+            let mut filter_paths = paths.clone();
+            filter_paths.resize(bundle.filters.len(), String::new());
+            clear_sign_preview(
+                &struct_defs,
+                &data,
+                &"Mail".to_string(),
+                typed_data.domain(),
+                &bundle,
+                &StubResolver,
+                &clear_sign_options(filter_paths),
+            )
+        };
+
+        // an unsigned discarded filter path alone does not decode, nor hide the signed amount
+        let mut injected = bytes.clone();
+        injected.extend_from_slice(&[TAG_DISCARDED_FILTER_PATH, 0]);
+        push_len_prefixed(&mut injected, b"amount");
+        assert_eq!(
+            FilterBundle::from_bytes(&injected),
+            Err("extra data after filters")
+        );
+        assert_eq!(
+            preview(vec![marker.clone()], 6).unwrap_err(),
+            "discarded filter path must precede a discarded filter"
+        );
+
+        // the discarded filter it names must be signed for that path
+        let mut unsigned = raw_field_with_sig("Amount", stub_signature("payback"));
+        unsigned.discarded = true;
+        assert_eq!(
+            preview(vec![marker.clone(), unsigned], 7).unwrap_err(),
+            "invalid filter signature: amount"
+        );
+
+        // and a signed discard can not override the signed amount filter
+        let mut signed = raw_field_with_sig("Amount", stub_signature("amount"));
+        signed.discarded = true;
+        assert_eq!(
+            preview(vec![marker, signed], 7).unwrap_err(),
+            "duplicate filter path: amount"
+        );
+    }
+
+    #[test]
+    fn test_clear_sign_preview_rejected() {
+        let struct_defs = prepare_mail_struct_defs();
+        let data = prepare_mail_data();
+        let typed_data = get_raw_mail_typed_data().unwrap();
+        let (bytes, paths) = signed_mail_bundle();
+        let bundle = FilterBundle::from_bytes(&bytes).unwrap();
+        let preview = |bundle: &FilterBundle, opts: &ClearSignOptions| {
+            clear_sign_preview(
+                &struct_defs,
+                &data,
+                &"Mail".to_string(),
+                typed_data.domain(),
+                bundle,
+                &StubResolver,
+                opts,
+            )
+            .unwrap_err()
+        };
+
+        // a filter signed for another path
+        let mut swapped = paths.clone();
+        swapped.swap(3, 4);
+        assert_eq!(
+            preview(&bundle, &clear_sign_options(swapped)),
+            "invalid filter signature: timestamp"
+        );
+
+        let opts = clear_sign_options(paths[1..].to_vec());
+        assert_eq!(preview(&bundle, &opts), "filter paths count mismatch");
+
+        let mut truncated = bundle.clone();
+        truncated.filters.pop();
+        let opts = clear_sign_options(paths.clone());
+        assert_eq!(preview(&truncated, &opts), "filters count mismatch");
+
+        let mut unsigned = bundle.clone();
+        if let Eip712FilterType::MessageInfo { signature, .. } =
+            &mut unsigned.message_info.filter_type
+        {
+            signature.clear();
+        }
+        assert_eq!(preview(&unsigned, &opts), "invalid message info signature");
+    }
+}
@@ -0,0 +1,504 @@
+use crate::utils::*;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use alloy_primitives::{I256, U256};
+
+// shift an unsigned integer right by `decimals` digits, trailing zeros of the fraction are trimmed
+pub fn shift_decimals(value: U256, decimals: u8) -> String {
+    let digits = value.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+
+    let (int_part, frac_part) = if digits.len() > decimals {
+        let (i, f) = digits.split_at(digits.len() - decimals);
+        (i.to_string(), f.to_string())
+    } else {
+        let mut frac = "0".repeat(decimals - digits.len());
+        frac.push_str(&digits);
+        ("0".to_string(), frac)
+    };
+
+    let frac_part = frac_part.trim_end_matches('0');
+    if frac_part.is_empty() {
+        int_part
+    } else {
+        format!("{}.{}", int_part, frac_part)
+    }
+}
+
+/// A signed value shifted right by `decimals` digits, eg: -123456789012 at 8 decimals ->
+/// -1234.56789012, trailing zeros of the fraction are trimmed
+pub fn format_fixed_point(value: I256, decimals: u8) -> String {
+    // unsigned_abs does not overflow on I256::MIN
+    let abs = shift_decimals(value.unsigned_abs(), decimals);
+    if value.is_negative() {
+        format!("-{}", abs)
+    } else {
+        abs
+    }
+}
+
+/// A formatted amount, `exact` is false when precision was dropped so the host can show a `≈`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AmountDisplay {
+    pub text: String,
+    pub exact: bool,
+}
+
+/// Format a raw amount-join value shifted by the token decimals and followed by the ticker
+///
+/// `size` and `signed` come from the field type, a signed value is rendered with a leading `-`.
+/// Shifting decimals never rounds so the result is always exact
+pub fn format_amount(
+    raw: &[u8],
+    size: usize,
+    signed: bool,
+    decimals: u8,
+    ticker: &str,
+) -> Result<AmountDisplay, String> {
+    format_amount_truncated(raw, size, signed, decimals, ticker, u8::MAX)
+}
+
+/// Like `format_amount` with at most `max_fraction_digits` digits after the point for a compact
+/// display, the dropped digits are truncated toward zero and the result is then not exact,
+/// eg: 1.23456 USDC at 2 digits -> 1.23 USDC
+pub fn format_amount_truncated(
+    raw: &[u8],
+    size: usize,
+    signed: bool,
+    decimals: u8,
+    ticker: &str,
+    max_fraction_digits: u8,
+) -> Result<AmountDisplay, String> {
+    if raw.len() > size {
+        return Err("invalid amount len".to_string());
+    }
+
+    let mut amount = if signed {
+        let val = parse_i256(raw, size).map_err(|err| err.to_string())?;
+        format_fixed_point(val, decimals)
+    } else {
+        let val = parse_u256(raw).map_err(|err| err.to_string())?;
+        shift_decimals(val, decimals)
+    };
+    // the fraction has no trailing zero, so any dropped digit loses precision
+    let mut exact = true;
+    if let Some(point) = amount.find('.') {
+        let keep = point + 1 + max_fraction_digits as usize;
+        if amount.len() > keep {
+            amount.truncate(keep);
+            // eg: 1.0 at one digit from 1.05, or -0 from -0.001
+            let len = amount.trim_end_matches('0').trim_end_matches('.').len();
+            amount.truncate(len);
+            exact = false;
+        }
+    }
+    if amount == "-0" {
+        amount = "0".to_string();
+    }
+    if !ticker.is_empty() {
+        amount.push(' ');
+        amount.push_str(ticker);
+    }
+    Ok(AmountDisplay {
+        text: amount,
+        exact,
+    })
+}
+
+/// Format an integer basis points value as a percentage with two decimals, eg: 30 -> 0.30%
+pub fn format_basis_points(raw: &[u8]) -> Result<String, String> {
+    let val = parse_u256(raw).map_err(|err| err.to_string())?;
+    let hundred = U256::from(100u8);
+    let int_part = val / hundred;
+    let frac_part = (val % hundred).to::<u8>();
+    Ok(format!("{}.{:02}%", int_part, frac_part))
+}
+
+// year, month and day of a count of days since 1970-01-01, from Howard Hinnant's civil_from_days
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Format a unix timestamp in seconds as a UTC date time, eg: 2021-10-01 07:20:00 UTC
+pub fn format_datetime(raw: &[u8]) -> Result<String, String> {
+    let val = parse_u256(raw).map_err(|err| err.to_string())?;
+    let secs: u64 = val
+        .try_into()
+        .map_err(|_| "invalid timestamp".to_string())?;
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let time = secs % 86_400;
+    Ok(format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    ))
+}
+
+/// Middle-ellipsis form of a hex address keeping `lead` leading and `tail` trailing characters,
+/// eg: 0x1234…abcd, the address is returned as is if it is not longer than the kept characters
+pub fn shorten_address(addr_hex: &str, lead: usize, tail: usize) -> String {
+    let len = addr_hex.chars().count();
+    if lead + tail >= len {
+        return addr_hex.to_string();
+    }
+    let mut short: String = addr_hex.chars().take(lead).collect();
+    short.push('\u{2026}');
+    short.extend(addr_hex.chars().skip(len - tail));
+    short
+}
+
+/// Name of a well known chain id, eg: 1 -> Ethereum
+pub fn network_name(chain_id: U256) -> Option<&'static str> {
+    let chain_id: u64 = chain_id.try_into().ok()?;
+    let name = match chain_id {
+        1 => "Ethereum",
+        10 => "Optimism",
+        56 => "BNB Smart Chain",
+        137 => "Polygon",
+        1029 => "Conflux",
+        1030 => "Conflux eSpace",
+        8453 => "Base",
+        42161 => "Arbitrum One",
+        _ => return None,
+    };
+    Some(name)
+}
+
+const CIP37_ALPHABET: &[u8; 32] = b"abcdefghjkmnprstuvwxyz0123456789";
+
+// the 40 bits BCH checksum of CIP-37
+fn cip37_polymod(words: impl Iterator<Item = u8>) -> u64 {
+    let mut c = 1u64;
+    for d in words {
+        let c0 = (c >> 35) as u8;
+        c = ((c & 0x07_ffff_ffff) << 5) ^ d as u64;
+        for (bit, generator) in [
+            0x98_f2bc_8e61,
+            0x79_b76d_99e2,
+            0xf3_3e5f_b3c4,
+            0xae_2eab_e2a8,
+            0x1e_4f43_e470,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if c0 & (1 << bit) != 0 {
+                c ^= generator;
+            }
+        }
+    }
+    c ^ 1
+}
+
+// the type of a Conflux address is given by its leading 4 bits
+fn cip37_address_type(addr: &[u8; 20]) -> &'static str {
+    match addr[0] >> 4 {
+        _ if addr.iter().all(|b| *b == 0) => "null",
+        0x0 => "builtin",
+        0x1 => "user",
+        0x8 => "contract",
+        _ => "unknown",
+    }
+}
+
+/// CIP-37 base32 form of a Conflux address, eg: cfx:acc7uawf5ubtnmezvhu9dhc6sghea0403y2dgpyfjp
+/// for a contract on mainnet. The verbose form is uppercase and names the address type taken from
+/// the leading 4 bits, eg: CFX:TYPE.CONTRACT:ACC7…, a user address being TYPE.USER
+pub fn format_cip37_address(addr: &[u8; 20], network_id: u32, verbose: bool) -> String {
+    let prefix = match network_id {
+        1029 => "cfx".to_string(),
+        1 => "cfxtest".to_string(),
+        _ => format!("net{}", network_id),
+    };
+
+    // version byte then the address, split into 5 bits words
+    let mut words = Vec::with_capacity(34);
+    let (mut acc, mut bits) = (0u16, 0);
+    for b in core::iter::once(0u8).chain(addr.iter().copied()) {
+        acc = (acc << 8) | b as u16;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            words.push(((acc >> bits) & 0x1f) as u8);
+        }
+    }
+    if bits > 0 {
+        words.push(((acc << (5 - bits)) & 0x1f) as u8);
+    }
+
+    let checksum = cip37_polymod(
+        prefix
+            .bytes()
+            .map(|c| c & 0x1f)
+            .chain(core::iter::once(0))
+            .chain(words.iter().copied())
+            .chain([0u8; 8]),
+    );
+    words.extend((0..8).rev().map(|i| ((checksum >> (i * 5)) & 0x1f) as u8));
+
+    let mut out = prefix;
+    if verbose {
+        out.push_str(":type.");
+        out.push_str(cip37_address_type(addr));
+    }
+    out.push(':');
+    out.extend(words.iter().map(|w| CIP37_ALPHABET[*w as usize] as char));
+    if verbose {
+        out.make_ascii_uppercase();
+    }
+    out
+}
+
+const HEX_DIGITS_LOWER: &[u8; 16] = b"0123456789abcdef";
+const HEX_DIGITS_UPPER: &[u8; 16] = b"0123456789ABCDEF";
+
+fn write_hex_digits(out: &mut String, bytes: &[u8], digits: &[u8; 16]) {
+    out.reserve(2 + bytes.len() * 2);
+    out.push_str("0x");
+    for b in bytes {
+        out.push(digits[(b >> 4) as usize] as char);
+        out.push(digits[(b & 0x0f) as usize] as char);
+    }
+}
+
+/// Append `0x` and the lowercase hex of `bytes` to `out` without an intermediate allocation
+pub fn write_hex_prefixed(out: &mut String, bytes: &[u8]) {
+    write_hex_digits(out, bytes, HEX_DIGITS_LOWER);
+}
+
+/// Same as `write_hex_prefixed` with uppercase hex digits, the `0x` prefix stays lowercase
+pub fn write_hex_prefixed_upper(out: &mut String, bytes: &[u8]) {
+    write_hex_digits(out, bytes, HEX_DIGITS_UPPER);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy_primitives::hex;
+
+    #[test]
+    fn test_shift_decimals() {
+        assert_eq!(shift_decimals(U256::from(1500u64), 3), "1.5");
+        assert_eq!(shift_decimals(U256::from(15u64), 3), "0.015");
+        assert_eq!(shift_decimals(U256::from(2000u64), 3), "2");
+        assert_eq!(shift_decimals(U256::ZERO, 18), "0");
+        assert_eq!(shift_decimals(U256::from(42u64), 0), "42");
+    }
+
+    #[test]
+    fn test_format_fixed_point() {
+        let val = |v: i128| I256::try_from(v).unwrap();
+        assert_eq!(format_fixed_point(val(123_456_789_012), 8), "1234.56789012");
+        assert_eq!(
+            format_fixed_point(val(-123_456_789_012), 8),
+            "-1234.56789012"
+        );
+        assert_eq!(format_fixed_point(val(-5_000_000), 8), "-0.05");
+        assert_eq!(
+            format_fixed_point(val(1_250_000_000_000_000_000), 18),
+            "1.25"
+        );
+        assert_eq!(
+            format_fixed_point(val(-1_000_000_000_000_000_001), 18),
+            "-1.000000000000000001"
+        );
+        assert_eq!(format_fixed_point(I256::ZERO, 18), "0");
+        assert_eq!(
+            format_fixed_point(I256::MIN, 18),
+            format!("-{}", shift_decimals(I256::MIN.unsigned_abs(), 18))
+        );
+    }
+
+    #[test]
+    fn test_format_basis_points() {
+        assert_eq!(format_basis_points(&[30]).unwrap(), "0.30%");
+        assert_eq!(
+            format_basis_points(&hex::decode("3039").unwrap()).unwrap(),
+            "123.45%"
+        );
+        assert_eq!(
+            format_basis_points(&hex::decode("2710").unwrap()).unwrap(),
+            "100.00%"
+        );
+        assert_eq!(format_basis_points(&[]).unwrap(), "0.00%");
+    }
+
+    #[test]
+    fn test_format_datetime() {
+        assert_eq!(format_datetime(&[]).unwrap(), "1970-01-01 00:00:00 UTC");
+        assert_eq!(
+            format_datetime(&hex::decode("6156b6a0").unwrap()).unwrap(),
+            "2021-10-01 07:20:00 UTC"
+        );
+        // leap day and the end of a year
+        assert_eq!(
+            format_datetime(&hex::decode("5e59f4ff").unwrap()).unwrap(),
+            "2020-02-29 05:22:07 UTC"
+        );
+        assert_eq!(
+            format_datetime(&hex::decode("386d437f").unwrap()).unwrap(),
+            "1999-12-31 23:59:59 UTC"
+        );
+        assert_eq!(
+            format_datetime(&U256::MAX.to_be_bytes::<32>()).unwrap_err(),
+            "invalid timestamp"
+        );
+    }
+
+    #[test]
+    fn test_format_cip37_address() {
+        let contract: [u8; 20] = hex::decode("85d80245dc02f5a89589e1f19c5c718e405b56cd")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            format_cip37_address(&contract, 1029, false),
+            "cfx:acc7uawf5ubtnmezvhu9dhc6sghea0403y2dgpyfjp"
+        );
+        assert_eq!(
+            format_cip37_address(&contract, 1029, true),
+            "CFX:TYPE.CONTRACT:ACC7UAWF5UBTNMEZVHU9DHC6SGHEA0403Y2DGPYFJP"
+        );
+        assert_eq!(
+            format_cip37_address(&contract, 1, false),
+            "cfxtest:acc7uawf5ubtnmezvhu9dhc6sghea0403ywjz6wtpg"
+        );
+
+        let user: [u8; 20] = hex::decode("1a2f80341409639ea6a35bbcab8299066109aa55")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            format_cip37_address(&user, 1029, false),
+            "cfx:aarc9abycue0hhzgyrr53m6cxedgccrmmyybjgh4xg"
+        );
+        assert_eq!(
+            format_cip37_address(&user, 1, true),
+            "CFXTEST:TYPE.USER:AARC9ABYCUE0HHZGYRR53M6CXEDGCCRMMY8M50BU1P"
+        );
+        assert!(format_cip37_address(&user, 10000, false).starts_with("net10000:aarc9abycue0"));
+        assert!(format_cip37_address(&[0; 20], 1029, true).starts_with("CFX:TYPE.NULL:"));
+    }
+
+    #[test]
+    fn test_shorten_address() {
+        let addr = "0x1234567890abcdef1234567890abcdef1234abcd";
+        assert_eq!(shorten_address(addr, 6, 4), "0x1234\u{2026}abcd");
+        assert_eq!(shorten_address(addr, 0, 4), "\u{2026}abcd");
+        assert_eq!(shorten_address(addr, 30, 12), addr);
+        assert_eq!(shorten_address("0x12", 6, 4), "0x12");
+    }
+
+    #[test]
+    fn test_network_name() {
+        assert_eq!(network_name(U256::from(1)), Some("Ethereum"));
+        assert_eq!(network_name(U256::from(1030)), Some("Conflux eSpace"));
+        assert_eq!(network_name(U256::from(12345)), None);
+        assert_eq!(network_name(U256::MAX), None);
+    }
+
+    #[test]
+    fn test_write_hex_prefixed() {
+        let addr = hex::decode("cd2a3d9f938e13cd947ec05abc7fe734df8dd826").unwrap();
+        let mut out = String::new();
+        write_hex_prefixed(&mut out, &addr);
+        assert_eq!(out, format!("0x{}", hex::encode(&addr)));
+
+        let mut out = String::new();
+        write_hex_prefixed_upper(&mut out, &addr);
+        assert_eq!(out, format!("0x{}", hex::encode_upper(&addr)));
+
+        // appends to the existing content
+        let mut out = String::from("to: ");
+        write_hex_prefixed(&mut out, &[]);
+        assert_eq!(out, "to: 0x");
+    }
+
+    #[test]
+    fn test_format_amount_unsigned() {
+        let raw = hex::decode("14d1120d7b160000").unwrap(); // 1.5e18
+        let amount = format_amount(&raw, 32, false, 18, "DAI").unwrap();
+        assert_eq!(amount.text, "1.5 DAI");
+    }
+
+    #[test]
+    fn test_format_amount_negative() {
+        let val = I256::try_from(-1_500_000_000_000_000_000i128).unwrap();
+        let raw = val.to_be_bytes::<32>();
+        let amount = format_amount(&raw, 32, true, 18, "DAI").unwrap();
+        assert_eq!(amount.text, "-1.5 DAI");
+
+        // short sign-extended int16
+        let raw = hex::decode("fc18").unwrap(); // -1000
+        let amount = format_amount(&raw, 2, true, 3, "").unwrap();
+        assert_eq!(amount.text, "-1");
+    }
+
+    #[test]
+    fn test_format_amount_i256_min() {
+        let raw = I256::MIN.to_be_bytes::<32>();
+        let amount = format_amount(&raw, 32, true, 0, "DAI").unwrap();
+        assert_eq!(amount.text, format!("-{} DAI", I256::MIN.unsigned_abs()));
+    }
+
+    #[test]
+    fn test_format_amount_exact() {
+        // decimal shifting drops no digit, even for tiny or huge values
+        let cases = [
+            (U256::from(1u8), 18),
+            (U256::from(123_456_789u64), 6),
+            (U256::MAX, 18),
+            (U256::MAX, 77),
+            (U256::ZERO, 255),
+        ];
+        for (val, decimals) in cases {
+            let amount = format_amount(&val.to_be_bytes::<32>(), 32, false, decimals, "").unwrap();
+            assert!(amount.exact);
+            let digits: String = amount.text.chars().filter(|c| *c != '.').collect();
+            assert_eq!(
+                digits.trim_start_matches('0'),
+                val.to_string().trim_start_matches('0')
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_amount_truncated() {
+        let format = |raw: &[u8], signed, max_digits| {
+            let amount = format_amount_truncated(raw, 32, signed, 6, "USDC", max_digits).unwrap();
+            (amount.text, amount.exact)
+        };
+        let raw = U256::from(1_234_560u64).to_be_bytes::<32>();
+        assert_eq!(format(&raw, false, 6), ("1.23456 USDC".to_string(), true));
+        assert_eq!(format(&raw, false, 5), ("1.23456 USDC".to_string(), true));
+        assert_eq!(format(&raw, false, 2), ("1.23 USDC".to_string(), false));
+        assert_eq!(format(&raw, false, 0), ("1 USDC".to_string(), false));
+
+        let raw = I256::try_from(-1_500_000i64).unwrap().to_be_bytes::<32>();
+        assert_eq!(format(&raw, true, 0), ("-1 USDC".to_string(), false));
+        let raw = I256::try_from(-1i64).unwrap().to_be_bytes::<32>();
+        assert_eq!(format(&raw, true, 2), ("0 USDC".to_string(), false));
+        // a whole amount has nothing to drop
+        let raw = U256::from(2_000_000u64).to_be_bytes::<32>();
+        assert_eq!(format(&raw, false, 0), ("2 USDC".to_string(), true));
+    }
+}
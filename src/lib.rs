@@ -4,6 +4,7 @@ extern crate alloc;
 mod consts;
 pub mod eip712;
 pub mod eip712_filter;
+pub mod format;
 pub mod parser;
 pub(crate) mod test_utils;
 pub mod types;
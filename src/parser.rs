@@ -1,15 +1,22 @@
-use crate::types::Eip712StructDefinitions;
+use crate::Eip712Domain;
+use crate::format::{
+    format_basis_points, format_cip37_address, format_fixed_point, network_name, shift_decimals,
+    shorten_address, write_hex_prefixed, write_hex_prefixed_upper,
+};
+use crate::types::{Eip712ArrayLevel, Eip712FieldValue, Eip712StructDefinitions};
 use crate::utils::*;
 
 use alloc::{
     borrow::{Cow, ToOwned},
     boxed::Box,
+    collections::{BTreeMap, BTreeSet},
     format,
     string::{String, ToString},
     vec,
     vec::Vec,
 };
-use alloy_primitives::hex;
+use alloy_dyn_abi::DynSolType;
+use alloy_primitives::{Address, B256, U256, hex};
 use serde_json::{Number, Value};
 
 pub enum TypeSchema {
@@ -26,10 +33,24 @@ pub struct Field {
     pub ty: TypeSchema,
 }
 
+// max struct nesting depth, guards against cyclic struct definitions
+pub const MAX_SCHEMA_DEPTH: usize = 32;
+
 pub fn build_schema(
     struct_defs: &Eip712StructDefinitions,
     type_name: &String,
 ) -> Result<TypeSchema, String> {
+    build_schema_at(struct_defs, type_name, 0)
+}
+
+fn build_schema_at(
+    struct_defs: &Eip712StructDefinitions,
+    type_name: &String,
+    depth: usize,
+) -> Result<TypeSchema, String> {
+    if depth > MAX_SCHEMA_DEPTH {
+        return Err(format!("{} nested too deep", type_name));
+    }
     let field_defs = struct_defs.get(type_name).ok_or("build_schema not found")?;
 
     let mut fields = Vec::new();
@@ -39,9 +60,9 @@ pub fn build_schema(
             let custom_type_name = fd
                 .field_type
                 .custom_type_name()
-                .expect("should exist")
+                .ok_or("custom type name lacked")?
                 .to_string();
-            build_schema(struct_defs, &custom_type_name)?
+            build_schema_at(struct_defs, &custom_type_name, depth + 1)?
         } else {
             let (name, size) = fd.primitive_type_string_and_size();
             TypeSchema::Primitive { name, size }
@@ -67,85 +88,367 @@ pub fn build_schema(
     });
 }
 
+/// Borrowed counterpart of `TypeSchema`, names point into the struct definitions instead of
+/// being cloned. This saves one `String` per struct, field and primitive node of the tree,
+/// eg: 21 allocations for the mail fixture, only the `Box` and `Vec` of the nodes remain
+pub enum TypeSchemaRef<'a> {
+    Primitive {
+        name: &'a str,
+        size: Option<u8>,
+    },
+    Array {
+        item: Box<TypeSchemaRef<'a>>,
+    },
+    Struct {
+        name: &'a str,
+        fields: Vec<FieldRef<'a>>,
+    },
+}
+
+pub struct FieldRef<'a> {
+    pub name: &'a str,
+    pub ty: TypeSchemaRef<'a>,
+}
+
+pub fn build_schema_ref<'a>(
+    struct_defs: &'a Eip712StructDefinitions,
+    type_name: &str,
+) -> Result<TypeSchemaRef<'a>, String> {
+    build_schema_ref_at(struct_defs, type_name, 0)
+}
+
+fn build_schema_ref_at<'a>(
+    struct_defs: &'a Eip712StructDefinitions,
+    type_name: &str,
+    depth: usize,
+) -> Result<TypeSchemaRef<'a>, String> {
+    if depth > MAX_SCHEMA_DEPTH {
+        return Err(format!("{} nested too deep", type_name));
+    }
+    let (name, field_defs) = struct_defs
+        .get_key_value(type_name)
+        .ok_or("build_schema not found")?;
+
+    let mut fields = Vec::with_capacity(field_defs.len());
+    for fd in field_defs {
+        let (type_name, size) = fd.field_type.type_name_and_size();
+        let mut ty = if fd.is_struct() {
+            build_schema_ref_at(struct_defs, type_name, depth + 1)?
+        } else {
+            TypeSchemaRef::Primitive {
+                name: type_name,
+                size,
+            }
+        };
+        for _ in 0..fd.array_levels.len() {
+            ty = TypeSchemaRef::Array { item: Box::new(ty) }
+        }
+        fields.push(FieldRef { name: &fd.name, ty });
+    }
+
+    Ok(TypeSchemaRef::Struct { name, fields })
+}
+
+/// One node of a schema tree as seen by the encoder
+pub enum SchemaNodeKind<'s, S> {
+    Primitive { name: &'s str, size: Option<u8> },
+    Array { item: &'s S },
+    // the fields are read with `SchemaNode::field`
+    Struct { name: &'s str, field_count: usize },
+}
+
+/// Read access to a schema tree, implemented by the owned and the borrowed schema so
+/// `encode_data` works on both
+pub trait SchemaNode: Sized {
+    fn kind(&self) -> SchemaNodeKind<'_, Self>;
+
+    /// Name and type of the struct field at `index`
+    fn field(&self, index: usize) -> Option<(&str, &Self)>;
+}
+
+impl SchemaNode for TypeSchema {
+    fn kind(&self) -> SchemaNodeKind<'_, Self> {
+        match self {
+            TypeSchema::Primitive { name, size } => SchemaNodeKind::Primitive { name, size: *size },
+            TypeSchema::Array { item } => SchemaNodeKind::Array { item },
+            TypeSchema::Struct { name, fields } => SchemaNodeKind::Struct {
+                name,
+                field_count: fields.len(),
+            },
+        }
+    }
+
+    fn field(&self, index: usize) -> Option<(&str, &Self)> {
+        match self {
+            TypeSchema::Struct { fields, .. } => {
+                fields.get(index).map(|f| (f.name.as_str(), &f.ty))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl SchemaNode for TypeSchemaRef<'_> {
+    fn kind(&self) -> SchemaNodeKind<'_, Self> {
+        match self {
+            TypeSchemaRef::Primitive { name, size } => {
+                SchemaNodeKind::Primitive { name, size: *size }
+            }
+            TypeSchemaRef::Array { item } => SchemaNodeKind::Array { item },
+            TypeSchemaRef::Struct { name, fields } => SchemaNodeKind::Struct {
+                name,
+                field_count: fields.len(),
+            },
+        }
+    }
+
+    fn field(&self, index: usize) -> Option<(&str, &Self)> {
+        match self {
+            TypeSchemaRef::Struct { fields, .. } => fields.get(index).map(|f| (f.name, &f.ty)),
+            _ => None,
+        }
+    }
+}
+
+// read the presence marker of an optional field, 0x01 if the value follows, 0x00 if it is absent
+pub(crate) fn read_presence<T: AsRef<[u8]>>(
+    data: &mut impl Iterator<Item = T>,
+    path: &str,
+) -> Result<bool, String> {
+    let marker = data
+        .next()
+        .ok_or_else(|| at_path(path, "build value data.next failed"))?;
+    match marker.as_ref() {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        _ => Err(at_path(path, "invalid presence marker")),
+    }
+}
+
+// the data items of the zero value of a schema, an absent optional field is encoded and rebuilt
+// as this value, ie: false, 0, the zero address, zeroed bytesN, an empty string, bytes or array.
+// The items have no presence markers, they are read with the default options
+pub(crate) fn zero_data_items<S: SchemaNode>(schema: &S, items: &mut Vec<Vec<u8>>) {
+    match schema.kind() {
+        SchemaNodeKind::Primitive { name, size } => {
+            let item = match (name, size) {
+                ("address", _) => vec![0u8; 20],
+                ("bytes", Some(size)) => vec![0u8; size as usize],
+                ("bytes", None) | ("string", _) => vec![],
+                _ => vec![0u8],
+            };
+            items.push(item);
+        }
+        SchemaNodeKind::Array { .. } => items.push(vec![0u8]),
+        SchemaNodeKind::Struct { field_count, .. } => {
+            for index in 0..field_count {
+                if let Some((_, ty)) = schema.field(index) {
+                    zero_data_items(ty, items);
+                }
+            }
+        }
+    }
+}
+
+// convert a type schema to alloy's DynSolType, structs become tuples
+// struct_defs are used to resolve the fixed size of each array level
+pub fn schema_to_dyn_sol_type(
+    schema: &TypeSchema,
+    struct_defs: &Eip712StructDefinitions,
+) -> Result<DynSolType, String> {
+    schema_to_dyn_sol_type_at(schema, &[], struct_defs, 0)
+}
+
+fn schema_to_dyn_sol_type_at(
+    schema: &TypeSchema,
+    array_levels: &[Eip712ArrayLevel],
+    struct_defs: &Eip712StructDefinitions,
+    depth: usize,
+) -> Result<DynSolType, String> {
+    let res = match schema {
+        TypeSchema::Primitive { name, size } => match (name.as_str(), size) {
+            ("bool", _) => DynSolType::Bool,
+            ("int", Some(s)) => DynSolType::Int(*s as usize * 8),
+            ("uint", Some(s)) => DynSolType::Uint(*s as usize * 8),
+            ("address", _) => DynSolType::Address,
+            ("bytes", Some(s)) => DynSolType::FixedBytes(*s as usize),
+            ("bytes", None) => DynSolType::Bytes,
+            ("string", _) => DynSolType::String,
+            _ => return Err(format!("unsupported primitive type {}", name)),
+        },
+        TypeSchema::Array { item } => {
+            // the last array level is the outermost one, eg: string[][2]
+            let (level, inner_levels) =
+                array_levels.split_last().ok_or("array level info lacked")?;
+            let item = schema_to_dyn_sol_type_at(item, inner_levels, struct_defs, depth)?;
+            match level {
+                Eip712ArrayLevel::Dynamic => DynSolType::Array(Box::new(item)),
+                Eip712ArrayLevel::Fixed(size) => {
+                    DynSolType::FixedArray(Box::new(item), *size as usize)
+                }
+            }
+        }
+        TypeSchema::Struct { name, fields } => {
+            if depth > MAX_SCHEMA_DEPTH {
+                return Err(format!("{} nested too deep", name));
+            }
+            let field_defs = struct_defs.get(name).ok_or("struct defs not found")?;
+            if field_defs.len() != fields.len() {
+                return Err(format!("{} field count not match", name));
+            }
+            let mut types = Vec::new();
+            for (f, fd) in fields.iter().zip(field_defs) {
+                types.push(schema_to_dyn_sol_type_at(
+                    &f.ty,
+                    &fd.array_levels,
+                    struct_defs,
+                    depth + 1,
+                )?);
+            }
+            DynSolType::Tuple(types)
+        }
+    };
+    Ok(res)
+}
+
+/// Limits applied to the values while encoding or building them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Max len of a dynamic `bytes`/`string` value, unlimited if none
+    pub max_dynamic_len: Option<usize>,
+    /// Paths of the `bytes`/`string` leaves supplied as their 32 bytes keccak256 hash
+    pub prehashed: PrehashedPaths,
+    /// Paths of the fields which may be absent from the data stream, their value is preceded by
+    /// a one byte presence marker, 0x01 if it follows and 0x00 if it is absent. An absent field
+    /// is encoded as the zero value of its type
+    pub optional: OptionalPaths,
+}
+
+/// A set of field paths, eg: `attachments.[]`. Paths are kept in the filter form so the indexed
+/// paths of the encoder and the filter paths of the display match the same entries
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FieldPaths(BTreeSet<String>);
+
+impl FieldPaths {
+    /// Add a path in either form, eg: `attachments[1]` or `attachments.[]`
+    pub fn insert(&mut self, path: &str) -> bool {
+        self.0.insert(filter_path(path))
+    }
+
+    /// Whether `path`, in either form, is in the set
+    pub fn contains(&self, path: &str) -> bool {
+        !self.0.is_empty() && self.0.contains(&filter_path(path))
+    }
+}
+
+/// Paths of `bytes`/`string` leaves supplied as their 32 bytes keccak256 hash
+pub type PrehashedPaths = FieldPaths;
+
+/// Paths of the fields preceded by a presence marker in the data stream
+pub type OptionalPaths = FieldPaths;
+
+impl EncodeOptions {
+    /// Display options sharing the pre-hashed and optional paths of these encode options
+    pub fn ui_options(&self) -> UIOptions {
+        UIOptions {
+            prehashed: self.prehashed.clone(),
+            optional: self.optional.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn check_dynamic_len(&self, len: usize) -> Result<(), String> {
+        match self.max_dynamic_len {
+            Some(max) if len > max => Err(format!("value too large: {} > {}", len, max)),
+            _ => Ok(()),
+        }
+    }
+}
+
 // from type schema and raw data build serde_json::Value
 pub fn build_value(
     schema: &TypeSchema,
     data: &mut impl Iterator<Item = Vec<u8>>,
+) -> Result<Value, String> {
+    build_value_with(schema, data, false, &EncodeOptions::default())
+}
+
+pub fn build_value_with_options(
+    schema: &TypeSchema,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+    opts: &EncodeOptions,
+) -> Result<Value, String> {
+    build_value_with(schema, data, false, opts)
+}
+
+// same as build_value, but every int/uint is rendered as a decimal string, eg: "-256",
+// which is how many dapp encoders send integers
+pub fn build_value_strings(
+    schema: &TypeSchema,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+) -> Result<Value, String> {
+    build_value_with(schema, data, true, &EncodeOptions::default())
+}
+
+/// Reconstruct the whole message as pretty JSON, integers are in the canonical decimal-string form
+#[cfg(feature = "std")]
+pub fn decode_to_json(
+    struct_defs: &Eip712StructDefinitions,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+    primary_type: &String,
+) -> Result<String, String> {
+    let schema = build_schema(struct_defs, primary_type)?;
+    let value = build_value_strings(&schema, data)?;
+    serde_json::to_string_pretty(&value).map_err(|err| err.to_string())
+}
+
+fn build_value_with(
+    schema: &TypeSchema,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+    decimal_strings: bool,
+    opts: &EncodeOptions,
+) -> Result<Value, String> {
+    build_value_at(schema, data, decimal_strings, opts, "")
+}
+
+// errors are prefixed with the path of the failing field, eg: from.wallets[2]: invalid address len
+fn build_value_at(
+    schema: &TypeSchema,
+    data: &mut impl Iterator<Item = Vec<u8>>,
+    decimal_strings: bool,
+    opts: &EncodeOptions,
+    path: &str,
 ) -> Result<Value, String> {
     let res = match schema {
         TypeSchema::Primitive { name, size } => {
-            let raw = data.next().ok_or("build value data.next failed")?;
-            match name.as_str() {
-                "bool" => Value::Bool(raw[0] == 1),
-                "int" => {
-                    let the_size = size.expect("exist") as usize;
-                    if raw.len() > the_size as usize {
-                        return Err("invalid int len".to_string());
-                    }
-                    if the_size <= 16 {
-                        let val = parse_i128(&raw, the_size).map_err(|err| err.to_string())?;
-                        match Number::from_i128(val) {
-                            Some(num) => Value::Number(num),
-                            None => Value::String(format!("{:#x}", val)),
-                        }
-                    } else {
-                        let val = parse_i256(&raw, the_size).map_err(|err| err.to_string())?;
-                        Value::String(val.to_hex_string())
-                    }
-                }
-                "uint" => {
-                    if let Some(s) = size {
-                        if raw.len() > *s as usize {
-                            return Err("invalid uint len".to_string());
-                        }
-                    }
-                    if raw.len() <= 16 {
-                        let val = parse_u128(&raw).map_err(|err| err.to_string())?;
-                        match Number::from_u128(val) {
-                            Some(num) => Value::Number(num),
-                            None => Value::String(format!("{:#x}", val)),
-                        }
-                    } else {
-                        let val = parse_u256(&raw).map_err(|err| err.to_string())?;
-                        let hex_str = format!("{:#x}", val);
-                        Value::String(hex_str)
-                    }
-                }
-                "bytes" => {
-                    if let Some(s) = size {
-                        if raw.len() != *s as usize {
-                            return Err("invalid bytes len".to_string());
-                        }
-                    }
-                    let hex_str = format!("0x{}", hex::encode(&raw));
-                    Value::String(hex_str)
-                }
-                "string" => {
-                    let val = parse_utf8_string(&raw).map_err(|err| err.to_string())?;
-                    Value::String(val)
-                }
-                "address" => {
-                    if raw.len() != 20 {
-                        return Err("invalid address len".to_string());
-                    }
-                    let addr_hex_str = format!("0x{}", hex::encode(&raw));
-                    Value::String(addr_hex_str)
-                }
-                _ => {
-                    unreachable!();
-                }
+            let raw = data
+                .next()
+                .ok_or_else(|| at_path(path, "build value data.next failed"))?;
+            if opts.prehashed.contains(path) {
+                return Err(at_path(path, "pre-hashed value can not be rebuilt"));
             }
+            build_primitive_value(name, *size, &raw, decimal_strings, opts)
+                .map_err(|err| at_path(path, &err))?
         }
         TypeSchema::Array { item } => {
-            let len_v = data.next().ok_or("build value data.next failed")?;
+            let len_v = data
+                .next()
+                .ok_or_else(|| at_path(path, "build value data.next failed"))?;
             if len_v.len() != 1 {
-                return Err("invalid array size len".to_string());
+                return Err(at_path(path, "invalid array size len"));
             }
             let len = len_v[0];
             let mut arr = vec![];
 
-            for _ in 0..len {
-                arr.push(build_value(item, data)?);
+            for i in 0..len {
+                let item_path = format!("{}[{}]", path, i);
+                arr.push(build_value_at(
+                    item,
+                    data,
+                    decimal_strings,
+                    opts,
+                    &item_path,
+                )?);
             }
 
             arr.into()
@@ -153,7 +456,22 @@ pub fn build_value(
         TypeSchema::Struct { name: _, fields } => {
             let mut obj = serde_json::Map::new();
             for f in fields {
-                let value = build_value(&f.ty, data)?;
+                let f_path = join_path(path, &f.name);
+                let value = if opts.optional.contains(&f_path) && !read_presence(data, &f_path)? {
+                    let mut zero_items = vec![];
+                    zero_data_items(&f.ty, &mut zero_items);
+                    let mut zero_items = zero_items.into_iter();
+                    let default_opts = EncodeOptions::default();
+                    build_value_at(
+                        &f.ty,
+                        &mut zero_items,
+                        decimal_strings,
+                        &default_opts,
+                        &f_path,
+                    )?
+                } else {
+                    build_value_at(&f.ty, data, decimal_strings, opts, &f_path)?
+                };
                 obj.insert(f.name.clone(), value);
             }
             Value::Object(obj)
@@ -162,168 +480,1353 @@ pub fn build_value(
     Ok(res)
 }
 
-#[derive(Debug)]
-pub struct UIField<'a> {
-    pub name: &'a str,
-    pub value: Cow<'a, str>,
-}
-
-pub fn build_ui_fields<'a>(
-    schema: &'a TypeSchema,
-    data: &mut impl Iterator<Item = &'a [u8]>,
-    field_name: &'a str, // used for primitives
-) -> Result<Vec<UIField<'a>>, String> {
-    let res = match schema {
-        TypeSchema::Primitive { name, size } => {
-            let raw = data.next().ok_or("build_ui data.next failed")?;
-            let field = match name.as_str() {
-                "bool" => UIField {
-                    name: field_name,
-                    value: if raw[0] == 1 {
-                        Cow::Borrowed("true")
-                    } else {
-                        Cow::Borrowed("false")
-                    },
-                },
-                "int" => {
-                    let the_size = size.expect("exist") as usize;
-                    if raw.len() > the_size as usize {
-                        return Err("invalid int len".to_string());
-                    }
-                    let value = if the_size <= 16 {
-                        let val = parse_i128(&raw, the_size).map_err(|err| err.to_string())?;
-                        format!("{}", val)
-                    } else {
-                        let val = parse_i256(&raw, the_size).map_err(|err| err.to_string())?;
-                        format!("{}", val)
-                    };
-                    UIField {
-                        name: field_name,
-                        value: Cow::Owned(value),
-                    }
-                }
-                "uint" => {
-                    if let Some(s) = size {
-                        if raw.len() > *s as usize {
-                            return Err("invalid uint len".to_string());
-                        }
-                    }
-                    let value = if raw.len() <= 16 {
-                        let val = parse_u128(&raw).map_err(|err| err.to_string())?;
-                        format!("{}", val)
-                    } else {
-                        let val = parse_u256(&raw).map_err(|err| err.to_string())?;
-                        format!("{}", val)
-                    };
-                    UIField {
-                        name: field_name,
-                        value: Cow::Owned(value),
-                    }
-                }
-                "bytes" => {
-                    if let Some(s) = size {
-                        if raw.len() != *s as usize {
-                            return Err("invalid bytes len".to_string());
-                        }
-                    }
-                    let hex_str = format!("0x{}", hex::encode(&raw));
-                    UIField {
-                        name: field_name,
-                        value: Cow::Owned(hex_str),
-                    }
-                }
-                "string" => {
-                    let val = core::str::from_utf8(&raw).map_err(|err| err.to_string())?;
-                    UIField {
-                        name: field_name,
-                        value: Cow::Borrowed(val),
-                    }
+fn build_primitive_value(
+    name: &str,
+    size: Option<u8>,
+    raw: &[u8],
+    decimal_strings: bool,
+    opts: &EncodeOptions,
+) -> Result<Value, String> {
+    let res = match name {
+        "bool" => Value::Bool(parse_bool(raw)?),
+        "int" => {
+            let the_size = size.ok_or("size info lacked")? as usize;
+            if decimal_strings {
+                let val = parse_int(raw, the_size).map_err(|err| err.to_string())?;
+                Value::String(val.to_string())
+            } else if the_size <= 16 {
+                let val = parse_i128(raw, the_size).map_err(|err| err.to_string())?;
+                match Number::from_i128(val) {
+                    Some(num) => Value::Number(num),
+                    None => Value::String(format!("{:#x}", val)),
                 }
-                "address" => {
-                    if raw.len() != 20 {
-                        return Err("invalid address len".to_string());
-                    }
-                    let addr_hex_str = format!("0x{}", hex::encode(&raw));
-                    UIField {
-                        name: field_name,
-                        value: Cow::Owned(addr_hex_str),
-                    }
+            } else {
+                let val = parse_i256(raw, the_size).map_err(|err| err.to_string())?;
+                Value::String(val.to_hex_string())
+            }
+        }
+        "uint" => {
+            let the_size = size.ok_or("size info lacked")? as usize;
+            let val = parse_u256_checked(raw, the_size)?;
+            if decimal_strings {
+                Value::String(val.to_string())
+            } else if raw.len() <= 16 {
+                let val = val.to::<u128>();
+                match Number::from_u128(val) {
+                    Some(num) => Value::Number(num),
+                    None => Value::String(format!("{:#x}", val)),
                 }
-                _ => {
-                    unreachable!();
+            } else {
+                let hex_str = format!("{:#x}", val);
+                Value::String(hex_str)
+            }
+        }
+        "bytes" => {
+            if let Some(s) = size {
+                if raw.len() != s as usize {
+                    return Err("invalid bytes len".to_string());
                 }
-            };
-            vec![field]
+            } else {
+                opts.check_dynamic_len(raw.len())?;
+            }
+            let hex_str = format!("0x{}", hex::encode(raw));
+            Value::String(hex_str)
         }
-        TypeSchema::Array { item } => {
-            let len_v = data.next().ok_or("build_ui data.next failed")?;
-            if len_v.len() != 1 {
-                return Err("invalid array size len".to_string());
+        "string" => {
+            opts.check_dynamic_len(raw.len())?;
+            let val = parse_utf8_string(raw).map_err(|err| err.to_string())?;
+            Value::String(val)
+        }
+        "address" => {
+            if raw.len() != 20 {
+                return Err("invalid address len".to_string());
             }
-            let len = len_v[0];
-            let mut arr = vec![];
+            let addr_hex_str = format!("0x{}", hex::encode(raw));
+            Value::String(addr_hex_str)
+        }
+        _ => return Err(format!("unknown primitive type: {}", name)),
+    };
+    Ok(res)
+}
 
-            for _ in 0..len {
-                arr.extend(build_ui_fields(item, data, field_name)?);
-            }
+// the reverse of build_value, convert a dapp's json message to the data items the encoder reads.
+// array lengths are emitted as a single byte item before the array items
+pub fn value_to_data_items(schema: &TypeSchema, value: &Value) -> Result<Vec<Vec<u8>>, String> {
+    value_to_data_items_with_options(schema, value, &EncodeOptions::default())
+}
+
+/// Same as `value_to_data_items`, a missing or null field at one of the `opts.optional` paths is
+/// emitted as absent
+pub fn value_to_data_items_with_options(
+    schema: &TypeSchema,
+    value: &Value,
+    opts: &EncodeOptions,
+) -> Result<Vec<Vec<u8>>, String> {
+    let mut items = vec![];
+    value_to_data_items_at(schema, value, "", opts, &mut items)?;
+    Ok(items)
+}
 
-            arr
+fn value_to_data_items_at(
+    schema: &TypeSchema,
+    value: &Value,
+    path: &str,
+    opts: &EncodeOptions,
+    items: &mut Vec<Vec<u8>>,
+) -> Result<(), String> {
+    match schema {
+        TypeSchema::Primitive { name, size } => {
+            let item =
+                primitive_to_data_item(name, *size, value).map_err(|err| at_path(path, &err))?;
+            items.push(item);
+        }
+        TypeSchema::Array { item } => {
+            let arr = value
+                .as_array()
+                .ok_or_else(|| at_path(path, "expect an array"))?;
+            let len = u8::try_from(arr.len()).map_err(|_| at_path(path, "array too long"))?;
+            items.push(vec![len]);
+            for (i, v) in arr.iter().enumerate() {
+                let item_path = format!("{}[{}]", path, i);
+                value_to_data_items_at(item, v, &item_path, opts, items)?;
+            }
         }
         TypeSchema::Struct { name: _, fields } => {
-            let mut arr = vec![];
+            let obj = value
+                .as_object()
+                .ok_or_else(|| at_path(path, "expect an object"))?;
             for f in fields {
-                let res = build_ui_fields(&f.ty, data, &f.name)?;
-                arr.extend(res);
+                let f_path = join_path(path, &f.name);
+                let optional = opts.optional.contains(&f_path);
+                let v = match obj.get(&f.name) {
+                    Some(v) if optional && v.is_null() => None,
+                    Some(v) => Some(v),
+                    None if optional => None,
+                    None => return Err(at_path(&f_path, "missing field")),
+                };
+                if optional {
+                    items.push(vec![v.is_some() as u8]);
+                }
+                if let Some(v) = v {
+                    value_to_data_items_at(&f.ty, v, &f_path, opts, items)?;
+                }
             }
-            arr
         }
-    };
-    Ok(res)
+    }
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{build_schema, build_ui_fields, build_value};
-    use crate::{
-        test_utils::*,
-        types::{
-            Eip712FieldDefinition, Eip712FieldType, Eip712StructDefinitions,
-            build_resolver_from_struct_defs,
-        },
-    };
-    use alloy_dyn_abi::eip712::TypedData;
-    use alloy_primitives::hex;
-
-    #[test]
-    fn test_build_value() {
-        let struct_defs = prepare_mail_struct_defs();
+// the text of a json number, a decimal string or a 0x hex string, negative values start with `-`
+fn json_int_text(value: &Value) -> Result<String, String> {
+    match value {
+        Value::Number(num) => Ok(num.to_string()),
+        Value::String(s) => Ok(s.clone()),
+        _ => Err("expect an integer".to_string()),
+    }
+}
 
-        let type_schema = build_schema(&struct_defs, &"Mail".to_string());
-        assert_eq!(type_schema.is_ok(), true);
-        let type_schema = type_schema.unwrap();
+fn parse_u256_text(text: &str) -> Result<U256, String> {
+    text.parse::<U256>()
+        .map_err(|_| format!("invalid integer: {}", text))
+}
 
-        let data = prepare_mail_data();
-        let value = build_value(&type_schema, &mut data.into_iter());
-        assert_eq!(value.is_ok(), true);
-        let value = value.unwrap();
+fn json_to_uint_item(value: &Value, size: usize) -> Result<Vec<u8>, String> {
+    let text = json_int_text(value)?;
+    let val = parse_u256_text(&text)?;
+    if size < 32 && val >> (size * 8) != U256::ZERO {
+        return Err(format!("uint{} overflow: {}", size * 8, text));
+    }
+    Ok(Eip712FieldValue::from_u256_minimal(val).value)
+}
+
+// a positive value is emitted in minimal bytes, a negative one in `size` bytes of two's complement
+// since shorter data is zero padded to `size` when decoded
+fn json_to_int_item(value: &Value, size: usize) -> Result<Vec<u8>, String> {
+    if size == 0 || size > 32 {
+        return Err(format!("invalid int size: {}", size));
+    }
+    let text = json_int_text(value)?;
+    let (negative, abs) = match text.strip_prefix('-') {
+        Some(abs_text) => (true, parse_u256_text(abs_text)?),
+        None => (false, parse_u256_text(&text)?),
+    };
+    let half = U256::from(1u8) << (size * 8 - 1);
+    let fits = if negative { abs <= half } else { abs < half };
+    if !fits {
+        return Err(format!("int{} overflow: {}", size * 8, text));
+    }
+    if negative && !abs.is_zero() {
+        let bytes = abs.wrapping_neg().to_be_bytes::<32>();
+        Ok(bytes[32 - size..].to_vec())
+    } else {
+        Ok(Eip712FieldValue::from_u256_minimal(abs).value)
+    }
+}
+
+fn json_to_hex_bytes(value: &Value) -> Result<Vec<u8>, String> {
+    let s = value.as_str().ok_or("expect a hex string")?;
+    hex::decode(s).map_err(|_| format!("invalid hex string: {}", s))
+}
+
+fn primitive_to_data_item(name: &str, size: Option<u8>, value: &Value) -> Result<Vec<u8>, String> {
+    let item = match name {
+        "bool" => {
+            let b = value.as_bool().ok_or("expect a bool")?;
+            vec![b as u8]
+        }
+        "int" => json_to_int_item(value, size.ok_or("size info lacked")? as usize)?,
+        "uint" => json_to_uint_item(value, size.ok_or("size info lacked")? as usize)?,
+        "bytes" => {
+            let bytes = json_to_hex_bytes(value)?;
+            if size.is_some_and(|s| bytes.len() != s as usize) {
+                return Err("invalid bytes len".to_string());
+            }
+            bytes
+        }
+        "string" => value.as_str().ok_or("expect a string")?.as_bytes().to_vec(),
+        "address" => {
+            let bytes = json_to_hex_bytes(value)?;
+            if bytes.len() != 20 {
+                return Err("invalid address len".to_string());
+            }
+            bytes
+        }
+        _ => return Err(format!("unknown primitive type: {}", name)),
+    };
+    Ok(item)
+}
+
+/// Build a domain from a dapp's json domain object, `chainId` may be a number, a decimal string
+/// or a 0x hex string, eg: `1`, `"1"` and `"0x1"` give the same domain
+pub fn domain_from_json(value: &Value) -> Result<Eip712Domain, String> {
+    let obj = value.as_object().ok_or("expect a domain object")?;
+    let mut domain = Eip712Domain::default();
+    for (key, v) in obj {
+        let field_err = |err: String| at_path(key, &err);
+        match key.as_str() {
+            "name" => {
+                let name = v.as_str().ok_or("name: expect a string")?;
+                domain.name = Some(Cow::Owned(name.to_string()));
+            }
+            "version" => {
+                let version = v.as_str().ok_or("version: expect a string")?;
+                domain.version = Some(Cow::Owned(version.to_string()));
+            }
+            "chainId" => {
+                let text = json_int_text(v).map_err(field_err)?;
+                domain.chain_id = Some(parse_u256_text(text.trim()).map_err(field_err)?);
+            }
+            "verifyingContract" => {
+                let bytes = json_to_hex_bytes(v).map_err(field_err)?;
+                let addr = Address::try_from(bytes.as_slice())
+                    .map_err(|_| field_err("invalid address len".to_string()))?;
+                domain.verifying_contract = Some(addr);
+            }
+            "salt" => {
+                let bytes = json_to_hex_bytes(v).map_err(field_err)?;
+                let salt = B256::try_from(bytes.as_slice())
+                    .map_err(|_| field_err("invalid salt len".to_string()))?;
+                domain.salt = Some(salt);
+            }
+            _ => return Err(format!("invalid domain field: {}", key)),
+        }
+    }
+    Ok(domain)
+}
+
+// only dynamic bytes and strings are hashed by encode_data, so only they can be pre-hashed
+pub(crate) fn is_hashed_primitive(name: &str, size: Option<u8>) -> bool {
+    (name == "bytes" && size.is_none()) || name == "string"
+}
+
+/// Structured kind of a displayed leaf
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UIFieldKind {
+    Bool,
+    Int,
+    Uint,
+    Bytes,
+    String,
+    /// The full address, even when the displayed value is shortened
+    Address([u8; 20]),
+    /// A pre-hashed `bytes`/`string` value, only its hash is known
+    Opaque,
+    /// An optional field absent from the data stream, hashed as the zero value of its type
+    Absent,
+}
+
+// the displayed value of an absent optional field
+const ABSENT_VALUE: &str = "(absent)";
+
+impl UIFieldKind {
+    /// Short lowercase name of the kind, eg: `uint`
+    pub fn name(&self) -> &'static str {
+        match self {
+            UIFieldKind::Bool => "bool",
+            UIFieldKind::Int => "int",
+            UIFieldKind::Uint => "uint",
+            UIFieldKind::Bytes => "bytes",
+            UIFieldKind::String => "string",
+            UIFieldKind::Address(_) => "address",
+            UIFieldKind::Opaque => "opaque",
+            UIFieldKind::Absent => "absent",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UIField<'a> {
+    pub name: &'a str,
+    // the full field path, eg: from.wallets.[]
+    pub path: String,
+    pub value: Cow<'a, str>,
+    pub kind: UIFieldKind,
+}
+
+/// Per-field display format
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FieldFormat {
+    /// Integer basis points rendered as a percentage
+    BasisPoints,
+    /// Integer rendered as `0x` prefixed hex, a negative int as its two's complement
+    Hex,
+    /// Integer rendered as a fixed-point decimal, eg: an 8 decimals price as -1234.56789012
+    FixedPoint { decimals: u8 },
+}
+
+/// Letter case of hex digits in displayed byte values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HexCase {
+    #[default]
+    Lower,
+    Upper,
+}
+
+/// How address leaves are displayed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AddressDisplay {
+    /// The full 0x prefixed address
+    #[default]
+    Full,
+    /// Middle-ellipsis form keeping `lead` leading and `tail` trailing characters
+    Short { lead: usize, tail: usize },
+    /// CIP-37 base32 form on the Conflux `network_id`, the verbose form names the address type
+    Conflux { network_id: u32, verbose: bool },
+}
+
+/// UI formatting options
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UIOptions {
+    /// Opt-in display format keyed by field path
+    pub field_formats: BTreeMap<String, FieldFormat>,
+    /// Hex case of `bytes` values and of hex formatted integers, the `0x` prefix is always
+    /// lowercase
+    pub hex_case: HexCase,
+    /// Address leaves are shown in full unless a short form is requested
+    pub address_display: AddressDisplay,
+    /// Paths of `bytes`/`string` leaves supplied pre-hashed, shown as `opaque(0x<hash>)`, see
+    /// `EncodeOptions::ui_options`
+    pub prehashed: PrehashedPaths,
+    /// Paths of the fields preceded by a presence marker, an absent one is shown as `(absent)`,
+    /// see `EncodeOptions::optional`
+    pub optional: OptionalPaths,
+}
+
+fn write_hex_in_case(out: &mut String, bytes: &[u8], case: HexCase) {
+    match case {
+        HexCase::Lower => write_hex_prefixed(out, bytes),
+        HexCase::Upper => write_hex_prefixed_upper(out, bytes),
+    }
+}
+
+fn format_address(addr: &[u8; 20], display: AddressDisplay) -> String {
+    let mut addr_hex_str = String::new();
+    write_hex_prefixed(&mut addr_hex_str, addr);
+    match display {
+        AddressDisplay::Full => addr_hex_str,
+        AddressDisplay::Short { lead, tail } => shorten_address(&addr_hex_str, lead, tail),
+        AddressDisplay::Conflux {
+            network_id,
+            verbose,
+        } => format_cip37_address(addr, network_id, verbose),
+    }
+}
+
+// the filter form of an indexed path, eg: from.wallets[1] -> from.wallets.[], a path already in
+// the filter form is unchanged
+pub(crate) fn filter_path(path: &str) -> String {
+    let mut res = String::with_capacity(path.len());
+    let mut in_index = false;
+    for c in path.chars() {
+        match c {
+            '[' => {
+                in_index = true;
+                if !res.is_empty() && !res.ends_with('.') {
+                    res.push('.');
+                }
+                res.push_str("[]");
+            }
+            ']' => in_index = false,
+            _ if in_index => {}
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+// prefix an error with the path of the field it occurred on
+pub(crate) fn at_path(path: &str, err: &str) -> String {
+    if path.is_empty() {
+        err.to_string()
+    } else {
+        format!("{}: {}", path, err)
+    }
+}
+
+pub(crate) fn join_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", prefix, name)
+    }
+}
+
+pub fn build_ui_fields<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    field_name: &'a str, // used for primitives
+) -> Result<Vec<UIField<'a>>, String> {
+    build_ui_fields_with_options(schema, data, field_name, &UIOptions::default())
+}
+
+pub fn build_ui_fields_with_options<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    field_name: &'a str, // used for primitives
+    opts: &UIOptions,
+) -> Result<Vec<UIField<'a>>, String> {
+    build_ui_fields_at(schema, data, field_name, field_name, opts, None, None)
+}
+
+/// A local address book consulted for every address leaf, purely cosmetic
+pub trait AddressLabels {
+    fn label(&self, addr: &[u8; 20]) -> Option<String>;
+}
+
+/// Same as `build_ui_fields_with_options`, an address with a label is shown as `Treasury (0xabc…)`
+/// where the address in parentheses follows `opts.address_display`
+pub fn build_ui_fields_with_labels<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    field_name: &'a str, // used for primitives
+    opts: &UIOptions,
+    labels: &dyn AddressLabels,
+) -> Result<Vec<UIField<'a>>, String> {
+    build_ui_fields_at(
+        schema,
+        data,
+        field_name,
+        field_name,
+        opts,
+        Some(labels),
+        None,
+    )
+}
+
+/// A host hook consulted for every displayed leaf, eg: to map a small uint to an enum label.
+/// `path` is the dotted field path, `default` the value formatted with the options, returning
+/// None keeps the default
+pub trait FieldTransform {
+    fn transform(&self, path: &str, raw: &[u8], default: &str) -> Option<String>;
+}
+
+// a transform which may also fail, an error aborts the display, for the formatting of the
+// trusted clear-sign flow
+pub(crate) trait CheckedFieldTransform {
+    fn try_transform(
+        &self,
+        path: &str,
+        raw: &[u8],
+        default: &str,
+    ) -> Result<Option<String>, String>;
+}
+
+struct InfallibleTransform<'t>(&'t dyn FieldTransform);
+
+impl CheckedFieldTransform for InfallibleTransform<'_> {
+    fn try_transform(
+        &self,
+        path: &str,
+        raw: &[u8],
+        default: &str,
+    ) -> Result<Option<String>, String> {
+        Ok(self.0.transform(path, raw, default))
+    }
+}
+
+/// Same as `build_ui_fields_with_options`, the displayed values can be overridden by `transform`
+pub fn build_ui_fields_with_transform<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    field_name: &'a str, // used for primitives
+    opts: &UIOptions,
+    transform: &dyn FieldTransform,
+) -> Result<Vec<UIField<'a>>, String> {
+    build_ui_fields_with_checked_transform(
+        schema,
+        data,
+        field_name,
+        opts,
+        &InfallibleTransform(transform),
+    )
+}
+
+pub(crate) fn build_ui_fields_with_checked_transform<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    field_name: &'a str,
+    opts: &UIOptions,
+    transform: &dyn CheckedFieldTransform,
+) -> Result<Vec<UIField<'a>>, String> {
+    build_ui_fields_at(
+        schema,
+        data,
+        field_name,
+        field_name,
+        opts,
+        None,
+        Some(transform),
+    )
+}
+
+/// Same as `build_ui_fields` without collecting the fields, each one is passed to `callback` as
+/// soon as it is decoded. Returning false stops the walk, the rest of `data` is then left unread
+pub fn for_each_ui_field<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    callback: &mut impl FnMut(UIField<'a>) -> bool,
+) -> Result<(), String> {
+    let ctx = UIContext {
+        opts: &UIOptions::default(),
+        labels: None,
+        transform: None,
+    };
+    walk_ui_fields_at(schema, data, "", "", &ctx, callback)?;
+    Ok(())
+}
+
+fn build_ui_fields_at<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    field_name: &'a str,
+    path: &str,
+    opts: &UIOptions,
+    labels: Option<&dyn AddressLabels>,
+    transform: Option<&dyn CheckedFieldTransform>,
+) -> Result<Vec<UIField<'a>>, String> {
+    let ctx = UIContext {
+        opts,
+        labels,
+        transform,
+    };
+    let mut fields = vec![];
+    walk_ui_fields_at(schema, data, field_name, path, &ctx, &mut |field| {
+        fields.push(field);
+        true
+    })?;
+    Ok(fields)
+}
+
+// the display settings shared by every leaf
+struct UIContext<'c> {
+    opts: &'c UIOptions,
+    labels: Option<&'c dyn AddressLabels>,
+    transform: Option<&'c dyn CheckedFieldTransform>,
+}
+
+// emits the leaves in message order, returns false once `emit` asked to stop
+fn walk_ui_fields_at<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    field_name: &'a str,
+    path: &str,
+    ctx: &UIContext,
+    emit: &mut dyn FnMut(UIField<'a>) -> bool,
+) -> Result<bool, String> {
+    let UIContext {
+        opts,
+        labels,
+        transform,
+    } = *ctx;
+    let res = match schema {
+        TypeSchema::Primitive { name, size } => {
+            let raw = data.next().ok_or("build_ui data.next failed")?;
+            let format = opts.field_formats.get(path);
+            let format_supported = match format {
+                None => true,
+                Some(FieldFormat::BasisPoints) => name == "uint",
+                Some(FieldFormat::Hex) | Some(FieldFormat::FixedPoint { .. }) => {
+                    name == "uint" || name == "int"
+                }
+            };
+            if !format_supported {
+                return Err(format!(
+                    "field format not supported for {} at {}",
+                    name, path
+                ));
+            }
+            let prehashed = opts.prehashed.contains(path);
+            if prehashed && !is_hashed_primitive(name, *size) {
+                return Err(format!(
+                    "pre-hashed value not allowed for {} at {}",
+                    name, path
+                ));
+            }
+            let (kind, value) = match name.as_str() {
+                _ if prehashed => {
+                    if raw.len() != 32 {
+                        return Err(format!("invalid pre-hashed value len at {}", path));
+                    }
+                    let mut hash = String::from("opaque(");
+                    write_hex_prefixed(&mut hash, raw);
+                    hash.push(')');
+                    (UIFieldKind::Opaque, Cow::Owned(hash))
+                }
+                "bool" => {
+                    let value = if parse_bool(raw)? {
+                        Cow::Borrowed("true")
+                    } else {
+                        Cow::Borrowed("false")
+                    };
+                    (UIFieldKind::Bool, value)
+                }
+                "int" => {
+                    let the_size = size.ok_or("size info lacked")? as usize;
+                    let val = parse_int(raw, the_size).map_err(|err| err.to_string())?;
+                    let value = match format {
+                        Some(FieldFormat::Hex) => {
+                            let bytes = val.to_be_bytes::<32>();
+                            let mut hex_str = String::new();
+                            write_hex_in_case(&mut hex_str, &bytes[32 - the_size..], opts.hex_case);
+                            hex_str
+                        }
+                        Some(FieldFormat::FixedPoint { decimals }) => {
+                            format_fixed_point(val, *decimals)
+                        }
+                        _ => format!("{}", val),
+                    };
+                    (UIFieldKind::Int, Cow::Owned(value))
+                }
+                "uint" => {
+                    let the_size = size.ok_or("size info lacked")? as usize;
+                    let val = parse_u256_checked(raw, the_size)?;
+                    let value = match format {
+                        Some(FieldFormat::BasisPoints) => format_basis_points(raw)?,
+                        // in the declared size like an int, eg: 0x00ff for a uint16
+                        Some(FieldFormat::Hex) => {
+                            let bytes = val.to_be_bytes::<32>();
+                            let mut hex_str = String::new();
+                            write_hex_in_case(&mut hex_str, &bytes[32 - the_size..], opts.hex_case);
+                            hex_str
+                        }
+                        Some(FieldFormat::FixedPoint { decimals }) => {
+                            shift_decimals(val, *decimals)
+                        }
+                        None => format!("{}", val),
+                    };
+                    (UIFieldKind::Uint, Cow::Owned(value))
+                }
+                "bytes" => {
+                    if let Some(s) = size {
+                        if raw.len() != *s as usize {
+                            return Err("invalid bytes len".to_string());
+                        }
+                    }
+                    let mut hex_str = String::new();
+                    write_hex_in_case(&mut hex_str, raw, opts.hex_case);
+                    (UIFieldKind::Bytes, Cow::Owned(hex_str))
+                }
+                "string" => {
+                    let val = core::str::from_utf8(raw).map_err(|err| err.to_string())?;
+                    (UIFieldKind::String, Cow::Borrowed(val))
+                }
+                "address" => {
+                    if raw.len() != 20 {
+                        return Err("invalid address len".to_string());
+                    }
+                    let mut addr = [0u8; 20];
+                    addr.copy_from_slice(raw);
+                    let mut value = format_address(&addr, opts.address_display);
+                    if let Some(label) = labels.and_then(|labels| labels.label(&addr)) {
+                        value = format!("{} ({})", label, value);
+                    }
+                    (UIFieldKind::Address(addr), Cow::Owned(value))
+                }
+                _ => return Err(format!("unknown primitive type: {}", name)),
+            };
+            let custom = match transform {
+                Some(t) => t.try_transform(path, raw, &value)?,
+                None => None,
+            };
+            let value = match custom {
+                Some(custom) => Cow::Owned(custom),
+                None => value,
+            };
+            emit(UIField {
+                name: field_name,
+                path: path.to_string(),
+                value,
+                kind,
+            })
+        }
+        TypeSchema::Array { item } => {
+            let len_v = data.next().ok_or("build_ui data.next failed")?;
+            if len_v.len() != 1 {
+                return Err("invalid array size len".to_string());
+            }
+            let len = len_v[0];
+            let item_path = join_path(path, "[]");
+
+            for _ in 0..len {
+                if !walk_ui_fields_at(item, data, field_name, &item_path, ctx, emit)? {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        TypeSchema::Struct { name: _, fields } => {
+            for f in fields {
+                let field_path = join_path(path, &f.name);
+                // an absent optional field is shown as such rather than as its zero value
+                if opts.optional.contains(&field_path) && !read_presence(data, &field_path)? {
+                    let field = UIField {
+                        name: &f.name,
+                        path: field_path,
+                        value: Cow::Borrowed(ABSENT_VALUE),
+                        kind: UIFieldKind::Absent,
+                    };
+                    if !emit(field) {
+                        return Ok(false);
+                    }
+                    continue;
+                }
+                if !walk_ui_fields_at(&f.ty, data, &f.name, &field_path, ctx, emit)? {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+    };
+    Ok(res)
+}
+
+/// One `path (kind): value` line per field in order, a stable text form for audit logs and
+/// snapshots. Backslashes and line breaks of a value are escaped as `\\`, `\n` and `\r` so a
+/// string value can not forge extra lines
+pub fn ui_fields_transcript(fields: &[UIField]) -> String {
+    let mut transcript = String::new();
+    for field in fields {
+        transcript.push_str(&field.path);
+        transcript.push_str(" (");
+        transcript.push_str(field.kind.name());
+        transcript.push_str("): ");
+        for c in field.value.chars() {
+            match c {
+                '\\' => transcript.push_str("\\\\"),
+                '\n' => transcript.push_str("\\n"),
+                '\r' => transcript.push_str("\\r"),
+                _ => transcript.push(c),
+            }
+        }
+        transcript.push('\n');
+    }
+    transcript
+}
+
+/// The whole message as `(path, value)` pairs for an export, paths are fully qualified with the
+/// array indices, eg: `to.wallets[2]`, and values are formatted like the UI fields
+pub fn build_flat_kv<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+) -> Result<Vec<(String, String)>, String> {
+    build_flat_kv_with_options(schema, data, &UIOptions::default())
+}
+
+/// Same as `build_flat_kv`, the values are formatted and the optional fields read with `opts`
+pub fn build_flat_kv_with_options<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    opts: &UIOptions,
+) -> Result<Vec<(String, String)>, String> {
+    let mut kv = vec![];
+    build_flat_kv_at(schema, data, "", opts, &mut kv)?;
+    Ok(kv)
+}
+
+fn build_flat_kv_at<'a>(
+    schema: &'a TypeSchema,
+    data: &mut impl Iterator<Item = &'a [u8]>,
+    path: &str,
+    opts: &UIOptions,
+    kv: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    match schema {
+        TypeSchema::Primitive { .. } => {
+            let field = build_ui_fields_at(schema, data, "", path, opts, None, None)
+                .map_err(|err| at_path(path, &err))?
+                .pop()
+                .ok_or_else(|| at_path(path, "no value"))?;
+            kv.push((path.to_string(), field.value.into_owned()));
+        }
+        TypeSchema::Array { item } => {
+            let len_v = data
+                .next()
+                .ok_or_else(|| at_path(path, "build value data.next failed"))?;
+            if len_v.len() != 1 {
+                return Err(at_path(path, "invalid array size len"));
+            }
+            for i in 0..len_v[0] {
+                let item_path = format!("{}[{}]", path, i);
+                build_flat_kv_at(item, data, &item_path, opts, kv)?;
+            }
+        }
+        TypeSchema::Struct { name: _, fields } => {
+            for f in fields {
+                let f_path = join_path(path, &f.name);
+                if opts.optional.contains(&f_path) && !read_presence(data, &f_path)? {
+                    kv.push((f_path, ABSENT_VALUE.to_string()));
+                    continue;
+                }
+                build_flat_kv_at(&f.ty, data, &f_path, opts, kv)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Domain fields shown at the start of a signing flow
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DomainSummary {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub chain_id: Option<U256>,
+    /// Name of the chain if it is a well known one
+    pub network: Option<&'static str>,
+    /// Formatted following the address display option
+    pub verifying_contract: Option<String>,
+}
+
+/// An owned `UIField`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreviewField {
+    pub name: String,
+    pub path: String,
+    pub value: String,
+    pub kind: UIFieldKind,
+}
+
+/// Everything needed to render a signing flow
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessagePreview {
+    pub domain: DomainSummary,
+    pub primary_type: String,
+    pub fields: Vec<PreviewField>,
+}
+
+pub(crate) fn domain_summary(domain: &Eip712Domain, opts: &UIOptions) -> DomainSummary {
+    DomainSummary {
+        name: domain.name.as_ref().map(|name| name.to_string()),
+        version: domain.version.as_ref().map(|version| version.to_string()),
+        chain_id: domain.chain_id,
+        network: domain.chain_id.and_then(network_name),
+        verifying_contract: domain
+            .verifying_contract
+            .map(|contract| format_address(&contract.0.0, opts.address_display)),
+    }
+}
+
+pub fn preview_message(
+    struct_defs: &Eip712StructDefinitions,
+    data: &[Vec<u8>],
+    primary_type: &String,
+    domain: &Eip712Domain,
+    opts: &UIOptions,
+) -> Result<MessagePreview, String> {
+    let schema = build_schema(struct_defs, primary_type)?;
+    let mut ref_data = data.iter().map(|v| v.as_slice());
+    let fields = build_ui_fields_with_options(&schema, &mut ref_data, "", opts)?
+        .into_iter()
+        .map(|f| PreviewField {
+            name: f.name.to_string(),
+            path: f.path,
+            value: f.value.into_owned(),
+            kind: f.kind,
+        })
+        .collect();
+
+    Ok(MessagePreview {
+        domain: domain_summary(domain, opts),
+        primary_type: primary_type.clone(),
+        fields,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use super::decode_to_json;
+    use super::{
+        AddressDisplay, AddressLabels, EncodeOptions, Field, FieldFormat, FieldTransform, HexCase,
+        PrehashedPaths, TypeSchema, UIFieldKind, UIOptions, build_flat_kv,
+        build_flat_kv_with_options, build_schema, build_ui_fields, build_ui_fields_with_labels,
+        build_ui_fields_with_options, build_ui_fields_with_transform, build_value,
+        build_value_strings, build_value_with_options, domain_from_json, domain_summary,
+        filter_path, for_each_ui_field, preview_message, schema_to_dyn_sol_type,
+        ui_fields_transcript, value_to_data_items, value_to_data_items_with_options,
+    };
+    use crate::{
+        test_utils::*,
+        types::{
+            Eip712ArrayLevel, Eip712FieldDefinition, Eip712FieldType, Eip712StructDefinitions,
+            build_resolver_from_struct_defs,
+        },
+    };
+    use alloy_dyn_abi::{DynSolType, DynSolValue, eip712::TypedData};
+    use alloy_primitives::{I256, hex};
+
+    #[test]
+    fn test_domain_from_json_chain_id() {
+        let domain_json = |chain_id: serde_json::Value| {
+            serde_json::json!({
+                "name": "Simple Mail",
+                "version": "1",
+                "chainId": chain_id,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            })
+        };
+        let expected = get_raw_mail_typed_data().unwrap().domain().separator();
+        for chain_id in [
+            serde_json::json!(1),
+            serde_json::json!("1"),
+            serde_json::json!("0x1"),
+        ] {
+            let domain = domain_from_json(&domain_json(chain_id.clone())).unwrap();
+            assert_eq!(domain.chain_id, Some(alloy_primitives::U256::from(1)));
+            assert_eq!(domain.separator(), expected, "chainId {}", chain_id);
+
+            // the data items path accepts the same forms
+            let struct_defs = prepare_mail_struct_defs();
+            let schema = build_schema(&struct_defs, &"EIP712Domain".to_string()).unwrap();
+            let items = value_to_data_items(&schema, &domain_json(chain_id)).unwrap();
+            assert_eq!(items[2], vec![1]);
+        }
+
+        assert_eq!(
+            domain_from_json(&domain_json(serde_json::json!("one"))).unwrap_err(),
+            "chainId: invalid integer: one"
+        );
+        assert_eq!(
+            domain_from_json(&serde_json::json!({ "chain": 1 })).unwrap_err(),
+            "invalid domain field: chain"
+        );
+    }
+
+    #[test]
+    fn test_build_flat_kv() {
+        let struct_defs = prepare_mail_struct_defs();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let mail_data = prepare_mail_data();
+        let mut ref_data = mail_data.iter().map(|v| v.as_slice());
+        let kv = build_flat_kv(&schema, &mut ref_data).unwrap();
+
+        let paths: Vec<&str> = kv.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "from.name",
+                "from.wallets[0]",
+                "from.wallets[1]",
+                "to.name",
+                "to.wallets[0]",
+                "to.wallets[1]",
+                "to.wallets[2]",
+                "contents",
+                "timestamp",
+                "amount",
+                "payback",
+            ]
+        );
+        assert_eq!(kv[0].1, "Cow");
+        assert_eq!(kv[1].1, "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826");
+        assert_eq!(kv[7].1, "Hello, Bob!");
+        assert_eq!(kv[9].1, "1000000");
+
+        // every leaf is exported, ready for a newline-delimited text
+        let mut ref_data = mail_data.iter().map(|v| v.as_slice());
+        let ui_fields = build_ui_fields(&schema, &mut ref_data, "").unwrap();
+        assert_eq!(kv.len(), ui_fields.len());
+
+        let mut truncated = mail_data.clone();
+        truncated[6].pop();
+        let mut ref_data = truncated.iter().map(|v| v.as_slice());
+        assert_eq!(
+            build_flat_kv(&schema, &mut ref_data).unwrap_err(),
+            "to.wallets[0]: invalid address len"
+        );
+    }
+
+    #[test]
+    fn test_optional_field() {
+        let struct_defs = prepare_mail_struct_defs();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let mut opts = EncodeOptions::default();
+        opts.optional.insert("from.wallets");
+        opts.optional.insert("to.wallets");
+        let ui_opts = opts.ui_options();
+
+        // the wallets of `from` are absent, the ones of `to` are present
+        let mut mail_data = prepare_mail_data();
+        mail_data.drain(1..4);
+        mail_data.insert(1, vec![0]);
+        mail_data.insert(3, vec![1]);
+
+        let value =
+            build_value_with_options(&schema, &mut mail_data.clone().into_iter(), &opts).unwrap();
+        assert_eq!(value["from"]["wallets"], serde_json::json!([]));
+        assert_eq!(value["to"]["wallets"].as_array().unwrap().len(), 3);
+
+        let mut ref_data = mail_data.iter().map(|v| v.as_slice());
+        let ui_fields = build_ui_fields_with_options(&schema, &mut ref_data, "", &ui_opts).unwrap();
+        assert!(ui_fields.iter().all(|f| f.path != "from.wallets.[]"));
+        // the absent field is shown, not silently hashed as an empty array
+        let absent = ui_fields.iter().find(|f| f.path == "from.wallets").unwrap();
+        assert_eq!(absent.kind, UIFieldKind::Absent);
+        assert_eq!(absent.value, "(absent)");
+        assert_eq!(ui_fields[1].path, "from.wallets");
+        let mut ref_data = mail_data.iter().map(|v| v.as_slice());
+        let kv = build_flat_kv_with_options(&schema, &mut ref_data, &ui_opts).unwrap();
+        assert_eq!(kv[1], ("from.wallets".to_string(), "(absent)".to_string()));
+        assert_eq!(
+            ui_fields
+                .iter()
+                .filter(|f| f.path == "to.wallets.[]")
+                .count(),
+            3
+        );
+
+        // a missing or null optional field is emitted as absent
+        let mut message = value.clone();
+        message["from"].as_object_mut().unwrap().remove("wallets");
+        assert_eq!(
+            value_to_data_items_with_options(&schema, &message, &opts).unwrap(),
+            mail_data
+        );
+        message["from"]["wallets"] = serde_json::Value::Null;
+        assert_eq!(
+            value_to_data_items_with_options(&schema, &message, &opts).unwrap(),
+            mail_data
+        );
+
+        // the markers are only read at the optional paths
+        assert!(build_value(&schema, &mut mail_data.into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_build_value() {
+        let struct_defs = prepare_mail_struct_defs();
+
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string());
+        assert_eq!(type_schema.is_ok(), true);
+        let type_schema = type_schema.unwrap();
+
+        let data = prepare_mail_data();
+        let value = build_value(&type_schema, &mut data.into_iter());
+        assert_eq!(value.is_ok(), true);
+        let value = value.unwrap();
 
         let typed = get_raw_mail_typed_data().expect("success");
 
         let resolver = build_resolver_from_struct_defs(&struct_defs).unwrap();
 
-        let new_typed_data = TypedData {
-            domain: typed.domain.clone(),
-            resolver,
-            primary_type: "Mail".to_string(),
-            message: value,
-        };
+        let new_typed_data = TypedData {
+            domain: typed.domain.clone(),
+            resolver,
+            primary_type: "Mail".to_string(),
+            message: value,
+        };
+
+        let hash1 = typed.eip712_signing_hash().unwrap();
+        let maybe_hash2 = new_typed_data.eip712_signing_hash();
+
+        assert!(maybe_hash2.is_ok());
+
+        assert_eq!(hash1, maybe_hash2.unwrap());
+    }
+
+    #[test]
+    fn test_build_schema_cyclic() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        struct_defs.insert(
+            "Node".to_string(),
+            vec![Eip712FieldDefinition {
+                name: "next".to_string(),
+                field_type: Eip712FieldType::Custom("Node".to_string()),
+                array_levels: vec![],
+            }],
+        );
+        assert!(build_schema(&struct_defs, &"Node".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_value_to_data_items() {
+        let struct_defs = prepare_mail_struct_defs();
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let typed = get_raw_mail_typed_data().unwrap();
+        let message: serde_json::Value = serde_json::from_str::<serde_json::Value>(include_str!(
+            "../res/mail.json"
+        ))
+        .unwrap()["message"]
+            .clone();
+
+        let items = value_to_data_items(&type_schema, &message).unwrap();
+        assert_eq!(items, prepare_mail_data());
+
+        let hash = crate::eip712::eip712_signing_hash(
+            &struct_defs,
+            &mut items.into_iter(),
+            &"Mail".to_string(),
+            typed.domain(),
+        )
+        .unwrap();
+        assert_eq!(hash, typed.eip712_signing_hash().unwrap());
+
+        let mut bad = message.clone();
+        bad["to"]["wallets"][1] = "0x1234".into();
+        assert_eq!(
+            value_to_data_items(&type_schema, &bad).unwrap_err(),
+            "to.wallets[1]: invalid address len"
+        );
+    }
+
+    #[test]
+    fn test_value_to_data_items_integers() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        struct_defs.insert(
+            "Amounts".to_string(),
+            vec![
+                Eip712FieldDefinition::new(Eip712FieldType::Uint(2), "a".to_string()),
+                Eip712FieldDefinition::new(Eip712FieldType::Int(1), "b".to_string()),
+                Eip712FieldDefinition::new(Eip712FieldType::Int(2), "c".to_string()),
+            ],
+        );
+        let schema = build_schema(&struct_defs, &"Amounts".to_string()).unwrap();
+        let convert = |a: serde_json::Value, b: serde_json::Value, c: serde_json::Value| {
+            value_to_data_items(&schema, &serde_json::json!({ "a": a, "b": b, "c": c }))
+        };
+
+        for a in [
+            serde_json::json!("256"),
+            serde_json::json!("0x100"),
+            serde_json::json!(256),
+        ] {
+            let items = convert(a, "-128".into(), "-128".into()).unwrap();
+            assert_eq!(items, [vec![0x01, 0x00], vec![0x80], vec![0xff, 0x80]]);
+        }
+        let items = convert(0.into(), 127.into(), "0x7fff".into()).unwrap();
+        assert_eq!(items, [vec![0x00], vec![0x7f], vec![0x7f, 0xff]]);
+
+        // the items decode back to the same values
+        let items = convert("65535".into(), (-1).into(), "-0x8000".into()).unwrap();
+        let value = build_value_strings(&schema, &mut items.into_iter()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({ "a": "65535", "b": "-1", "c": "-32768" })
+        );
+
+        assert_eq!(
+            convert("65536".into(), 0.into(), 0.into()).unwrap_err(),
+            "a: uint16 overflow: 65536"
+        );
+        assert_eq!(
+            convert(0.into(), "128".into(), 0.into()).unwrap_err(),
+            "b: int8 overflow: 128"
+        );
+        assert_eq!(
+            convert(0.into(), (-129).into(), 0.into()).unwrap_err(),
+            "b: int8 overflow: -129"
+        );
+        assert_eq!(
+            convert(0.into(), 0.into(), "1.5".into()).unwrap_err(),
+            "c: invalid integer: 1.5"
+        );
+    }
+
+    #[test]
+    fn test_malformed_schema_errors() {
+        let int_without_size = TypeSchema::Primitive {
+            name: "int".to_string(),
+            size: None,
+        };
+        let data = vec![vec![0x01]];
+        assert_eq!(
+            build_value(&int_without_size, &mut data.clone().into_iter()).unwrap_err(),
+            "size info lacked"
+        );
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        assert!(build_ui_fields(&int_without_size, &mut ref_data, "x").is_err());
+
+        let unknown = TypeSchema::Primitive {
+            name: "fixed".to_string(),
+            size: None,
+        };
+        assert_eq!(
+            build_value(&unknown, &mut data.clone().into_iter()).unwrap_err(),
+            "unknown primitive type: fixed"
+        );
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        assert!(build_ui_fields(&unknown, &mut ref_data, "x").is_err());
+
+        let empty_bool = vec![vec![]];
+        let bool_schema = TypeSchema::Primitive {
+            name: "bool".to_string(),
+            size: None,
+        };
+        assert_eq!(
+            build_value(&bool_schema, &mut empty_bool.clone().into_iter()).unwrap_err(),
+            "invalid bool len"
+        );
+        let mut ref_data = empty_bool.iter().map(|v| v.as_slice());
+        assert!(build_ui_fields(&bool_schema, &mut ref_data, "x").is_err());
+
+        for (raw, err) in [
+            (vec![2], "invalid bool value"),
+            (vec![1, 0], "invalid bool len"),
+        ] {
+            assert_eq!(
+                build_value(&bool_schema, &mut vec![raw.clone()].into_iter()).unwrap_err(),
+                err
+            );
+            let mut ref_data = core::iter::once(raw.as_slice());
+            assert_eq!(
+                build_ui_fields(&bool_schema, &mut ref_data, "x").unwrap_err(),
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_to_dyn_sol_type() {
+        let struct_defs = prepare_mail_struct_defs();
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+
+        let sol_type = schema_to_dyn_sol_type(&type_schema, &struct_defs).unwrap();
+        match &sol_type {
+            DynSolType::Tuple(types) => assert_eq!(types.len(), 6),
+            _ => panic!("Mail should be a tuple"),
+        }
+        assert_eq!(
+            sol_type.sol_type_name(),
+            "((string,address[]),(string,address[]),string,uint64,uint256,uint256)"
+        );
+
+        // decode an abi encoded blob of the same shape with alloy
+        let value = DynSolValue::Tuple(vec![
+            DynSolValue::Bool(true),
+            DynSolValue::FixedArray(vec![DynSolValue::String("a".to_string()); 2]),
+        ]);
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        struct_defs.insert(
+            "Test".to_string(),
+            vec![
+                Eip712FieldDefinition {
+                    name: "flag".to_string(),
+                    field_type: Eip712FieldType::Bool,
+                    array_levels: vec![],
+                },
+                Eip712FieldDefinition {
+                    name: "names".to_string(),
+                    field_type: Eip712FieldType::String,
+                    array_levels: vec![Eip712ArrayLevel::Fixed(2)],
+                },
+            ],
+        );
+        let type_schema = build_schema(&struct_defs, &"Test".to_string()).unwrap();
+        let sol_type = schema_to_dyn_sol_type(&type_schema, &struct_defs).unwrap();
+        assert_eq!(sol_type.sol_type_name(), "(bool,string[2])");
+        let decoded = sol_type
+            .abi_decode_params(&value.abi_encode_params())
+            .unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn test_build_value_strings() {
+        let struct_defs = prepare_mail_struct_defs();
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let resolver = build_resolver_from_struct_defs(&struct_defs).unwrap();
+        let typed = get_raw_mail_typed_data().expect("success");
+
+        let value = build_value(&type_schema, &mut prepare_mail_data().into_iter()).unwrap();
+        let string_value =
+            build_value_strings(&type_schema, &mut prepare_mail_data().into_iter()).unwrap();
+        assert_eq!(value["timestamp"], 1633072800);
+        assert_eq!(string_value["timestamp"], "1633072800");
+        assert_eq!(string_value["payback"], "4722366482869645213696");
 
-        let hash1 = typed.eip712_signing_hash().unwrap();
-        let maybe_hash2 = new_typed_data.eip712_signing_hash();
+        let mut hashes = vec![];
+        for message in [value, string_value] {
+            let typed_data = TypedData {
+                domain: typed.domain.clone(),
+                resolver: resolver.clone(),
+                primary_type: "Mail".to_string(),
+                message,
+            };
+            hashes.push(typed_data.eip712_signing_hash().unwrap());
+        }
+        assert_eq!(hashes[0], hashes[1]);
+        assert_eq!(hashes[0], typed.eip712_signing_hash().unwrap());
+    }
 
-        assert!(maybe_hash2.is_ok());
+    // lowercase hex strings and render every number as a decimal string
+    #[cfg(feature = "std")]
+    fn normalize_json(value: &serde_json::Value) -> serde_json::Value {
+        use serde_json::Value;
+        match value {
+            Value::Number(num) => Value::String(num.to_string()),
+            Value::String(s) if s.starts_with("0x") && s.len() != 42 => {
+                let num: alloy_primitives::U256 = s.parse().unwrap();
+                Value::String(num.to_string())
+            }
+            Value::String(s) => Value::String(s.to_lowercase()),
+            Value::Array(items) => Value::Array(items.iter().map(normalize_json).collect()),
+            Value::Object(obj) => Value::Object(
+                obj.iter()
+                    .map(|(k, v)| (k.clone(), normalize_json(v)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
 
-        assert_eq!(hash1, maybe_hash2.unwrap());
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_decode_to_json() {
+        let struct_defs = prepare_mail_struct_defs();
+        let json = decode_to_json(
+            &struct_defs,
+            &mut prepare_mail_data().into_iter(),
+            &"Mail".to_string(),
+        )
+        .unwrap();
+        assert!(json.contains("\n  \"timestamp\": \"1633072800\""));
+
+        let decoded: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let expected: serde_json::Value =
+            serde_json::from_str(include_str!("../res/mail.json")).unwrap();
+        assert_eq!(
+            normalize_json(&decoded),
+            normalize_json(&expected["message"])
+        );
     }
 
     #[test]
@@ -344,6 +1847,652 @@ mod tests {
         assert!(ui_fields.len() > 0);
     }
 
+    #[test]
+    fn test_build_value_invalid_int_size() {
+        for size in [0, 33] {
+            let schema = TypeSchema::Primitive {
+                name: "int".to_string(),
+                size: Some(size),
+            };
+            for data in [vec![], vec![0x01], vec![0xff; 34]] {
+                assert!(build_value(&schema, &mut vec![data.clone()].into_iter()).is_err());
+                assert!(build_value_strings(&schema, &mut vec![data].into_iter()).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_value_oversized_uint() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        for (name, size) in [("U8", 1u8), ("U128", 16), ("U256", 32)] {
+            struct_defs.insert(
+                name.to_string(),
+                vec![Eip712FieldDefinition {
+                    name: "value".to_string(),
+                    field_type: Eip712FieldType::Uint(size),
+                    array_levels: vec![],
+                }],
+            );
+
+            let type_schema = build_schema(&struct_defs, &name.to_string()).unwrap();
+
+            let fit = vec![vec![0x01; size as usize]];
+            assert!(build_value(&type_schema, &mut fit.into_iter()).is_ok());
+
+            let oversized = vec![vec![0x01; size as usize + 1]];
+            let err = build_value(&type_schema, &mut oversized.into_iter()).unwrap_err();
+            assert!(err.contains(&format!("uint{}", size as usize * 8)));
+
+            let oversized = [vec![0x01; size as usize + 1]];
+            let mut ref_data = oversized.iter().map(|v| v.as_slice());
+            assert!(build_ui_fields(&type_schema, &mut ref_data, "").is_err());
+        }
+    }
+
+    #[test]
+    fn test_build_ui_field_nested_bytes() {
+        let struct_defs = prepare_avatar_mail_struct_defs();
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+
+        let data = prepare_avatar_mail_data();
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields = build_ui_fields(&type_schema, &mut ref_data, "").unwrap();
+
+        let fields: Vec<(&str, &str)> = ui_fields
+            .iter()
+            .map(|f| (f.path.as_str(), f.value.as_ref()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("from.name", "Cow"),
+                ("from.avatar", "0x89504e470d0a1a0a"),
+                ("from.badge", "0xc0ffee01"),
+                ("to.name", "Bob"),
+                ("to.avatar", "0x"),
+                ("to.badge", "0xc0ffee02"),
+                ("contents", "Hello, Bob!"),
+            ]
+        );
+        assert_eq!(ui_fields[1].name, "avatar");
+
+        // bytes4 leaf with a wrong length
+        let mut data = prepare_avatar_mail_data();
+        data[2] = hex::decode("c0ffee").unwrap();
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let res = build_ui_fields(&type_schema, &mut ref_data, "");
+        assert_eq!(res.unwrap_err(), "invalid bytes len");
+    }
+
+    #[test]
+    fn test_build_ui_field_short_address() {
+        let struct_defs = prepare_mail_struct_defs();
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let data = prepare_mail_data();
+
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields = build_ui_fields(&type_schema, &mut ref_data, "").unwrap();
+        assert_eq!(ui_fields[1].path, "from.wallets.[]");
+        assert_eq!(
+            ui_fields[1].value,
+            "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826"
+        );
+
+        let opts = UIOptions {
+            address_display: AddressDisplay::Short { lead: 6, tail: 4 },
+            ..Default::default()
+        };
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields =
+            build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).unwrap();
+        assert_eq!(ui_fields[1].value, "0xcd2a\u{2026}d826");
+        let mut full = [0u8; 20];
+        full.copy_from_slice(&data[2]);
+        assert_eq!(ui_fields[1].kind, UIFieldKind::Address(full));
+        assert_eq!(ui_fields[0].kind, UIFieldKind::String);
+    }
+
+    struct StubLabels;
+
+    impl AddressLabels for StubLabels {
+        fn label(&self, addr: &[u8; 20]) -> Option<String> {
+            if addr[0] == 0xbb {
+                Some("Treasury".to_string())
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_ui_field_address_labels() {
+        let struct_defs = prepare_mail_struct_defs();
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let data = prepare_mail_data();
+        let opts = UIOptions {
+            address_display: AddressDisplay::Short { lead: 5, tail: 0 },
+            ..Default::default()
+        };
+
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields =
+            build_ui_fields_with_labels(&type_schema, &mut ref_data, "", &opts, &StubLabels)
+                .unwrap();
+        assert_eq!(ui_fields[4].path, "to.wallets.[]");
+        assert_eq!(ui_fields[4].value, "Treasury (0xbbb\u{2026})");
+        // unlabelled addresses are unchanged
+        assert_eq!(ui_fields[1].value, "0xcd2\u{2026}");
+        assert_eq!(ui_fields[6].value, "0xb0b\u{2026}");
+    }
+
+    #[test]
+    fn test_build_value_empty_primitives() {
+        let primitive = |name: &str| TypeSchema::Primitive {
+            name: name.to_string(),
+            size: None,
+        };
+        let value = |name: &str| build_value(&primitive(name), &mut vec![vec![]].into_iter());
+        assert_eq!(value("string").unwrap(), serde_json::json!(""));
+        assert_eq!(value("bytes").unwrap(), serde_json::json!("0x"));
+        // fixed size primitives need their bytes
+        assert_eq!(value("address").unwrap_err(), "invalid address len");
+        assert_eq!(value("bool").unwrap_err(), "invalid bool len");
+
+        let ui_value = |name: &str| {
+            let schema = primitive(name);
+            let data: Vec<&[u8]> = vec![&[]];
+            build_ui_fields(&schema, &mut data.into_iter(), "f")
+                .map(|fields| fields[0].value.to_string())
+        };
+        assert_eq!(ui_value("string").unwrap(), "");
+        assert_eq!(ui_value("bytes").unwrap(), "0x");
+        assert_eq!(ui_value("address").unwrap_err(), "invalid address len");
+        assert_eq!(ui_value("bool").unwrap_err(), "invalid bool len");
+    }
+
+    struct SideLabels;
+
+    impl FieldTransform for SideLabels {
+        fn transform(&self, path: &str, raw: &[u8], _default: &str) -> Option<String> {
+            match (path, raw) {
+                ("order.side", [0]) => Some("Buy".to_string()),
+                ("order.side", [1]) => Some("Sell".to_string()),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_mail_ui_transcript() {
+        let struct_defs = prepare_mail_struct_defs();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let data = prepare_mail_data();
+        let fields = build_ui_fields(&schema, &mut data.iter().map(|v| v.as_slice()), "").unwrap();
+        assert_eq!(
+            ui_fields_transcript(&fields),
+            concat!(
+                "from.name (string): Cow\n",
+                "from.wallets.[] (address): 0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826\n",
+                "from.wallets.[] (address): 0xdeadbeefdeadbeefdeadbeefdeadbeefdeadbeef\n",
+                "to.name (string): Bob\n",
+                "to.wallets.[] (address): 0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb\n",
+                "to.wallets.[] (address): 0xb0bdabea57b0bdabea57b0bdabea57b0bdabea57\n",
+                "to.wallets.[] (address): 0xb0b0b0b0b0b0b000000000000000000000000000\n",
+                "contents (string): Hello, Bob!\n",
+                "timestamp (uint): 1633072800\n",
+                "amount (uint): 1000000\n",
+                "payback (uint): 4722366482869645213696\n",
+            )
+        );
+        assert_eq!(ui_fields_transcript(&[]), "");
+
+        // a string value can not forge another line
+        let mut data = prepare_mail_data();
+        data[9] = b"hi\namount (uint): 1\r\\".to_vec();
+        let fields = build_ui_fields(&schema, &mut data.iter().map(|v| v.as_slice()), "").unwrap();
+        let transcript = ui_fields_transcript(&fields);
+        assert_eq!(transcript.lines().count(), fields.len());
+        assert!(transcript.contains("contents (string): hi\\namount (uint): 1\\r\\\\\n"));
+    }
+
+    #[test]
+    fn test_for_each_ui_field() {
+        let struct_defs = prepare_mail_struct_defs();
+        let schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let data = prepare_mail_data();
+        let expected =
+            build_ui_fields(&schema, &mut data.iter().map(|v| v.as_slice()), "").unwrap();
+
+        let mut fields = vec![];
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        for_each_ui_field(&schema, &mut ref_data, &mut |field| {
+            fields.push(field);
+            true
+        })
+        .unwrap();
+        assert_eq!(
+            ui_fields_transcript(&fields),
+            ui_fields_transcript(&expected)
+        );
+        assert!(ref_data.next().is_none());
+
+        // stop at the first to.wallets item, the array lengths are consumed on the way
+        let mut paths = vec![];
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        for_each_ui_field(&schema, &mut ref_data, &mut |field| {
+            paths.push(field.path);
+            paths.len() < 5
+        })
+        .unwrap();
+        assert_eq!(
+            paths,
+            [
+                "from.name",
+                "from.wallets.[]",
+                "from.wallets.[]",
+                "to.name",
+                "to.wallets.[]"
+            ]
+        );
+        assert_eq!(ref_data.next(), Some(data[7].as_slice()));
+
+        let mut truncated = data[..8].iter().map(|v| v.as_slice());
+        assert!(for_each_ui_field(&schema, &mut truncated, &mut |_| true).is_err());
+    }
+
+    #[test]
+    fn test_build_ui_fields_with_transform() {
+        let uint8 = || TypeSchema::Primitive {
+            name: "uint".to_string(),
+            size: Some(1),
+        };
+        let schema = TypeSchema::Struct {
+            name: "Order".to_string(),
+            fields: vec![
+                Field {
+                    name: "side".to_string(),
+                    ty: uint8(),
+                },
+                Field {
+                    name: "qty".to_string(),
+                    ty: uint8(),
+                },
+            ],
+        };
+
+        let data: Vec<&[u8]> = vec![&[1], &[1]];
+        let ui_fields = build_ui_fields_with_transform(
+            &schema,
+            &mut data.into_iter(),
+            "order",
+            &UIOptions::default(),
+            &SideLabels,
+        )
+        .unwrap();
+        assert_eq!(ui_fields[0].value, "Sell");
+        assert_eq!(ui_fields[0].kind, UIFieldKind::Uint);
+        // no override, the default formatting is kept
+        assert_eq!(ui_fields[1].value, "1");
+
+        let data: Vec<&[u8]> = vec![&[7], &[2]];
+        let ui_fields = build_ui_fields_with_transform(
+            &schema,
+            &mut data.into_iter(),
+            "order",
+            &UIOptions::default(),
+            &SideLabels,
+        )
+        .unwrap();
+        assert_eq!(ui_fields[0].value, "7");
+    }
+
+    #[test]
+    fn test_preview_message() {
+        let struct_defs = prepare_mail_struct_defs();
+        let typed = get_raw_mail_typed_data().unwrap();
+        let opts = UIOptions {
+            address_display: AddressDisplay::Short { lead: 6, tail: 4 },
+            ..Default::default()
+        };
+        let preview = preview_message(
+            &struct_defs,
+            &prepare_mail_data(),
+            &"Mail".to_string(),
+            typed.domain(),
+            &opts,
+        )
+        .unwrap();
+
+        assert_eq!(preview.primary_type, "Mail");
+        assert_eq!(preview.domain.name.as_deref(), Some("Simple Mail"));
+        assert_eq!(preview.domain.version.as_deref(), Some("1"));
+        assert_eq!(
+            preview.domain.chain_id,
+            Some(alloy_primitives::U256::from(1))
+        );
+        assert_eq!(preview.domain.network, Some("Ethereum"));
+        assert_eq!(
+            preview.domain.verifying_contract.as_deref(),
+            Some("0xcccc\u{2026}cccc")
+        );
+        assert_eq!(preview.fields.len(), 11);
+        assert_eq!(preview.fields[7].path, "contents");
+        assert_eq!(preview.fields[7].value, "Hello, Bob!");
+    }
+
+    #[test]
+    fn test_domain_summary_conflux_contract() {
+        let contract = alloy_primitives::address!("85d80245dc02f5a89589e1f19c5c718e405b56cd");
+        let domain = crate::Eip712Domain::new(
+            Some("Conflux Mail".into()),
+            None,
+            Some(alloy_primitives::U256::from(1029)),
+            Some(contract),
+            None,
+        );
+
+        let summary = domain_summary(&domain, &UIOptions::default());
+        assert_eq!(
+            summary.verifying_contract.as_deref(),
+            Some("0x85d80245dc02f5a89589e1f19c5c718e405b56cd")
+        );
+        assert_eq!(summary.network, Some("Conflux"));
+
+        let mut opts = UIOptions {
+            address_display: AddressDisplay::Conflux {
+                network_id: 1029,
+                verbose: false,
+            },
+            ..Default::default()
+        };
+        let summary = domain_summary(&domain, &opts);
+        assert_eq!(
+            summary.verifying_contract.as_deref(),
+            Some("cfx:acc7uawf5ubtnmezvhu9dhc6sghea0403y2dgpyfjp")
+        );
+
+        opts.address_display = AddressDisplay::Conflux {
+            network_id: 1029,
+            verbose: true,
+        };
+        let summary = domain_summary(&domain, &opts);
+        assert_eq!(
+            summary.verifying_contract.as_deref(),
+            Some("CFX:TYPE.CONTRACT:ACC7UAWF5UBTNMEZVHU9DHC6SGHEA0403Y2DGPYFJP")
+        );
+    }
+
+    #[test]
+    fn test_build_ui_field_hex_format() {
+        let struct_defs = prepare_mail_struct_defs();
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let data = prepare_mail_data();
+
+        let mut opts = UIOptions::default();
+        opts.field_formats
+            .insert("timestamp".to_string(), FieldFormat::Hex);
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields =
+            build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).unwrap();
+        assert_eq!(ui_fields[8].path, "timestamp");
+        // a uint64 shows its 8 bytes
+        assert_eq!(ui_fields[8].value, "0x000000006156b6a0");
+        assert_eq!(ui_fields[9].value, "1000000");
+
+        opts.hex_case = HexCase::Upper;
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields =
+            build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).unwrap();
+        assert_eq!(ui_fields[8].value, "0x000000006156B6A0");
+
+        // signed values show their two's complement in the declared size
+        let int_schema = TypeSchema::Primitive {
+            name: "int".to_string(),
+            size: Some(2),
+        };
+        let mut opts = UIOptions::default();
+        opts.field_formats
+            .insert("delta".to_string(), FieldFormat::Hex);
+        let raw = [0xffu8, 0x80];
+        let mut ref_data = [raw.as_slice()].into_iter();
+        let ui_fields =
+            build_ui_fields_with_options(&int_schema, &mut ref_data, "delta", &opts).unwrap();
+        assert_eq!(ui_fields[0].value, "0xff80");
+        opts.hex_case = HexCase::Upper;
+        let mut ref_data = [raw.as_slice()].into_iter();
+        let ui_fields =
+            build_ui_fields_with_options(&int_schema, &mut ref_data, "delta", &opts).unwrap();
+        assert_eq!(ui_fields[0].value, "0xFF80");
+
+        // hex is only for integers
+        opts.field_formats
+            .insert("contents".to_string(), FieldFormat::Hex);
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        assert!(build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).is_err());
+    }
+
+    #[test]
+    fn test_build_ui_field_fixed_point() {
+        let int_schema = TypeSchema::Primitive {
+            name: "int".to_string(),
+            size: Some(32),
+        };
+        let mut opts = UIOptions::default();
+        opts.field_formats
+            .insert("price".to_string(), FieldFormat::FixedPoint { decimals: 8 });
+        let ui_value = |raw: &[u8], opts: &UIOptions| {
+            let mut ref_data = [raw].into_iter();
+            let ui_fields =
+                build_ui_fields_with_options(&int_schema, &mut ref_data, "price", opts).unwrap();
+            ui_fields[0].value.to_string()
+        };
+
+        let price = I256::try_from(-123_456_789_012i64)
+            .unwrap()
+            .to_be_bytes::<32>();
+        assert_eq!(ui_value(&price, &opts), "-1234.56789012");
+        let price = I256::try_from(123_456_789_012i64)
+            .unwrap()
+            .to_be_bytes::<32>();
+        assert_eq!(ui_value(&price, &opts), "1234.56789012");
+
+        opts.field_formats.insert(
+            "price".to_string(),
+            FieldFormat::FixedPoint { decimals: 18 },
+        );
+        let price = I256::try_from(-2_500_000_000_000_000_000i128)
+            .unwrap()
+            .to_be_bytes::<32>();
+        assert_eq!(ui_value(&price, &opts), "-2.5");
+        let min = I256::MIN.to_be_bytes::<32>();
+        assert_eq!(
+            ui_value(&min, &opts),
+            "-57896044618658097711785492504343953926634992332820282019728.792003956564819968"
+        );
+
+        // unsigned values are shifted the same way
+        let struct_defs = prepare_mail_struct_defs();
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let data = prepare_mail_data();
+        let mut opts = UIOptions::default();
+        opts.field_formats.insert(
+            "amount".to_string(),
+            FieldFormat::FixedPoint { decimals: 8 },
+        );
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields =
+            build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).unwrap();
+        assert_eq!(ui_fields[9].value, "0.01");
+    }
+
+    #[test]
+    fn test_build_ui_field_prehashed() {
+        let struct_defs = prepare_avatar_mail_struct_defs();
+        let type_schema = build_schema(&struct_defs, &"Mail".to_string()).unwrap();
+        let mut data = prepare_avatar_mail_data();
+        data[1] = vec![0xab; 32];
+
+        // the display follows the leaves the encoder takes as hashes
+        let mut encode_opts = EncodeOptions::default();
+        encode_opts.prehashed.insert("from.avatar");
+        let opts = encode_opts.ui_options();
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields =
+            build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).unwrap();
+        assert_eq!(ui_fields[1].kind, UIFieldKind::Opaque);
+        assert_eq!(ui_fields[1].value, format!("opaque(0x{})", "ab".repeat(32)));
+
+        // a pre-hashed value must be a 32 bytes hash of a bytes or string leaf
+        data[1] = vec![0xab; 31];
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        assert!(build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).is_err());
+        let mut opts = UIOptions::default();
+        opts.prehashed.insert("from.badge");
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        assert!(build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).is_err());
+    }
+
+    #[test]
+    fn test_prehashed_paths() {
+        let mut prehashed = PrehashedPaths::default();
+        assert!(!prehashed.contains("attachments[0]"));
+        prehashed.insert("attachments[1]");
+        assert!(!prehashed.insert("attachments.[]"));
+        assert!(prehashed.contains("attachments[0]"));
+        assert!(prehashed.contains("attachments.[]"));
+        assert!(!prehashed.contains("attachments"));
+    }
+
+    #[test]
+    fn test_filter_path() {
+        assert_eq!(filter_path("from.wallets[1]"), "from.wallets.[]");
+        assert_eq!(filter_path("a[0][12].b"), "a.[].[].b");
+        assert_eq!(filter_path("[3]"), "[]");
+        assert_eq!(filter_path("contents"), "contents");
+        assert_eq!(filter_path("from.wallets.[]"), "from.wallets.[]");
+        assert_eq!(filter_path("a.[].[].b"), "a.[].[].b");
+    }
+
+    #[test]
+    fn test_build_ui_field_basis_points() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        struct_defs.insert(
+            "Swap".to_string(),
+            vec![
+                Eip712FieldDefinition {
+                    name: "fee".to_string(),
+                    field_type: Eip712FieldType::Uint(2),
+                    array_levels: vec![],
+                },
+                Eip712FieldDefinition {
+                    name: "slippage".to_string(),
+                    field_type: Eip712FieldType::Uint(32),
+                    array_levels: vec![],
+                },
+            ],
+        );
+        let type_schema = build_schema(&struct_defs, &"Swap".to_string()).unwrap();
+        let data = [hex::decode("1e").unwrap(), hex::decode("3039").unwrap()];
+
+        let mut opts = UIOptions::default();
+        opts.field_formats
+            .insert("fee".to_string(), FieldFormat::BasisPoints);
+        opts.field_formats
+            .insert("slippage".to_string(), FieldFormat::BasisPoints);
+
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields =
+            build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).unwrap();
+        assert_eq!(ui_fields[0].path, "fee");
+        assert_eq!(ui_fields[0].value, "0.30%");
+        assert_eq!(ui_fields[1].value, "123.45%");
+
+        // without the opt-in the raw integer is shown
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields = build_ui_fields(&type_schema, &mut ref_data, "").unwrap();
+        assert_eq!(ui_fields[0].value, "30");
+    }
+
+    #[test]
+    fn test_build_ui_field_hex_case() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        struct_defs.insert(
+            "Test".to_string(),
+            vec![Eip712FieldDefinition {
+                name: "hash".to_string(),
+                field_type: Eip712FieldType::FixedBytes(32),
+                array_levels: vec![],
+            }],
+        );
+        let type_schema = build_schema(&struct_defs, &"Test".to_string()).unwrap();
+        let data =
+            [
+                hex::decode("b5d679d10bf948280080e802ce9fde218b0f8c442c47bf4ab05657d8da04d1da")
+                    .unwrap(),
+            ];
+
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields = build_ui_fields(&type_schema, &mut ref_data, "").unwrap();
+        assert_eq!(
+            ui_fields[0].value,
+            "0xb5d679d10bf948280080e802ce9fde218b0f8c442c47bf4ab05657d8da04d1da"
+        );
+
+        let opts = UIOptions {
+            hex_case: HexCase::Upper,
+            ..Default::default()
+        };
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields =
+            build_ui_fields_with_options(&type_schema, &mut ref_data, "", &opts).unwrap();
+        assert_eq!(
+            ui_fields[0].value,
+            "0xB5D679D10BF948280080E802CE9FDE218B0F8C442C47BF4AB05657D8DA04D1DA"
+        );
+    }
+
+    #[test]
+    fn test_build_ui_field_signed_int() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        struct_defs.insert(
+            "Test".to_string(),
+            vec![
+                Eip712FieldDefinition {
+                    name: "neg192".to_string(),
+                    field_type: Eip712FieldType::Int(24),
+                    array_levels: vec![],
+                },
+                Eip712FieldDefinition {
+                    name: "pos192".to_string(),
+                    field_type: Eip712FieldType::Int(24),
+                    array_levels: vec![],
+                },
+                Eip712FieldDefinition {
+                    name: "neg40".to_string(),
+                    field_type: Eip712FieldType::Int(5),
+                    array_levels: vec![],
+                },
+                Eip712FieldDefinition {
+                    name: "neg8".to_string(),
+                    field_type: Eip712FieldType::Int(1),
+                    array_levels: vec![],
+                },
+            ],
+        );
+        let type_schema = build_schema(&struct_defs, &"Test".to_string()).unwrap();
+        let data = [
+            hex::decode("ffffffffffffffffffffffffffffffffffffffffffffff00").unwrap(),
+            hex::decode("0100").unwrap(),
+            hex::decode("ffffffff80").unwrap(),
+            hex::decode("f8").unwrap(),
+        ];
+
+        let mut ref_data = data.iter().map(|v| v.as_slice());
+        let ui_fields = build_ui_fields(&type_schema, &mut ref_data, "").unwrap();
+        let values: Vec<&str> = ui_fields.iter().map(|f| f.value.as_ref()).collect();
+        assert_eq!(values, ["-256", "256", "-128", "-8"]);
+    }
+
     #[test]
     fn test_signed_int() {
         let mut struct_defs: Eip712StructDefinitions = Default::default();
@@ -97,6 +97,69 @@ pub fn prepare_mail_struct_defs() -> Eip712StructDefinitions {
     struct_defs
 }
 
+// Mail(Person from,Person to,string contents)Person(string name,bytes avatar,bytes4 badge)
+pub fn prepare_avatar_mail_struct_defs() -> Eip712StructDefinitions {
+    let mut struct_defs: Eip712StructDefinitions = Default::default();
+
+    struct_defs.insert("EIP712Domain".to_string(), get_domain_struct_def());
+
+    struct_defs.insert(
+        "Mail".to_string(),
+        vec![
+            Eip712FieldDefinition {
+                name: "from".to_string(),
+                field_type: Eip712FieldType::Custom("Person".to_string()),
+                array_levels: vec![],
+            },
+            Eip712FieldDefinition {
+                name: "to".to_string(),
+                field_type: Eip712FieldType::Custom("Person".to_string()),
+                array_levels: vec![],
+            },
+            Eip712FieldDefinition {
+                name: "contents".to_string(),
+                field_type: Eip712FieldType::String,
+                array_levels: vec![],
+            },
+        ],
+    );
+
+    struct_defs.insert(
+        "Person".to_string(),
+        vec![
+            Eip712FieldDefinition {
+                name: "name".to_string(),
+                field_type: Eip712FieldType::String,
+                array_levels: vec![],
+            },
+            Eip712FieldDefinition {
+                name: "avatar".to_string(),
+                field_type: Eip712FieldType::DynamicBytes,
+                array_levels: vec![],
+            },
+            Eip712FieldDefinition {
+                name: "badge".to_string(),
+                field_type: Eip712FieldType::FixedBytes(4),
+                array_levels: vec![],
+            },
+        ],
+    );
+
+    struct_defs
+}
+
+pub fn prepare_avatar_mail_data() -> Vec<Vec<u8>> {
+    vec![
+        hex::decode("436f77").unwrap(),
+        hex::decode("89504e470d0a1a0a").unwrap(),
+        hex::decode("c0ffee01").unwrap(),
+        hex::decode("426f62").unwrap(),
+        hex::decode("").unwrap(),
+        hex::decode("c0ffee02").unwrap(),
+        hex::decode("48656c6c6f2c20426f6221").unwrap(),
+    ]
+}
+
 pub fn get_raw_mail_typed_data() -> Result<TypedData, String> {
     let json_str = include_str!("../res/mail.json");
     let typed: TypedData = serde_json::from_str(json_str).map_err(|_| "invalid json str")?;
@@ -120,3 +183,17 @@ pub fn prepare_mail_data() -> Vec<Vec<u8>> {
         hex::decode("01000000000000000000").unwrap(),
     ]
 }
+
+// offset of the first 32 bytes word that differs between two encodings, None if they are equal.
+// a length mismatch is reported at the first word missing from the shorter one
+pub fn compare_encoded(ours: &[u8], theirs: &[u8]) -> Option<usize> {
+    let mismatch = ours
+        .chunks(32)
+        .zip(theirs.chunks(32))
+        .position(|(a, b)| a != b);
+    match mismatch {
+        Some(word) => Some(word * 32),
+        None if ours.len() != theirs.len() => Some(ours.len().min(theirs.len()) / 32 * 32),
+        None => None,
+    }
+}
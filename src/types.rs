@@ -1,6 +1,10 @@
 use crate::{
-    CIP23_DOMAIN_TYPE_NAME, EIP712_DOMAIN_TYPE_NAME, Eip712Domain,
-    utils::{parse_u64, parse_utf8_string},
+    CIP23_DOMAIN_TYPE_NAME, EIP712_DOMAIN_TYPE_NAME, Eip712Domain, FLAG_ARRAY, FLAG_SIZE,
+    STRUCT_RECORD_FIELD, STRUCT_RECORD_NAME, STRUCT_RECORD_PRIMARY_TYPE, TYPE_ID_MASK,
+    utils::{
+        canonical_int_bytes, parse_u32, parse_u64, parse_u256_checked, parse_utf8_string,
+        significant_bytes,
+    },
 };
 use alloc::{
     borrow::ToOwned,
@@ -12,7 +16,7 @@ use alloc::{
 };
 use alloy_dyn_abi::{Eip712Types, PropertyDef, Resolver};
 use alloy_primitives::{Address, B256, U256, hex};
-use bytes::{Buf, Bytes, TryGetError};
+use bytes::{Buf, TryGetError};
 
 /// EIP-712 field type enumeration
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -50,6 +54,44 @@ impl Eip712FieldType {
         }
     }
 
+    /// Inverse of `type_id`, int, uint and fixed bytes require a size and custom types a name
+    pub fn from_type_id(
+        id: u8,
+        size: Option<u8>,
+        custom_name: Option<String>,
+    ) -> Result<Self, &'static str> {
+        let field_type = match id {
+            0 => Eip712FieldType::Custom(custom_name.ok_or("custom type must have a name")?),
+            1 => Eip712FieldType::Int(int_size(size.ok_or("Int type must specify size")?)?),
+            2 => Eip712FieldType::Uint(int_size(size.ok_or("UInt type must specify size")?)?),
+            3 => Eip712FieldType::Address,
+            4 => Eip712FieldType::Bool,
+            5 => Eip712FieldType::String,
+            6 => Eip712FieldType::FixedBytes(fixed_bytes_size(
+                size.ok_or("fixed bytes type must specify size")?,
+            )?),
+            7 => Eip712FieldType::DynamicBytes,
+            _ => return Err("Unknown field type"),
+        };
+        Ok(field_type)
+    }
+
+    /// A struct type, rejects names which are not identifiers or which parse as a primitive,
+    /// eg: `custom("uint256")` where `Uint(32)` was meant
+    pub fn custom(name: &str) -> Result<Self, String> {
+        let mut chars = name.chars();
+        let valid_start = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$');
+        if !valid_start || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$') {
+            return Err(format!("Invalid struct name: {}", name));
+        }
+        match Self::parse_base_field_type(name, &ParseOptions::default()) {
+            Ok(Eip712FieldType::Custom(_)) => Ok(Eip712FieldType::Custom(name.to_string())),
+            _ => Err(format!("Primitive type name used as a struct: {}", name)),
+        }
+    }
+
     /// Get the type size if applicable
     pub fn type_size(&self) -> Option<u8> {
         match self {
@@ -60,6 +102,34 @@ impl Eip712FieldType {
         }
     }
 
+    /// Whether the value is hashed with keccak256 instead of being encoded in place
+    pub fn is_dynamic(&self) -> bool {
+        matches!(
+            self,
+            Eip712FieldType::String | Eip712FieldType::DynamicBytes
+        )
+    }
+
+    /// Byte length of a raw value of this type, exact for address, bool, fixed bytes and the
+    /// empty struct marker, the maximum for ints whose leading bytes may be stripped, and None
+    /// for the dynamic `string` and `bytes`
+    pub fn expected_value_len(&self) -> Option<usize> {
+        match self {
+            Eip712FieldType::Address => Some(20),
+            Eip712FieldType::Bool => Some(1),
+            Eip712FieldType::Int(size)
+            | Eip712FieldType::Uint(size)
+            | Eip712FieldType::FixedBytes(size) => Some(*size as usize),
+            Eip712FieldType::Custom(_) => Some(0),
+            Eip712FieldType::String | Eip712FieldType::DynamicBytes => None,
+        }
+    }
+
+    /// Size of the member encoding, every EIP-712 member is encoded as one 32 bytes word
+    pub fn encoded_head_size(&self) -> usize {
+        32
+    }
+
     /// Get the type name for custom types
     pub fn custom_type_name(&self) -> Option<&str> {
         match self {
@@ -82,20 +152,34 @@ impl Eip712FieldType {
     }
 
     pub fn type_string_and_size(&self) -> (String, Option<u8>) {
+        let (name, size) = self.type_name_and_size();
+        (name.to_string(), size)
+    }
+
+    /// Borrowed form of `type_string_and_size`, eg: `("uint", Some(32))` for uint256
+    pub fn type_name_and_size(&self) -> (&str, Option<u8>) {
         let name = match self {
-            Eip712FieldType::Custom(name) => name.clone(),
-            Eip712FieldType::Int(_) => "int".to_string(),
-            Eip712FieldType::Uint(_) => "uint".to_string(),
-            Eip712FieldType::Address => "address".to_string(),
-            Eip712FieldType::Bool => "bool".to_string(),
-            Eip712FieldType::String => "string".to_string(),
-            Eip712FieldType::FixedBytes(_) => "bytes".to_string(),
-            Eip712FieldType::DynamicBytes => "bytes".to_string(),
+            Eip712FieldType::Custom(name) => name.as_str(),
+            Eip712FieldType::Int(_) => "int",
+            Eip712FieldType::Uint(_) => "uint",
+            Eip712FieldType::Address => "address",
+            Eip712FieldType::Bool => "bool",
+            Eip712FieldType::String => "string",
+            Eip712FieldType::FixedBytes(_) => "bytes",
+            Eip712FieldType::DynamicBytes => "bytes",
         };
         (name, self.type_size())
     }
 
     pub fn parse_field_type(type_str: &str) -> Result<Self, String> {
+        Self::parse_field_type_with_options(type_str, &ParseOptions::default())
+    }
+
+    /// Same as `parse_field_type` with the looser ABI aliases allowed by `opts`
+    pub fn parse_field_type_with_options(
+        type_str: &str,
+        opts: &ParseOptions,
+    ) -> Result<Self, String> {
         let type_str = type_str.trim();
 
         // Handle array types (e.g., "Person[]", "uint256[2]")
@@ -104,16 +188,21 @@ impl Eip712FieldType {
                 .split_once('[')
                 .ok_or_else(|| format!("Invalid array type format: {}", type_str))?;
 
-            let base_field_type = Self::parse_base_field_type(base_type)?;
+            let base_field_type = Self::parse_base_field_type(base_type, opts)?;
             return Ok(base_field_type);
         }
 
-        Self::parse_base_field_type(type_str)
+        Self::parse_base_field_type(type_str, opts)
     }
 
     /// Parse base field type (non-array)
-    fn parse_base_field_type(type_str: &str) -> Result<Self, String> {
+    fn parse_base_field_type(type_str: &str, opts: &ParseOptions) -> Result<Self, String> {
         match type_str {
+            "byte" if opts.allow_byte => Ok(Eip712FieldType::FixedBytes(1)),
+            "uint" if opts.allow_bare_int => Ok(Eip712FieldType::Uint(32)),
+            "int" if opts.allow_bare_int => Ok(Eip712FieldType::Int(32)),
+            // not a struct name either
+            "byte" => Err("byte is not an EIP-712 type, use bytes1".to_string()),
             "bool" => Ok(Eip712FieldType::Bool),
             "address" => Ok(Eip712FieldType::Address),
             "string" => Ok(Eip712FieldType::String),
@@ -155,6 +244,16 @@ impl Eip712FieldType {
     }
 }
 
+/// Solidity aliases accepted when parsing type strings, all rejected by default as EIP-712 only
+/// knows the explicit forms
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// `byte` as `bytes1`
+    pub allow_byte: bool,
+    /// bare `uint` and `int` as `uint256` and `int256`
+    pub allow_bare_int: bool,
+}
+
 /// EIP-712 array level type
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Eip712ArrayLevel {
@@ -196,7 +295,8 @@ pub struct Eip712FieldDefinition {
     pub field_type: Eip712FieldType,
     /// Field name
     pub name: String,
-    /// Array levels (empty if not an array)
+    /// Array levels (empty if not an array), from the innermost to the outermost like the
+    /// suffixes of the type string, eg: `[Dynamic, Fixed(2)]` is `T[][2]`, an array of 2 `T[]`
     pub array_levels: Vec<Eip712ArrayLevel>,
 }
 
@@ -229,6 +329,27 @@ impl Eip712FieldDefinition {
         !self.array_levels.is_empty()
     }
 
+    /// Iterate the array levels from the outermost to the innermost,
+    /// eg: `[2]`, `[]`, `[]` for `string[][][2]`
+    pub fn array_levels_iter(&self) -> impl DoubleEndedIterator<Item = &Eip712ArrayLevel> {
+        self.array_levels.iter().rev()
+    }
+
+    /// The element type once every array level is stripped, eg: `string` for `string[][][2]`
+    pub fn innermost_element_type(&self) -> &Eip712FieldType {
+        &self.field_type
+    }
+
+    /// Whether the member is hashed, arrays are always hashed regardless of the item type
+    pub fn is_dynamic(&self) -> bool {
+        self.is_array() || self.field_type.is_dynamic()
+    }
+
+    /// Size of the member encoding, arrays are encoded as the 32 bytes hash of their items
+    pub fn encoded_head_size(&self) -> usize {
+        self.field_type.encoded_head_size()
+    }
+
     pub fn type_string(&self) -> String {
         let mut type_str = self.field_type.type_string();
         for level in &self.array_levels {
@@ -245,71 +366,133 @@ impl Eip712FieldDefinition {
         PropertyDef::new(self.type_string(), self.name.clone()).map_err(|_| "invalid type")
     }
 
+    /// Check that `v` has the shape of a value of this field, an array field expects its one byte
+    /// size, a struct field the empty struct marker and a primitive its raw value
+    pub fn matches_value(&self, v: &Eip712FieldValue) -> Result<(), String> {
+        let err = |msg: String| Err(format!("{}: {}", self.name, msg));
+        let len = v.value.len();
+        if self.is_array() {
+            if !v.is_array_size || len != 1 {
+                return err("expected an array size".to_string());
+            }
+            return match self.array_levels.last() {
+                Some(Eip712ArrayLevel::Fixed(0)) => err("invalid fixed array size 0".to_string()),
+                Some(Eip712ArrayLevel::Fixed(size)) if v.value[0] != *size => {
+                    err(format!("expected {} items, got {}", size, v.value[0]))
+                }
+                _ => Ok(()),
+            };
+        }
+        if v.is_array_size {
+            return err("unexpected array size".to_string());
+        }
+        match &self.field_type {
+            Eip712FieldType::Custom(_) if len != 0 => err("expected an empty struct value".into()),
+            Eip712FieldType::Address if len != 20 => {
+                err(format!("expected 20 address bytes, got {}", len))
+            }
+            Eip712FieldType::Bool if !matches!(v.value.as_slice(), [0] | [1]) => {
+                err("expected a single 0x00 or 0x01 byte".to_string())
+            }
+            Eip712FieldType::FixedBytes(size) if len != *size as usize => {
+                err(format!("expected {} bytes, got {}", size, len))
+            }
+            // the value must fit the bit width, a longer int is accepted if it is sign extended
+            Eip712FieldType::Int(size)
+                if canonical_int_bytes(&v.value, *size as usize).is_err() =>
+            {
+                err(format!("value out of int{} range", *size as usize * 8))
+            }
+            Eip712FieldType::Uint(size) => match parse_u256_checked(&v.value, *size as usize) {
+                Ok(_) => Ok(()),
+                Err(msg) => err(msg),
+            },
+            Eip712FieldType::String if core::str::from_utf8(&v.value).is_err() => {
+                err("invalid UTF-8".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
     // the bytes is encoded as: https://github.com/LedgerHQ/app-ethereum/blob/develop/doc/ethapp.adoc#if-p2--struct-field
+    // bytes after the field are ignored
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
-        let get_err_str = |_: TryGetError| "bytes get u8 error";
+        let mut buf = bytes;
+        Eip712FieldDefinition::from_buf(&mut buf)
+    }
+
+    // same as from_bytes, but bytes left after the field are an error, to catch framing bugs
+    pub fn from_bytes_strict(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut buf = bytes;
+        let field = Eip712FieldDefinition::from_buf(&mut buf)?;
+        if buf.has_remaining() {
+            return Err("extra data after field definition");
+        }
+        Ok(field)
+    }
+
+    fn from_buf(buf: &mut &[u8]) -> Result<Self, &'static str> {
+        Eip712FieldDefinition::from_buf_with::<NameBuf>(buf)
+    }
 
-        let mut buf = Bytes::copy_from_slice(bytes);
+    // `B` is the scratch buffer of the names, see `NameBuf`
+    fn from_buf_with<B>(buf: &mut &[u8]) -> Result<Self, &'static str>
+    where
+        B: FromIterator<u8> + AsRef<[u8]> + AsMut<[u8]>,
+    {
+        let get_err_str = |_: TryGetError| "bytes get u8 error";
 
         // decode type info
         let type_desc = buf.try_get_u8().map_err(get_err_str)?;
-        let is_array = (type_desc & 0x80) == 0x80;
-        let is_type_size_specified = (type_desc & 0x40) == 0x40;
-        let field_type_id = type_desc & 0x0F;
-
-        let field_type = match field_type_id {
-            0 => {
-                let custom_name_len = buf.try_get_u8().map_err(get_err_str)? as usize;
-                if buf.remaining() < custom_name_len {
-                    return Err("Unexpected end of input when reading custom name");
-                }
-                let mut custom_name_bytes = vec![0u8; custom_name_len];
-                buf.copy_to_slice(&mut custom_name_bytes);
-                let custom_name = parse_utf8_string(&custom_name_bytes)?;
-                Eip712FieldType::Custom(custom_name)
+        let is_array = (type_desc & FLAG_ARRAY) == FLAG_ARRAY;
+        let is_type_size_specified = (type_desc & FLAG_SIZE) == FLAG_SIZE;
+        let field_type_id = type_desc & TYPE_ID_MASK;
+
+        let custom_name = if field_type_id == 0 {
+            let custom_name_len = buf.try_get_u8().map_err(get_err_str)? as usize;
+            if buf.remaining() < custom_name_len {
+                return Err("Unexpected end of input when reading custom name");
             }
-            1 => {
-                if !is_type_size_specified {
-                    return Err("Int type must specify size");
-                }
-                let type_size = buf.try_get_u8().map_err(get_err_str)?;
-                Eip712FieldType::Int(type_size)
-            }
-            2 => {
-                if !is_type_size_specified {
-                    return Err("UInt type must specify size");
-                }
-                let type_size = buf.try_get_u8().map_err(get_err_str)?;
-                Eip712FieldType::Uint(type_size)
-            }
-            3 => Eip712FieldType::Address,
-            4 => Eip712FieldType::Bool,
-            5 => Eip712FieldType::String,
-            6 => {
-                if !is_type_size_specified {
-                    return Err("fixed bytes type must specify size");
-                }
-                let type_size = buf.try_get_u8().map_err(get_err_str)?;
-                Eip712FieldType::FixedBytes(type_size)
-            }
-            7 => Eip712FieldType::DynamicBytes,
-            _ => return Err("Unknown field type"),
+            Some(read_name::<B>(buf, custom_name_len)?)
+        } else {
+            None
         };
+        // only int, uint and fixed bytes carry a size byte
+        let type_size = if is_type_size_specified && matches!(field_type_id, 1 | 2 | 6) {
+            Some(buf.try_get_u8().map_err(get_err_str)?)
+        } else {
+            None
+        };
+        let field_type = Eip712FieldType::from_type_id(field_type_id, type_size, custom_name)?;
 
         // decode array levels info
         let array_levels = if is_array {
             let mut levels = Vec::new();
-            let level_count = buf.try_get_u8().map_err(get_err_str)? as usize;
+            let level_count = buf
+                .try_get_u8()
+                .map_err(|_| "Unexpected end of input when reading array level count")?
+                as usize;
+            // every level takes at least its tag byte, and the name length byte follows
+            if level_count >= buf.remaining() {
+                return Err("Array level count exceeds the remaining input");
+            }
             for _ in 0..level_count {
-                let level_desc = buf.try_get_u8().map_err(get_err_str)?;
+                let level_desc = buf
+                    .try_get_u8()
+                    .map_err(|_| "Unexpected end of input when reading array level")?;
 
                 match level_desc {
                     0 => levels.push(Eip712ArrayLevel::Dynamic),
                     1 => {
-                        let size = buf.try_get_u8().map_err(get_err_str)?;
+                        let size = buf
+                            .try_get_u8()
+                            .map_err(|_| "Unexpected end of input when reading array level size")?;
+                        if size == 0 {
+                            return Err("Invalid array size");
+                        }
                         levels.push(Eip712ArrayLevel::Fixed(size));
                     }
-                    _ => return Err("Unknown array level type"),
+                    _ => return Err("Invalid array level tag"),
                 }
             }
             levels
@@ -322,9 +505,7 @@ impl Eip712FieldDefinition {
         if buf.remaining() < name_len {
             return Err("Unexpected end of input when reading field name");
         }
-        let mut name_bytes = vec![0u8; name_len];
-        buf.copy_to_slice(&mut name_bytes);
-        let name = parse_utf8_string(&name_bytes)?;
+        let name = read_name::<B>(buf, name_len)?;
 
         Ok(Eip712FieldDefinition {
             field_type,
@@ -333,6 +514,93 @@ impl Eip712FieldDefinition {
         })
     }
 
+    // the inverse of from_bytes, names longer than 255 bytes can not be encoded
+    pub fn to_bytes(&self) -> Result<Vec<u8>, &'static str> {
+        let mut type_desc = self.field_type.type_id();
+        if self.is_array() {
+            type_desc |= FLAG_ARRAY;
+        }
+        let type_size = self.field_type.type_size();
+        if type_size.is_some() {
+            type_desc |= FLAG_SIZE;
+        }
+
+        let mut bytes = vec![type_desc];
+        if let Some(custom_name) = self.field_type.custom_type_name() {
+            push_len_prefixed(&mut bytes, custom_name.as_bytes())?;
+        }
+        if let Some(size) = type_size {
+            bytes.push(size);
+        }
+        if self.is_array() {
+            let level_count =
+                u8::try_from(self.array_levels.len()).map_err(|_| "too many array levels")?;
+            bytes.push(level_count);
+            for level in &self.array_levels {
+                bytes.push(level.type_id());
+                if let Some(size) = level.size() {
+                    if size == 0 {
+                        return Err("Invalid array size");
+                    }
+                    bytes.push(size);
+                }
+            }
+        }
+        push_len_prefixed(&mut bytes, self.name.as_bytes())?;
+        Ok(bytes)
+    }
+
+    // length of the first encoded field definition in bytes, None if more bytes are needed.
+    // follows the same layout as from_bytes
+    pub fn encoded_len(bytes: &[u8]) -> Result<Option<usize>, &'static str> {
+        let Some(&type_desc) = bytes.first() else {
+            return Ok(None);
+        };
+        let is_array = (type_desc & FLAG_ARRAY) == FLAG_ARRAY;
+        let mut pos = 1;
+
+        match type_desc & TYPE_ID_MASK {
+            0 => {
+                let Some(&custom_name_len) = bytes.get(pos) else {
+                    return Ok(None);
+                };
+                pos += 1 + custom_name_len as usize;
+            }
+            1 | 2 | 6 => pos += 1,
+            3 | 4 | 5 | 7 => {}
+            _ => return Err("Unknown field type"),
+        }
+
+        if is_array {
+            let Some(&level_count) = bytes.get(pos) else {
+                return Ok(None);
+            };
+            pos += 1;
+            for _ in 0..level_count {
+                let Some(&level_desc) = bytes.get(pos) else {
+                    return Ok(None);
+                };
+                pos += 1;
+                match level_desc {
+                    0 => {}
+                    1 => pos += 1,
+                    _ => return Err("Invalid array level tag"),
+                }
+            }
+        }
+
+        let Some(&name_len) = bytes.get(pos) else {
+            return Ok(None);
+        };
+        pos += 1 + name_len as usize;
+
+        if bytes.len() < pos {
+            Ok(None)
+        } else {
+            Ok(Some(pos))
+        }
+    }
+
     fn parse_array_levels(type_str: &str) -> Result<Vec<Eip712ArrayLevel>, String> {
         let mut type_str = type_str.trim();
         let mut array_levels = Vec::new();
@@ -349,7 +617,9 @@ impl Eip712FieldDefinition {
             } else {
                 let size: u8 = array_spec
                     .parse()
-                    .map_err(|_| format!("Invalid array size: {}", array_spec))?;
+                    .ok()
+                    .filter(|size| *size > 0)
+                    .ok_or_else(|| format!("Invalid array size: {}", array_spec))?;
                 Eip712ArrayLevel::Fixed(size)
             };
 
@@ -364,10 +634,18 @@ impl Eip712FieldDefinition {
     }
 
     pub fn from_str(name: &str, type_name: &str) -> Result<Self, &'static str> {
+        Self::from_str_with_options(name, type_name, &ParseOptions::default())
+    }
+
+    pub fn from_str_with_options(
+        name: &str,
+        type_name: &str,
+        opts: &ParseOptions,
+    ) -> Result<Self, &'static str> {
         let array_levels =
             Self::parse_array_levels(type_name).map_err(|_| "invalid array levels")?;
-        let field_type =
-            Eip712FieldType::parse_field_type(type_name).map_err(|_| "invalid field type")?;
+        let field_type = Eip712FieldType::parse_field_type_with_options(type_name, opts)
+            .map_err(|_| "invalid field type")?;
         Ok(Eip712FieldDefinition {
             name: name.to_owned(),
             field_type,
@@ -376,6 +654,45 @@ impl Eip712FieldDefinition {
     }
 }
 
+// scratch buffer of a decoded name, kept on the stack with the `smallvec` feature
+// and spilled to the heap only for names longer than 32 bytes
+#[cfg(feature = "smallvec")]
+type NameBuf = smallvec::SmallVec<[u8; 32]>;
+#[cfg(not(feature = "smallvec"))]
+type NameBuf = Vec<u8>;
+
+// the caller checks that `len` bytes remain in `buf`
+fn read_name<B>(buf: &mut &[u8], len: usize) -> Result<String, &'static str>
+where
+    B: FromIterator<u8> + AsRef<[u8]> + AsMut<[u8]>,
+{
+    let mut name_bytes: B = core::iter::repeat_n(0u8, len).collect();
+    buf.copy_to_slice(name_bytes.as_mut());
+    parse_utf8_string(name_bytes.as_ref())
+}
+
+// an int or uint is 1 to 32 bytes wide
+fn int_size(size: u8) -> Result<u8, &'static str> {
+    if size == 0 || size > 32 {
+        return Err("int size should be in 1..=32");
+    }
+    Ok(size)
+}
+
+fn fixed_bytes_size(size: u8) -> Result<u8, &'static str> {
+    if size == 0 || size > 32 {
+        return Err("fixed bytes size should be in 1..=32");
+    }
+    Ok(size)
+}
+
+fn push_len_prefixed(bytes: &mut Vec<u8>, data: &[u8]) -> Result<(), &'static str> {
+    let len = u8::try_from(data.len()).map_err(|_| "name too long")?;
+    bytes.push(len);
+    bytes.extend_from_slice(data);
+    Ok(())
+}
+
 impl TryFrom<&PropertyDef> for Eip712FieldDefinition {
     type Error = &'static str;
 
@@ -384,6 +701,179 @@ impl TryFrom<&PropertyDef> for Eip712FieldDefinition {
     }
 }
 
+/// Incremental decoder of field definitions received across APDU chunks
+///
+/// A field definition may straddle a chunk boundary, the incomplete tail is buffered until
+/// the next chunk arrives. The stream can not be resumed after an error, every later call
+/// returns that error.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FieldDefStreamParser {
+    buf: Vec<u8>,
+    error: Option<&'static str>,
+}
+
+impl FieldDefStreamParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Feed a chunk, returns the field definitions completed by it
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<Eip712FieldDefinition>, &'static str> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        self.buf.extend_from_slice(chunk);
+        // the definitions decoded before the error are not returned, so their bytes must not
+        // be decoded again by a later push
+        self.decode().inspect_err(|err| {
+            self.error = Some(err);
+            self.buf.clear();
+        })
+    }
+
+    fn decode(&mut self) -> Result<Vec<Eip712FieldDefinition>, &'static str> {
+        let mut defs = Vec::new();
+        let mut start = 0;
+        while let Some(len) = Eip712FieldDefinition::encoded_len(&self.buf[start..])? {
+            defs.push(Eip712FieldDefinition::from_bytes(
+                &self.buf[start..start + len],
+            )?);
+            start += len;
+        }
+        self.buf.drain(..start);
+
+        Ok(defs)
+    }
+
+    /// Number of buffered bytes of an incomplete field definition
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// End of stream, errors if an incomplete field definition is left
+    pub fn finish(self) -> Result<(), &'static str> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if !self.buf.is_empty() {
+            return Err("Unexpected end of input in field definition stream");
+        }
+        Ok(())
+    }
+}
+
+/// Incremental decoder of a struct definition stream received across APDU chunks
+///
+/// The stream is a sequence of tagged records: a struct name starting a struct, the fields
+/// of the current struct, and an optional primary type record naming the struct to sign.
+/// The stream can not be resumed after an error, every later call returns that error.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StructDefStreamParser {
+    buf: Vec<u8>,
+    struct_defs: Eip712StructDefinitions,
+    current: Option<String>,
+    primary_type: Option<String>,
+    error: Option<&'static str>,
+}
+
+impl StructDefStreamParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Feed a chunk, records completed by it are applied
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), &'static str> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        self.buf.extend_from_slice(chunk);
+        // the records applied before the error must not be applied again by a later push
+        self.decode().inspect_err(|err| {
+            self.error = Some(err);
+            self.buf.clear();
+        })
+    }
+
+    fn decode(&mut self) -> Result<(), &'static str> {
+        let mut start = 0;
+        while let Some(len) = Self::record_len(&self.buf[start..])? {
+            let record = self.buf[start..start + len].to_vec();
+            self.apply(&record)?;
+            start += len;
+        }
+        self.buf.drain(..start);
+
+        Ok(())
+    }
+
+    // length of the record at the start of `buf`, None if incomplete
+    fn record_len(buf: &[u8]) -> Result<Option<usize>, &'static str> {
+        match buf.first() {
+            None => Ok(None),
+            Some(&STRUCT_RECORD_NAME | &STRUCT_RECORD_PRIMARY_TYPE) => Ok(buf
+                .get(1)
+                .map(|&len| 2 + len as usize)
+                .filter(|&len| len <= buf.len())),
+            Some(&STRUCT_RECORD_FIELD) => {
+                Ok(Eip712FieldDefinition::encoded_len(&buf[1..])?.map(|len| 1 + len))
+            }
+            Some(_) => Err("Invalid struct definition record tag"),
+        }
+    }
+
+    fn apply(&mut self, record: &[u8]) -> Result<(), &'static str> {
+        match record[0] {
+            STRUCT_RECORD_NAME => {
+                let name = parse_utf8_string(&record[2..])?;
+                if self.struct_defs.contains_key(&name) {
+                    return Err("Duplicate struct definition");
+                }
+                self.struct_defs.insert(name.clone(), Vec::new());
+                self.current = Some(name);
+            }
+            STRUCT_RECORD_PRIMARY_TYPE => {
+                if self.primary_type.is_some() {
+                    return Err("Duplicate primary type record");
+                }
+                self.primary_type = Some(parse_utf8_string(&record[2..])?);
+            }
+            _ => {
+                let field = Eip712FieldDefinition::from_bytes_strict(&record[1..])?;
+                let fields = self
+                    .current
+                    .as_ref()
+                    .and_then(|name| self.struct_defs.get_mut(name))
+                    .ok_or("Field definition before struct name")?;
+                fields.push(field);
+            }
+        }
+        Ok(())
+    }
+
+    /// Primary type named by the stream so far
+    pub fn primary_type(&self) -> Option<&str> {
+        self.primary_type.as_deref()
+    }
+
+    /// End of stream, returns the struct definitions and the primary type if one was named
+    ///
+    /// Errors if an incomplete record is left or the primary type is not a defined struct.
+    pub fn finish(self) -> Result<(Eip712StructDefinitions, Option<String>), &'static str> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        if !self.buf.is_empty() {
+            return Err("Unexpected end of input in struct definition stream");
+        }
+        match &self.primary_type {
+            Some(name) if !self.struct_defs.contains_key(name) => {
+                Err("Primary type is not a defined struct")
+            }
+            _ => Ok((self.struct_defs, self.primary_type)),
+        }
+    }
+}
+
 /// EIP-712 struct definition
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Eip712StructDefinition {
@@ -393,18 +883,202 @@ pub struct Eip712StructDefinition {
     pub fields: Vec<Eip712FieldDefinition>,
 }
 
+impl Eip712StructDefinition {
+    pub fn from_map_entry(name: String, fields: Vec<Eip712FieldDefinition>) -> Self {
+        Eip712StructDefinition { name, fields }
+    }
+
+    pub fn to_map_entry(self) -> (String, Vec<Eip712FieldDefinition>) {
+        (self.name, self.fields)
+    }
+}
+
 pub type Eip712StructDefinitions = BTreeMap<String, Vec<Eip712FieldDefinition>>;
 
+// a later definition replaces an earlier one of the same name
+pub fn defs_to_map(defs: Vec<Eip712StructDefinition>) -> Eip712StructDefinitions {
+    defs.into_iter()
+        .map(Eip712StructDefinition::to_map_entry)
+        .collect()
+}
+
+// the definitions are sorted by name
+pub fn map_to_defs(struct_defs: Eip712StructDefinitions) -> Vec<Eip712StructDefinition> {
+    struct_defs
+        .into_iter()
+        .map(|(name, fields)| Eip712StructDefinition::from_map_entry(name, fields))
+        .collect()
+}
+
+/// How struct definitions without fields are handled by `validate_struct_defs`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyStructPolicy {
+    /// Accepted, the spec permits `Empty()`
+    Allow,
+    /// Accepted, a warning naming the struct is returned
+    #[default]
+    Warn,
+    /// Rejected with an error naming the struct
+    Reject,
+}
+
+/// Options of `validate_struct_defs`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidateOptions {
+    pub empty_struct: EmptyStructPolicy,
+}
+
+// check every referenced custom type is defined and field names are unique,
+// returns the warnings of the accepted struct defs
+pub fn validate_struct_defs(
+    struct_defs: &Eip712StructDefinitions,
+    opts: &ValidateOptions,
+) -> Result<Vec<String>, String> {
+    let mut warnings = Vec::new();
+
+    for (struct_name, field_defs) in struct_defs {
+        if field_defs.is_empty() {
+            let msg = format!("{} has no fields", struct_name);
+            match opts.empty_struct {
+                EmptyStructPolicy::Allow => {}
+                EmptyStructPolicy::Warn => warnings.push(msg),
+                EmptyStructPolicy::Reject => return Err(msg),
+            }
+        }
+
+        for (i, field_def) in field_defs.iter().enumerate() {
+            let undefined_type = field_def
+                .field_type
+                .custom_type_name()
+                .filter(|custom_name| !struct_defs.contains_key(*custom_name));
+            if let Some(custom_name) = undefined_type {
+                return Err(format!(
+                    "{}.{} references undefined type {}",
+                    struct_name, field_def.name, custom_name
+                ));
+            }
+            if field_defs[..i].iter().any(|f| f.name == field_def.name) {
+                return Err(format!(
+                    "{} has duplicate field {}",
+                    struct_name, field_def.name
+                ));
+            }
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// A difference between two sets of struct definitions, as reported by `diff_struct_defs`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StructDiff {
+    StructAdded(String),
+    StructRemoved(String),
+    FieldAdded {
+        struct_name: String,
+        field: String,
+    },
+    FieldRemoved {
+        struct_name: String,
+        field: String,
+    },
+    /// The fields present in both are in a different order, which changes the hash
+    FieldsReordered {
+        struct_name: String,
+    },
+    TypeMismatch {
+        struct_name: String,
+        field: String,
+        a: String,
+        b: String,
+    },
+}
+
+// list what changes from `a` to `b`, field order matters as it changes the type hash
+pub fn diff_struct_defs(
+    a: &Eip712StructDefinitions,
+    b: &Eip712StructDefinitions,
+) -> Vec<StructDiff> {
+    let mut diffs = Vec::new();
+
+    for name in a.keys().filter(|name| !b.contains_key(*name)) {
+        diffs.push(StructDiff::StructRemoved(name.clone()));
+    }
+    for name in b.keys().filter(|name| !a.contains_key(*name)) {
+        diffs.push(StructDiff::StructAdded(name.clone()));
+    }
+
+    for (struct_name, a_fields) in a {
+        let Some(b_fields) = b.get(struct_name) else {
+            continue;
+        };
+        let find = |fields: &'_ [Eip712FieldDefinition], name: &str| {
+            fields.iter().position(|f| f.name == name)
+        };
+
+        for field in a_fields {
+            match find(b_fields, &field.name) {
+                None => diffs.push(StructDiff::FieldRemoved {
+                    struct_name: struct_name.clone(),
+                    field: field.name.clone(),
+                }),
+                Some(j) if b_fields[j].type_string() != field.type_string() => {
+                    diffs.push(StructDiff::TypeMismatch {
+                        struct_name: struct_name.clone(),
+                        field: field.name.clone(),
+                        a: field.type_string(),
+                        b: b_fields[j].type_string(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for field in b_fields {
+            if find(a_fields, &field.name).is_none() {
+                diffs.push(StructDiff::FieldAdded {
+                    struct_name: struct_name.clone(),
+                    field: field.name.clone(),
+                });
+            }
+        }
+
+        let a_common = a_fields
+            .iter()
+            .filter(|f| find(b_fields, &f.name).is_some());
+        let b_common = b_fields
+            .iter()
+            .filter(|f| find(a_fields, &f.name).is_some());
+        if !a_common.map(|f| &f.name).eq(b_common.map(|f| &f.name)) {
+            diffs.push(StructDiff::FieldsReordered {
+                struct_name: struct_name.clone(),
+            });
+        }
+    }
+
+    diffs
+}
+
+// convert the fields of a struct, on error returns the index and the type string of the bad field
+pub fn struct_to_property_defs(
+    defs: &[Eip712FieldDefinition],
+) -> Result<Vec<PropertyDef>, (usize, String)> {
+    defs.iter()
+        .enumerate()
+        .map(|(i, field)| field.to_proper_def().map_err(|_| (i, field.type_string())))
+        .collect()
+}
+
 pub fn build_resolver_from_struct_defs(
     struct_defs: &Eip712StructDefinitions,
-) -> Result<Resolver, &'static str> {
+) -> Result<Resolver, String> {
     let mut eip712_types: Eip712Types = Default::default();
     for (name, defs) in struct_defs.iter() {
-        let mut property_defs = Vec::new();
-        for field in defs {
-            let property_def = field.to_proper_def().unwrap();
-            property_defs.push(property_def);
-        }
+        let property_defs = struct_to_property_defs(defs).map_err(|(i, type_str)| {
+            format!(
+                "invalid type {} of field {}.{}",
+                type_str, name, defs[i].name
+            )
+        })?;
         eip712_types.insert(name.clone(), property_defs);
     }
     let resolver = Resolver::from(eip712_types);
@@ -415,19 +1089,32 @@ pub fn build_struct_defs_from_resolver(
     resolver: &Resolver,
 ) -> Result<Eip712StructDefinitions, &'static str> {
     let eip712_types = Eip712Types::from(resolver);
-    let struct_defs = eip712_types
-        .iter()
-        .map(|(name, fields)| {
-            let fs = fields
-                .iter()
-                .map(|f| Eip712FieldDefinition::try_from(f).expect("field def"))
-                .collect::<Vec<Eip712FieldDefinition>>();
-            (name.clone(), fs)
-        })
-        .collect();
+    let mut struct_defs = Eip712StructDefinitions::new();
+    for (name, fields) in eip712_types.iter() {
+        let fs = fields
+            .iter()
+            .map(Eip712FieldDefinition::try_from)
+            .collect::<Result<Vec<Eip712FieldDefinition>, _>>()?;
+        struct_defs.insert(name.clone(), fs);
+    }
     Ok(struct_defs)
 }
 
+// load struct defs from a json file holding either the `types` object or a whole typed data
+#[cfg(feature = "std")]
+pub fn load_struct_defs(path: &std::path::Path) -> Result<Eip712StructDefinitions, String> {
+    let json_str = std::fs::read_to_string(path)
+        .map_err(|err| format!("io error reading {}: {}", path.display(), err))?;
+    let mut value: serde_json::Value =
+        serde_json::from_str(&json_str).map_err(|err| format!("json error: {}", err))?;
+    if let Some(types) = value.get_mut("types") {
+        value = types.take();
+    }
+    let types: Eip712Types =
+        serde_json::from_value(value).map_err(|err| format!("json error: {}", err))?;
+    build_struct_defs_from_resolver(&Resolver::from(types)).map_err(|err| err.to_string())
+}
+
 /// EIP-712 struct implementation value
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Eip712FieldValue {
@@ -465,6 +1152,22 @@ impl Eip712FieldValue {
         }
     }
 
+    /// Create from a u256 value with leading zero bytes stripped (at least one byte is kept)
+    pub fn from_u256_minimal(value: U256) -> Self {
+        Eip712FieldValue {
+            value: Eip712FieldValue::from_u256(&value.to_be_bytes::<32>()).to_minimal_bytes(),
+            is_array_size: false,
+        }
+    }
+
+    /// The big-endian value without leading zero bytes, zero is kept as a single byte
+    pub fn to_minimal_bytes(&self) -> Vec<u8> {
+        match self.value.iter().position(|b| *b != 0) {
+            Some(start) => self.value[start..].to_vec(),
+            None => vec![0],
+        }
+    }
+
     /// Create from an address
     pub fn from_address(address: &[u8; 20]) -> Self {
         Eip712FieldValue {
@@ -473,6 +1176,29 @@ impl Eip712FieldValue {
         }
     }
 
+    /// Create from a bytesN value, N must be in 1..=32
+    pub fn from_fixed_bytes<const N: usize>(arr: [u8; N]) -> Self {
+        const { assert!(N >= 1 && N <= 32, "fixed bytes size should be in 1..=32") };
+        Eip712FieldValue {
+            value: arr.to_vec(),
+            is_array_size: false,
+        }
+    }
+
+    /// Create from a bytes32 value, eg: a hash or a salt
+    pub fn from_bytes32(arr: [u8; 32]) -> Self {
+        Eip712FieldValue::from_fixed_bytes(arr)
+    }
+
+    pub fn from_b256(value: B256) -> Self {
+        Eip712FieldValue::from_bytes32(value.0)
+    }
+
+    /// Read a bytes32 value, the data must be exactly 32 bytes
+    pub fn to_b256(&self) -> Result<B256, &'static str> {
+        B256::try_from(self.value.as_slice()).map_err(|_| "invalid bytes32 len")
+    }
+
     /// Create from a boolean
     pub fn from_bool(value: bool) -> Self {
         Eip712FieldValue {
@@ -481,6 +1207,20 @@ impl Eip712FieldValue {
         }
     }
 
+    /// Create from a boolean, always a single `0x01` or `0x00` byte which `to_bool` reads back
+    pub fn from_bool_strict(value: bool) -> Self {
+        Eip712FieldValue::from_bool(value)
+    }
+
+    /// Read a boolean, only a single `0x00` or `0x01` byte is accepted
+    pub fn to_bool(&self) -> Result<bool, &'static str> {
+        match self.value.as_slice() {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err("invalid bool data"),
+        }
+    }
+
     /// Create from a uint value (defaults to 8-byte u64)
     pub fn from_uint(value: u64) -> Self {
         Eip712FieldValue {
@@ -503,6 +1243,15 @@ impl Eip712FieldValue {
         }
     }
 
+    /// Same as `from_uint_sized` but errors instead of truncating a value longer than `size` bytes
+    pub fn try_from_uint_sized(size: u8, value: u64) -> Result<Self, &'static str> {
+        let needed = significant_bytes(&value.to_be_bytes());
+        if needed > size as usize {
+            return Err("uint value overflows size");
+        }
+        Ok(Eip712FieldValue::from_uint_sized(size, value))
+    }
+
     pub fn to_u64(self) -> Result<u64, &'static str> {
         parse_u64(&self.value)
     }
@@ -544,6 +1293,23 @@ impl Eip712FieldValue {
         })
     }
 
+    /// Create from a hex string, the 0x prefix is optional
+    pub fn from_hex(s: &str) -> Result<Self, String> {
+        let hex_str = s.strip_prefix("0x").unwrap_or(s);
+        let bytes = hex::decode(hex_str).map_err(|e| format!("Invalid hex: {}", e))?;
+        Ok(Eip712FieldValue {
+            value: bytes,
+            is_array_size: false,
+        })
+    }
+
+    /// 0x prefixed lowercase hex of the raw value
+    pub fn to_hex(&self) -> String {
+        let mut hex_str = String::from("0x");
+        hex_str.push_str(&hex::encode(&self.value));
+        hex_str
+    }
+
     pub fn to_address_string(&self) -> Result<String, &str> {
         if self.value.len() != 20 {
             return Err("invalid address len");
@@ -633,13 +1399,29 @@ impl Eip712StructImplementation {
                     eip712_domain.version = Some(version_value.into());
                 }
                 "chainId" => {
-                    let chain_id = value.to_u64()?;
-                    eip712_domain.chain_id = Some(U256::from(chain_id));
+                    let invalid = "invalid domain field: chainId";
+                    if value.value.is_empty() {
+                        return Err(invalid);
+                    }
+                    // read with the declared width, the value may have its leading zeros stripped
+                    let chain_id = match def.field_type {
+                        Eip712FieldType::Uint(4) => {
+                            let start = 4usize.checked_sub(value.value.len()).ok_or(invalid)?;
+                            let mut buf = [0u8; 4];
+                            buf[start..].copy_from_slice(&value.value);
+                            U256::from(parse_u32(&buf)?)
+                        }
+                        Eip712FieldType::Uint(size) => {
+                            parse_u256_checked(&value.value, size as usize).map_err(|_| invalid)?
+                        }
+                        _ => return Err(invalid),
+                    };
+                    eip712_domain.chain_id = Some(chain_id);
                 }
                 "verifyingContract" => {
                     let raw_addr_value = value.value;
                     if raw_addr_value.len() != 20 {
-                        return Err("invalid address len");
+                        return Err("invalid domain field: verifyingContract");
                     }
                     let mut buf = [0u8; 20];
                     buf.copy_from_slice(&raw_addr_value);
@@ -648,16 +1430,13 @@ impl Eip712StructImplementation {
                 "salt" => {
                     let raw_hash_value = value.value;
                     if raw_hash_value.len() != 32 {
-                        return Err("invalid hash len");
+                        return Err("invalid domain field: salt");
                     }
                     let mut buf = [0u8; 32];
                     buf.copy_from_slice(&raw_hash_value);
                     eip712_domain.salt = Some(B256::from(buf));
                 }
-                _ => {
-                    // should not happen
-                    unreachable!();
-                }
+                _ => return Err("invalid domain field"),
             }
         }
 
@@ -667,14 +1446,51 @@ impl Eip712StructImplementation {
 
 pub type Eip712StructImplementations = BTreeMap<String, Vec<Eip712FieldValue>>;
 
+// flag suspicious domain fields for the host to warn the user, the domain is still hashed as is.
+// absent fields are valid and not reported
+pub fn validate_domain(domain: &Eip712Domain) -> Vec<String> {
+    let mut warnings = vec![];
+    if domain
+        .name
+        .as_ref()
+        .is_some_and(|name| name.trim().is_empty())
+    {
+        warnings.push("domain name is empty".to_string());
+    }
+    if domain
+        .version
+        .as_ref()
+        .is_some_and(|version| version.trim().is_empty())
+    {
+        warnings.push("domain version is empty".to_string());
+    }
+    if domain.chain_id.is_some_and(|chain_id| chain_id.is_zero()) {
+        warnings.push("domain chainId is zero".to_string());
+    }
+    if domain
+        .verifying_contract
+        .is_some_and(|contract| contract.is_zero())
+    {
+        warnings.push("domain verifyingContract is the zero address".to_string());
+    }
+    warnings
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::{
         Eip712ArrayLevel, Eip712FieldDefinition, Eip712FieldType, Eip712FieldValue,
-        Eip712StructImplementation,
+        Eip712StructDefinition, Eip712StructDefinitions, Eip712StructImplementation,
+        EmptyStructPolicy, FieldDefStreamParser, ParseOptions, StructDefStreamParser, StructDiff,
+        ValidateOptions, build_resolver_from_struct_defs, defs_to_map, diff_struct_defs,
+        map_to_defs, struct_to_property_defs, validate_domain, validate_struct_defs,
+    };
+    use crate::{
+        FLAG_ARRAY, FLAG_SIZE, TYPE_ID_MASK,
+        utils::{parse_u64, parse_u256},
     };
-    use alloy_primitives::hex;
+    use alloy_primitives::{U256, hex, keccak256};
 
     #[test]
     fn test_field_definition_from_types_eip712_doamin_type() {
@@ -749,6 +1565,38 @@ mod tests {
         assert_eq!(field_def.field_type, Eip712FieldType::Uint(1));
     }
 
+    #[test]
+    fn test_type_desc_consts() {
+        // uint128, int8, address and Person[] descriptors from the fixtures above
+        let uint128 = hex::decode("42100775696e74313238").unwrap();
+        assert_eq!(uint128[0], FLAG_SIZE | 2);
+        let int8 = hex::decode("410104696e7438").unwrap();
+        assert_eq!(int8[0] & TYPE_ID_MASK, 1);
+        let persons = hex::decode("8006506572736f6e0100026363").unwrap();
+        assert_eq!(persons[0], FLAG_ARRAY);
+        assert_eq!(FLAG_ARRAY & FLAG_SIZE, 0);
+        assert_eq!((FLAG_ARRAY | FLAG_SIZE) & TYPE_ID_MASK, 0);
+
+        for (field_type, type_id) in [
+            (Eip712FieldType::Custom("Person".to_string()), 0),
+            (Eip712FieldType::Int(1), 1),
+            (Eip712FieldType::Uint(32), 2),
+            (Eip712FieldType::Address, 3),
+            (Eip712FieldType::Bool, 4),
+            (Eip712FieldType::String, 5),
+            (Eip712FieldType::FixedBytes(4), 6),
+            (Eip712FieldType::DynamicBytes, 7),
+        ] {
+            let sized = field_type.type_size().is_some();
+            let def = Eip712FieldDefinition::new(field_type, "f".to_string())
+                .with_array_level(Eip712ArrayLevel::Dynamic);
+            let type_desc = def.to_bytes().unwrap()[0];
+            assert_eq!(type_desc & TYPE_ID_MASK, type_id);
+            assert_eq!(type_desc & FLAG_ARRAY, FLAG_ARRAY);
+            assert_eq!(type_desc & FLAG_SIZE == FLAG_SIZE, sized);
+        }
+    }
+
     #[test]
     fn test_field_definition_from_types_bool() {
         let data = hex::decode("0404626f6f6c").expect("success");
@@ -863,4 +1711,1005 @@ mod tests {
             .expect("sucess");
         assert_eq!(eip712_domain.name.unwrap(), "Simple Mail");
     }
+
+    #[test]
+    fn test_field_value_minimal_bytes() {
+        let value = Eip712FieldValue::from_u256_minimal(U256::from(256));
+        assert_eq!(value.value, [0x01, 0x00]);
+        assert_eq!(parse_u256(&value.value).unwrap(), U256::from(256));
+
+        let value = Eip712FieldValue::from_u256_minimal(U256::ZERO);
+        assert_eq!(value.value, [0x00]);
+
+        let value = Eip712FieldValue::from_u256_minimal(U256::MAX);
+        assert_eq!(value.value, [0xff; 32]);
+
+        let value = Eip712FieldValue::from_uint_sized(8, 0x0f4240);
+        assert_eq!(value.to_minimal_bytes(), [0x0f, 0x42, 0x40]);
+        assert_eq!(parse_u64(&value.to_minimal_bytes()).unwrap(), 0x0f4240);
+        assert_eq!(Eip712FieldValue::new(vec![]).to_minimal_bytes(), [0x00]);
+    }
+
+    #[test]
+    fn test_field_value_bool() {
+        assert_eq!(Eip712FieldValue::from_bool_strict(true).to_bool(), Ok(true));
+        assert_eq!(
+            Eip712FieldValue::from_bool_strict(false).to_bool(),
+            Ok(false)
+        );
+        assert_eq!(
+            Eip712FieldValue::from_bytes(vec![0x02]).to_bool(),
+            Err("invalid bool data")
+        );
+        assert_eq!(
+            Eip712FieldValue::from_bytes(vec![]).to_bool(),
+            Err("invalid bool data")
+        );
+        assert_eq!(
+            Eip712FieldValue::from_bytes(vec![0x00, 0x01]).to_bool(),
+            Err("invalid bool data")
+        );
+    }
+
+    #[test]
+    fn test_field_type_from_type_id() {
+        let types = [
+            Eip712FieldType::Custom("Person".to_string()),
+            Eip712FieldType::Int(16),
+            Eip712FieldType::Uint(32),
+            Eip712FieldType::Address,
+            Eip712FieldType::Bool,
+            Eip712FieldType::String,
+            Eip712FieldType::FixedBytes(4),
+            Eip712FieldType::DynamicBytes,
+        ];
+        for field_type in types {
+            let custom_name = field_type.custom_type_name().map(|name| name.to_string());
+            assert_eq!(
+                Eip712FieldType::from_type_id(
+                    field_type.type_id(),
+                    field_type.type_size(),
+                    custom_name
+                ),
+                Ok(field_type)
+            );
+        }
+
+        assert_eq!(
+            Eip712FieldType::from_type_id(0, None, None),
+            Err("custom type must have a name")
+        );
+        assert_eq!(
+            Eip712FieldType::from_type_id(1, None, None),
+            Err("Int type must specify size")
+        );
+        assert_eq!(
+            Eip712FieldType::from_type_id(2, None, None),
+            Err("UInt type must specify size")
+        );
+        for (id, size) in [(1, 0), (1, 33), (2, 0), (2, 33)] {
+            assert_eq!(
+                Eip712FieldType::from_type_id(id, Some(size), None),
+                Err("int size should be in 1..=32")
+            );
+        }
+        assert_eq!(
+            Eip712FieldType::from_type_id(6, None, None),
+            Err("fixed bytes type must specify size")
+        );
+        for size in [0, 33, 255] {
+            assert_eq!(
+                Eip712FieldType::from_type_id(6, Some(size), None),
+                Err("fixed bytes size should be in 1..=32")
+            );
+        }
+        assert_eq!(
+            Eip712FieldType::from_type_id(8, None, None),
+            Err("Unknown field type")
+        );
+
+        // the sizes are checked when decoded: uint0, int33, bytes0, bytes33 and bytes255 a
+        for (hex_def, err) in [
+            ("42000161", "int size should be in 1..=32"),
+            ("41210161", "int size should be in 1..=32"),
+            ("46000161", "fixed bytes size should be in 1..=32"),
+            ("46210161", "fixed bytes size should be in 1..=32"),
+            ("46ff0161", "fixed bytes size should be in 1..=32"),
+        ] {
+            assert_eq!(
+                Eip712FieldDefinition::from_bytes(&hex::decode(hex_def).unwrap()),
+                Err(err)
+            );
+        }
+        assert!(Eip712FieldDefinition::from_bytes(&hex::decode("46200161").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_field_def_matches_value() {
+        let def = |field_type| Eip712FieldDefinition::new(field_type, "f".to_string());
+        let bytes = |value: &[u8]| Eip712FieldValue::new(value.to_vec());
+
+        let address = def(Eip712FieldType::Address);
+        assert_eq!(address.matches_value(&bytes(&[0x11; 20])), Ok(()));
+        assert_eq!(
+            address.matches_value(&bytes(&[0x11; 19])),
+            Err("f: expected 20 address bytes, got 19".to_string())
+        );
+
+        let bool_def = def(Eip712FieldType::Bool);
+        assert_eq!(
+            bool_def.matches_value(&Eip712FieldValue::from_bool(true)),
+            Ok(())
+        );
+        assert!(bool_def.matches_value(&bytes(&[2])).is_err());
+        assert!(bool_def.matches_value(&bytes(&[0, 1])).is_err());
+
+        let bytes4 = def(Eip712FieldType::FixedBytes(4));
+        assert_eq!(bytes4.matches_value(&bytes(&[1, 2, 3, 4])), Ok(()));
+        assert_eq!(
+            bytes4.matches_value(&bytes(&[1, 2, 3])),
+            Err("f: expected 4 bytes, got 3".to_string())
+        );
+
+        let uint8 = def(Eip712FieldType::Uint(1));
+        assert_eq!(uint8.matches_value(&bytes(&[0xff])), Ok(()));
+        assert_eq!(uint8.matches_value(&bytes(&[])), Ok(()));
+        assert_eq!(
+            uint8.matches_value(&bytes(&[0x01, 0xff])),
+            Err("f: uint8 value len 2 exceeds 1 bytes".to_string())
+        );
+        let int8 = def(Eip712FieldType::Int(1));
+        assert_eq!(int8.matches_value(&bytes(&[0x80])), Ok(()));
+        assert_eq!(int8.matches_value(&bytes(&[0x00, 0x7f])), Ok(()));
+        // -1 sign extended to 32 bytes still fits
+        assert_eq!(int8.matches_value(&bytes(&[0xff; 32])), Ok(()));
+        // 255 and -129 do not fit int8
+        assert_eq!(
+            int8.matches_value(&bytes(&[0x00, 0xff])),
+            Err("f: value out of int8 range".to_string())
+        );
+        assert!(int8.matches_value(&bytes(&[0xff, 0x7f])).is_err());
+        let int16 = def(Eip712FieldType::Int(2));
+        assert_eq!(int16.matches_value(&bytes(&[0xff, 0x00])), Ok(()));
+        assert!(int16.matches_value(&bytes(&[0x01, 0xff, 0xff])).is_err());
+
+        let string = def(Eip712FieldType::String);
+        assert_eq!(
+            string.matches_value(&Eip712FieldValue::from_string("hi")),
+            Ok(())
+        );
+        assert!(string.matches_value(&bytes(&[0xff])).is_err());
+        assert_eq!(
+            def(Eip712FieldType::DynamicBytes).matches_value(&bytes(&[])),
+            Ok(())
+        );
+
+        let person = def(Eip712FieldType::Custom("Person".to_string()));
+        assert_eq!(
+            person.matches_value(&Eip712FieldValue::from_struct()),
+            Ok(())
+        );
+        assert!(person.matches_value(&bytes(&[1])).is_err());
+
+        // arrays expect their size, a fixed outermost level its exact size
+        let array_size = Eip712FieldValue {
+            value: vec![2],
+            is_array_size: true,
+        };
+        let wallets = def(Eip712FieldType::Address).with_array_level(Eip712ArrayLevel::Dynamic);
+        assert_eq!(wallets.matches_value(&array_size), Ok(()));
+        assert!(wallets.matches_value(&bytes(&[0x11; 20])).is_err());
+        assert!(address.matches_value(&array_size).is_err());
+        let pair = def(Eip712FieldType::Address).with_array_level(Eip712ArrayLevel::Fixed(3));
+        assert_eq!(
+            pair.matches_value(&array_size),
+            Err("f: expected 3 items, got 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_field_value_try_from_uint_sized() {
+        let value = Eip712FieldValue::try_from_uint_sized(8, u64::MAX).unwrap();
+        assert_eq!(value, Eip712FieldValue::from_uint_sized(8, u64::MAX));
+        assert_eq!(
+            Eip712FieldValue::try_from_uint_sized(1, 255).unwrap().value,
+            vec![0xff]
+        );
+        assert_eq!(
+            Eip712FieldValue::try_from_uint_sized(1, 256),
+            Err("uint value overflows size")
+        );
+        // the lenient constructor keeps the low bytes
+        assert_eq!(Eip712FieldValue::from_uint_sized(1, 256).value, vec![0]);
+    }
+
+    #[test]
+    fn test_field_value_fixed_bytes() {
+        let hash = keccak256("salt");
+        let value = Eip712FieldValue::from_b256(hash);
+        assert_eq!(value.value, hash.to_vec());
+        assert_eq!(value.to_b256(), Ok(hash));
+        assert_eq!(Eip712FieldValue::from_bytes32(hash.0), value);
+
+        let selector = Eip712FieldValue::from_fixed_bytes([0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(selector.value, vec![0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(selector.to_b256(), Err("invalid bytes32 len"));
+    }
+
+    #[test]
+    fn test_field_value_hex() {
+        let hex_str = "0xb5d679d10bf948280080e802ce9fde218b0f8c442c47bf4ab05657d8da04d1da";
+        let value = Eip712FieldValue::from_hex(hex_str).unwrap();
+        assert_eq!(value.value.len(), 32);
+        assert_eq!(value.to_hex(), hex_str);
+
+        let value = Eip712FieldValue::from_hex(&hex_str[2..].to_uppercase()).unwrap();
+        assert_eq!(value.to_hex(), hex_str);
+
+        assert_eq!(Eip712FieldValue::from_hex("0x").unwrap().to_hex(), "0x");
+        assert!(Eip712FieldValue::from_hex("0xabc").is_err());
+        assert!(Eip712FieldValue::from_hex("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_field_def_stream_parser() {
+        let fixtures = [
+            "05046e616d65",
+            "422007636861696e4964",
+            "0311766572696679696e67436f6e7472616374",
+            "0006506572736f6e0466726f6d",
+            "84020001020f626f6f6c5f617272325f6669786564",
+            "8006506572736f6e0100026363",
+            "460106627974657331",
+        ];
+        let expected: Vec<Eip712FieldDefinition> = fixtures
+            .iter()
+            .map(|f| Eip712FieldDefinition::from_bytes(&hex::decode(f).unwrap()).unwrap())
+            .collect();
+        let stream = hex::decode(fixtures.concat()).unwrap();
+
+        for fixture in fixtures {
+            let data = hex::decode(fixture).unwrap();
+            assert_eq!(
+                Eip712FieldDefinition::encoded_len(&data).unwrap(),
+                Some(data.len())
+            );
+            assert_eq!(
+                Eip712FieldDefinition::encoded_len(&data[..data.len() - 1]).unwrap(),
+                None
+            );
+        }
+
+        // split into two chunks at every possible boundary
+        for split in 0..=stream.len() {
+            let mut parser = FieldDefStreamParser::new();
+            let mut defs = parser.push(&stream[..split]).unwrap();
+            defs.extend(parser.push(&stream[split..]).unwrap());
+            assert_eq!(defs, expected);
+            parser.finish().unwrap();
+        }
+
+        // one byte at a time
+        let mut parser = FieldDefStreamParser::new();
+        let mut defs = vec![];
+        for b in &stream {
+            defs.extend(parser.push(&[*b]).unwrap());
+        }
+        assert_eq!(defs, expected);
+
+        // truncated stream
+        let mut parser = FieldDefStreamParser::new();
+        parser.push(&stream[..stream.len() - 1]).unwrap();
+        assert_eq!(parser.pending(), 8);
+        assert!(parser.finish().is_err());
+
+        let mut parser = FieldDefStreamParser::new();
+        assert!(parser.push(&[0x08]).is_err());
+
+        // a chunk with a valid definition followed by an invalid one fails as a whole, the
+        // stream can not be resumed to decode the valid one twice
+        let mut parser = FieldDefStreamParser::new();
+        let mut chunk = hex::decode(fixtures[0]).unwrap();
+        chunk.push(0x08);
+        let err = parser.push(&chunk).unwrap_err();
+        assert_eq!(parser.pending(), 0);
+        assert_eq!(parser.push(&stream), Err(err));
+        assert_eq!(parser.finish(), Err(err));
+    }
+
+    #[test]
+    fn test_struct_def_stream_parser() {
+        // Person { string name; address wallet } then Mail { Person from; string contents },
+        // with the primary type record before the struct it names
+        let stream = hex::decode(concat!(
+            "01044d61696c",
+            "0006506572736f6e",
+            "ff05046e616d65",
+            "ff030677616c6c6574",
+            "00044d61696c",
+            "ff0006506572736f6e0466726f6d",
+            "ff0508636f6e74656e7473",
+        ))
+        .unwrap();
+
+        for split in 0..=stream.len() {
+            let mut parser = StructDefStreamParser::new();
+            parser.push(&stream[..split]).unwrap();
+            parser.push(&stream[split..]).unwrap();
+            assert_eq!(parser.primary_type(), Some("Mail"));
+            let (struct_defs, primary_type) = parser.finish().unwrap();
+            assert_eq!(primary_type.as_deref(), Some("Mail"));
+            assert_eq!(
+                struct_defs.keys().collect::<Vec<_>>(),
+                vec!["Mail", "Person"]
+            );
+            assert_eq!(struct_defs["Person"][1].name, "wallet");
+            assert_eq!(
+                struct_defs["Mail"][0].field_type,
+                Eip712FieldType::Custom("Person".into())
+            );
+        }
+
+        // without the marker
+        let mut parser = StructDefStreamParser::new();
+        parser.push(&stream[6..]).unwrap();
+        assert_eq!(parser.finish().unwrap().1, None);
+
+        // primary type not defined
+        let mut parser = StructDefStreamParser::new();
+        parser
+            .push(&hex::decode("01044d61696c0006506572736f6e").unwrap())
+            .unwrap();
+        assert!(parser.finish().is_err());
+
+        // field before any struct name, duplicate marker, unknown tag, truncated
+        let mut parser = StructDefStreamParser::new();
+        assert!(
+            parser
+                .push(&hex::decode("ff05046e616d65").unwrap())
+                .is_err()
+        );
+        let mut parser = StructDefStreamParser::new();
+        assert!(
+            parser
+                .push(&hex::decode("01014101014101").unwrap())
+                .is_err()
+        );
+        let mut parser = StructDefStreamParser::new();
+        assert!(parser.push(&[0x02]).is_err());
+        let mut parser = StructDefStreamParser::new();
+        parser.push(&stream[..stream.len() - 1]).unwrap();
+        assert!(parser.finish().is_err());
+
+        // a chunk failing after some applied records, the stream can not be resumed to apply
+        // them twice
+        let mut parser = StructDefStreamParser::new();
+        let err = parser
+            .push(&hex::decode("0006506572736f6eff05046e616d6502").unwrap())
+            .unwrap_err();
+        assert_eq!(err, "Invalid struct definition record tag");
+        assert_eq!(parser.push(&stream[6..]), Err(err));
+        assert_eq!(parser.finish(), Err(err));
+    }
+
+    #[test]
+    fn test_parse_eip712_domain_unknown_field() {
+        let field_defs = vec![Eip712FieldDefinition::new(
+            Eip712FieldType::String,
+            "owner".to_string(),
+        )];
+        let struct_impl = Eip712StructImplementation::new("EIP712Domain".to_string())
+            .with_value(Eip712FieldValue::from_string("Cow"));
+        let mut eip712_domain = Default::default();
+        assert_eq!(
+            struct_impl.parse_eip712_domain(&field_defs, &mut eip712_domain),
+            Err("invalid domain field")
+        );
+    }
+
+    #[test]
+    fn test_parse_eip712_domain_invalid_fields() {
+        let field_defs = vec![
+            Eip712FieldDefinition::new(Eip712FieldType::Uint(32), "chainId".to_string()),
+            Eip712FieldDefinition::new(Eip712FieldType::Address, "verifyingContract".to_string()),
+        ];
+        let parse = |chain_id: Vec<u8>, contract: Vec<u8>| {
+            let struct_impl = Eip712StructImplementation::new("EIP712Domain".to_string())
+                .with_value(Eip712FieldValue::from_bytes(chain_id))
+                .with_value(Eip712FieldValue::from_bytes(contract));
+            let mut eip712_domain = Default::default();
+            struct_impl
+                .parse_eip712_domain(&field_defs, &mut eip712_domain)
+                .map(|_| eip712_domain)
+        };
+
+        let domain = parse(vec![0x01], vec![0xcc; 20]).unwrap();
+        assert_eq!(domain.chain_id, Some(U256::from(1)));
+
+        // chainId wider than u64 is still a valid u256
+        let domain = parse(vec![0x01; 32], vec![0xcc; 20]).unwrap();
+        assert_eq!(domain.chain_id, Some(U256::from_be_bytes([0x01; 32])));
+
+        assert_eq!(
+            parse(vec![], vec![0xcc; 20]).unwrap_err(),
+            "invalid domain field: chainId"
+        );
+        assert_eq!(
+            parse(vec![0x01; 40], vec![0xcc; 20]).unwrap_err(),
+            "invalid domain field: chainId"
+        );
+        assert_eq!(
+            parse(vec![0x01], vec![0xcc; 21]).unwrap_err(),
+            "invalid domain field: verifyingContract"
+        );
+    }
+
+    #[test]
+    fn test_parse_eip712_domain_uint32_chain_id() {
+        let parse = |chain_type: Eip712FieldType, chain_id: Vec<u8>| {
+            let field_defs = vec![Eip712FieldDefinition::new(
+                chain_type,
+                "chainId".to_string(),
+            )];
+            let struct_impl = Eip712StructImplementation::new("EIP712Domain".to_string())
+                .with_value(Eip712FieldValue::from_bytes(chain_id));
+            let mut eip712_domain = Default::default();
+            struct_impl
+                .parse_eip712_domain(&field_defs, &mut eip712_domain)
+                .map(|_| eip712_domain.chain_id)
+        };
+
+        let uint32 = Eip712FieldType::Uint(4);
+        assert_eq!(
+            parse(uint32.clone(), vec![0x00, 0x00, 0x04, 0x05]),
+            Ok(Some(U256::from(1029)))
+        );
+        assert_eq!(
+            parse(uint32.clone(), vec![0xff; 4]),
+            Ok(Some(U256::from(u32::MAX)))
+        );
+        // leading zeros stripped
+        assert_eq!(
+            parse(uint32.clone(), vec![0x04, 0x05]),
+            Ok(Some(U256::from(1029)))
+        );
+        // out of the uint32 range
+        assert_eq!(
+            parse(uint32.clone(), vec![0x01, 0x00, 0x00, 0x00, 0x00]),
+            Err("invalid domain field: chainId")
+        );
+        // the declared width bounds the value of any uint
+        assert_eq!(
+            parse(Eip712FieldType::Uint(8), vec![0x01; 9]),
+            Err("invalid domain field: chainId")
+        );
+        assert_eq!(
+            parse(Eip712FieldType::String, vec![0x01]),
+            Err("invalid domain field: chainId")
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_load_struct_defs() {
+        use super::load_struct_defs;
+        use std::path::Path;
+
+        let res_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("res");
+        let defs = load_struct_defs(&res_dir.join("types/mail_types.json")).unwrap();
+        assert_eq!(defs, crate::test_utils::prepare_mail_struct_defs());
+        // the types of a whole typed data
+        let defs = load_struct_defs(&res_dir.join("mail.json")).unwrap();
+        assert_eq!(defs, crate::test_utils::prepare_mail_struct_defs());
+
+        let err = load_struct_defs(&res_dir.join("types/missing.json")).unwrap_err();
+        assert!(err.starts_with("io error"));
+        let err = load_struct_defs(&res_dir.join("../Cargo.toml")).unwrap_err();
+        assert!(err.starts_with("json error"));
+    }
+
+    #[test]
+    fn test_field_def_from_bytes_array_level_errors() {
+        let decode = |h: &str| Eip712FieldDefinition::from_bytes(&hex::decode(h).unwrap());
+
+        // uint256[] a
+        assert!(decode("c22001000161").is_ok());
+        assert_eq!(decode("c22001020161"), Err("Invalid array level tag"));
+        assert_eq!(
+            Eip712FieldDefinition::encoded_len(&hex::decode("c22001020161").unwrap()),
+            Err("Invalid array level tag")
+        );
+        // the second fixed level is cut before its size
+        assert_eq!(
+            decode("c22002010501"),
+            Err("Unexpected end of input when reading array level size")
+        );
+        assert_eq!(
+            decode("c220050000"),
+            Err("Array level count exceeds the remaining input")
+        );
+        assert_eq!(
+            decode("c220"),
+            Err("Unexpected end of input when reading array level count")
+        );
+    }
+
+    #[test]
+    fn test_field_def_fixed_zero_array() {
+        // uint256[0] a
+        assert_eq!(
+            Eip712FieldDefinition::from_bytes(&hex::decode("c2200101000161").unwrap()),
+            Err("Invalid array size")
+        );
+        assert!(Eip712FieldDefinition::from_bytes(&hex::decode("c2200101010161").unwrap()).is_ok());
+
+        let def = Eip712FieldDefinition::new(Eip712FieldType::Uint(32), "a".to_string())
+            .with_array_level(Eip712ArrayLevel::Fixed(0));
+        assert_eq!(def.to_bytes(), Err("Invalid array size"));
+        assert_eq!(
+            def.matches_value(&Eip712FieldValue {
+                value: vec![0],
+                is_array_size: true,
+            }),
+            Err("a: invalid fixed array size 0".to_string())
+        );
+        assert!(Eip712FieldDefinition::from_str("a", "uint256[0]").is_err());
+    }
+
+    #[test]
+    fn test_field_def_from_bytes_strict() {
+        let field = Eip712FieldDefinition::new(Eip712FieldType::Uint(32), "amount".to_string())
+            .with_array_level(Eip712ArrayLevel::Fixed(2));
+        let bytes = field.to_bytes().unwrap();
+        assert_eq!(
+            Eip712FieldDefinition::from_bytes_strict(&bytes),
+            Ok(field.clone())
+        );
+
+        let mut over = bytes.clone();
+        over.push(0x05);
+        assert_eq!(
+            Eip712FieldDefinition::from_bytes_strict(&over),
+            Err("extra data after field definition")
+        );
+        // the lenient decoding ignores the trailing bytes
+        assert_eq!(Eip712FieldDefinition::from_bytes(&over), Ok(field));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_field_def_from_bytes_fixtures() {
+        use super::load_struct_defs;
+        use std::{fs, path::Path};
+
+        let res_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("res");
+        for entry in fs::read_dir(&res_dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let defs = load_struct_defs(&path).unwrap();
+            for fields in defs.values() {
+                for field in fields {
+                    let bytes = field.to_bytes().unwrap();
+                    let decoded = Eip712FieldDefinition::from_bytes(&bytes).unwrap();
+                    assert_eq!(&decoded, field, "{}", path.display());
+                }
+            }
+        }
+    }
+
+    // the heap and the stack buffers decode the same definitions, names longer than the inline
+    // capacity included
+    #[cfg(all(feature = "std", feature = "smallvec"))]
+    #[test]
+    fn test_field_def_name_buffers() {
+        use super::load_struct_defs;
+        use std::{fs, path::Path};
+
+        let decode = |bytes: &[u8]| {
+            let heap = Eip712FieldDefinition::from_buf_with::<Vec<u8>>(&mut &bytes[..]);
+            let stack = Eip712FieldDefinition::from_buf_with::<smallvec::SmallVec<[u8; 32]>>(
+                &mut &bytes[..],
+            );
+            assert_eq!(heap, stack);
+            heap
+        };
+
+        let mut fields = vec![
+            Eip712FieldDefinition::new(Eip712FieldType::Bool, "a".repeat(33)),
+            Eip712FieldDefinition::new(Eip712FieldType::Custom("P".repeat(200)), "b".repeat(255)),
+            Eip712FieldDefinition::new(Eip712FieldType::String, "c".repeat(32)),
+        ];
+        let res_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("res");
+        for entry in fs::read_dir(&res_dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                fields.extend(load_struct_defs(&path).unwrap().into_values().flatten());
+            }
+        }
+        for field in fields {
+            let bytes = field.to_bytes().unwrap();
+            assert_eq!(decode(&bytes), Ok(field));
+            // truncated in the name
+            assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        }
+        // invalid UTF-8 past the inline capacity
+        let mut bytes = vec![0x04, 40];
+        bytes.extend([b'x'; 39]);
+        bytes.push(0xff);
+        assert_eq!(decode(&bytes), Err("Invalid UTF-8 in custom type"));
+    }
+
+    #[test]
+    fn test_struct_defs_map_round_trip() {
+        let struct_defs = crate::test_utils::prepare_mail_struct_defs();
+        let defs = map_to_defs(struct_defs.clone());
+        assert_eq!(defs.len(), struct_defs.len());
+        assert_eq!(defs[0].name, "EIP712Domain");
+        assert_eq!(defs_to_map(defs), struct_defs);
+
+        let person = Eip712StructDefinition::from_map_entry(
+            "Person".to_string(),
+            struct_defs["Person"].clone(),
+        );
+        assert_eq!(
+            person.to_map_entry(),
+            ("Person".to_string(), struct_defs["Person"].clone())
+        );
+    }
+
+    #[test]
+    fn test_diff_struct_defs() {
+        let defs = crate::test_utils::prepare_mail_struct_defs();
+        assert!(diff_struct_defs(&defs, &defs).is_empty());
+
+        let mut swapped = defs.clone();
+        swapped.get_mut("Mail").unwrap().swap(4, 5);
+        assert_eq!(
+            diff_struct_defs(&defs, &swapped),
+            [StructDiff::FieldsReordered {
+                struct_name: "Mail".to_string()
+            }]
+        );
+
+        let mut changed = defs.clone();
+        changed.remove("EIP712Domain");
+        let person = changed.get_mut("Person").unwrap();
+        person[0].field_type = Eip712FieldType::FixedBytes(32);
+        person.push(Eip712FieldDefinition::new(
+            Eip712FieldType::Bool,
+            "active".to_string(),
+        ));
+        assert_eq!(
+            diff_struct_defs(&defs, &changed),
+            [
+                StructDiff::StructRemoved("EIP712Domain".to_string()),
+                StructDiff::TypeMismatch {
+                    struct_name: "Person".to_string(),
+                    field: "name".to_string(),
+                    a: "string".to_string(),
+                    b: "bytes32".to_string(),
+                },
+                StructDiff::FieldAdded {
+                    struct_name: "Person".to_string(),
+                    field: "active".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_struct_to_property_defs() {
+        let defs = vec![
+            Eip712FieldDefinition::new(Eip712FieldType::String, "name".to_string()),
+            Eip712FieldDefinition::new(
+                Eip712FieldType::Custom("Bad Type".to_string()),
+                "owner".to_string(),
+            ),
+        ];
+        assert_eq!(struct_to_property_defs(&defs[..1]).unwrap().len(), 1);
+        assert_eq!(
+            struct_to_property_defs(&defs).unwrap_err(),
+            (1, "Bad Type".to_string())
+        );
+
+        let mut struct_defs = Eip712StructDefinitions::new();
+        struct_defs.insert("Person".to_string(), defs);
+        assert_eq!(
+            build_resolver_from_struct_defs(&struct_defs).unwrap_err(),
+            "invalid type Bad Type of field Person.owner"
+        );
+    }
+
+    #[test]
+    fn test_validate_domain() {
+        let mut domain = crate::Eip712Domain {
+            name: Some("Simple Mail".into()),
+            version: Some("1".into()),
+            chain_id: Some(U256::from(1)),
+            verifying_contract: Some([0xcc; 20].into()),
+            salt: None,
+        };
+        assert!(validate_domain(&domain).is_empty());
+
+        domain.version = Some(" ".into());
+        assert_eq!(validate_domain(&domain), ["domain version is empty"]);
+
+        domain.version = None;
+        domain.chain_id = Some(U256::ZERO);
+        domain.verifying_contract = Some(Default::default());
+        assert_eq!(
+            validate_domain(&domain),
+            [
+                "domain chainId is zero",
+                "domain verifyingContract is the zero address"
+            ]
+        );
+    }
+
+    fn check_from_bytes(bytes: &[u8]) {
+        let Ok(def) = Eip712FieldDefinition::from_bytes(bytes) else {
+            return;
+        };
+        let encoded = def.to_bytes().unwrap();
+        assert_eq!(Eip712FieldDefinition::from_bytes(&encoded), Ok(def.clone()));
+        assert_eq!(
+            Eip712FieldDefinition::encoded_len(&encoded),
+            Ok(Some(encoded.len()))
+        );
+    }
+
+    #[cfg(feature = "fuzz")]
+    mod fuzz {
+        use super::check_from_bytes;
+        use proptest::prelude::*;
+
+        // favour small values so lengths and type ids are often valid
+        fn field_def_bytes() -> impl Strategy<Value = Vec<u8>> {
+            proptest::collection::vec(prop_oneof![0u8..8, any::<u8>()], 0..48)
+        }
+
+        proptest! {
+            #![proptest_config(ProptestConfig::with_cases(20_000))]
+
+            #[test]
+            fn test_field_def_from_bytes_random(bytes in field_def_bytes()) {
+                check_from_bytes(&bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_field_def_from_bytes_regressions() {
+        let seeds: [&[u8]; 8] = [
+            &[],
+            &[0x00],
+            &[0x00, 0xff],
+            &[0x80, 0x05],
+            &[0xc2, 0x20, 0x01, 0x01, 0xff, 0x01, 0x61],
+            &[0x85, 0x02, 0x00, 0x01, 0x02, 0x02, 0x63, 0x63],
+            &[0x03, 0xff, 0xff],
+            &[0x00, 0x02, 0xff, 0xfe, 0x00],
+        ];
+        for seed in seeds {
+            check_from_bytes(seed);
+        }
+
+        let def = Eip712FieldDefinition::new(Eip712FieldType::Uint(32), "amount".to_string())
+            .with_array_level(Eip712ArrayLevel::Fixed(2))
+            .with_array_level(Eip712ArrayLevel::Dynamic);
+        let encoded = def.to_bytes().unwrap();
+        assert_eq!(hex::encode(&encoded), "c2200201020006616d6f756e74");
+        assert_eq!(Eip712FieldDefinition::from_bytes(&encoded), Ok(def));
+
+        let long_name = Eip712FieldDefinition::new(Eip712FieldType::Bool, "a".repeat(256));
+        assert_eq!(long_name.to_bytes(), Err("name too long"));
+    }
+
+    #[test]
+    fn test_array_levels_type_string() {
+        use Eip712ArrayLevel::{Dynamic, Fixed};
+
+        let cases = [
+            (vec![Dynamic], "string[]"),
+            (vec![Fixed(3)], "string[3]"),
+            (vec![Dynamic, Dynamic, Fixed(2)], "string[][][2]"),
+            (vec![Fixed(2), Dynamic], "string[2][]"),
+            (vec![Fixed(1), Fixed(4), Dynamic], "string[1][4][]"),
+        ];
+        for (levels, expected) in cases {
+            let def = Eip712FieldDefinition {
+                name: "cc".to_string(),
+                field_type: Eip712FieldType::String,
+                array_levels: levels.clone(),
+            };
+            assert_eq!(def.type_string(), expected);
+            // parsing the type string gives back the same levels
+            assert_eq!(
+                Eip712FieldDefinition::from_str("cc", expected)
+                    .unwrap()
+                    .array_levels,
+                levels
+            );
+
+            // alloy renders the same encodeType
+            let mut struct_defs = Eip712StructDefinitions::new();
+            struct_defs.insert("Mail".to_string(), vec![def]);
+            let resolver = build_resolver_from_struct_defs(&struct_defs).unwrap();
+            assert_eq!(
+                resolver.encode_type("Mail").unwrap(),
+                format!("Mail({} cc)", expected)
+            );
+        }
+    }
+
+    #[test]
+    fn test_array_levels_iter() {
+        let cc3 = Eip712FieldDefinition::new(Eip712FieldType::String, "cc3".to_string())
+            .with_array_level(Eip712ArrayLevel::Dynamic)
+            .with_array_level(Eip712ArrayLevel::Dynamic)
+            .with_array_level(Eip712ArrayLevel::Fixed(2));
+        assert_eq!(cc3.type_string(), "string[][][2]");
+
+        let sizes: Vec<_> = cc3.array_levels_iter().map(|level| level.size()).collect();
+        assert_eq!(sizes, [Some(2), None, None]);
+        assert_eq!(cc3.innermost_element_type(), &Eip712FieldType::String);
+
+        let name = Eip712FieldDefinition::new(Eip712FieldType::String, "name".to_string());
+        assert_eq!(name.array_levels_iter().count(), 0);
+    }
+
+    #[test]
+    fn test_parse_field_type_options() {
+        let loose = ParseOptions {
+            allow_byte: true,
+            allow_bare_int: true,
+        };
+        let parse = |type_str, opts| Eip712FieldType::parse_field_type_with_options(type_str, opts);
+
+        assert_eq!(parse("uint", &loose), Ok(Eip712FieldType::Uint(32)));
+        assert_eq!(parse("int", &loose), Ok(Eip712FieldType::Int(32)));
+        assert_eq!(parse("byte", &loose), Ok(Eip712FieldType::FixedBytes(1)));
+        // bare bytes stays dynamic
+        assert_eq!(parse("bytes", &loose), Ok(Eip712FieldType::DynamicBytes));
+
+        let strict = ParseOptions::default();
+        assert!(parse("uint", &strict).is_err());
+        assert!(parse("int", &strict).is_err());
+        assert_eq!(
+            parse("byte", &strict),
+            Err("byte is not an EIP-712 type, use bytes1".to_string())
+        );
+        assert_eq!(parse("uint256", &strict), Ok(Eip712FieldType::Uint(32)));
+
+        let field =
+            Eip712FieldDefinition::from_str_with_options("flags", "byte[]", &loose).unwrap();
+        assert_eq!(field.type_string(), "bytes1[]");
+        assert_eq!(
+            Eip712FieldDefinition::from_str("flags", "byte[]"),
+            Err("invalid field type")
+        );
+    }
+
+    #[test]
+    fn test_field_type_custom() {
+        assert_eq!(
+            Eip712FieldType::custom("Person"),
+            Ok(Eip712FieldType::Custom("Person".to_string()))
+        );
+        assert!(Eip712FieldType::custom("_Order$2").is_ok());
+
+        for name in ["address", "uint256", "bytes32", "bool", "int8"] {
+            assert_eq!(
+                Eip712FieldType::custom(name),
+                Err(format!("Primitive type name used as a struct: {}", name))
+            );
+        }
+        // malformed primitives are not struct names either
+        assert!(Eip712FieldType::custom("uint7").is_err());
+        assert!(Eip712FieldType::custom("byte").is_err());
+
+        for name in ["", "2Person", "Person[]", "Per son", "Person.Name"] {
+            assert_eq!(
+                Eip712FieldType::custom(name),
+                Err(format!("Invalid struct name: {}", name))
+            );
+        }
+    }
+
+    #[test]
+    fn test_field_type_expected_value_len() {
+        assert_eq!(Eip712FieldType::Address.expected_value_len(), Some(20));
+        assert_eq!(Eip712FieldType::Bool.expected_value_len(), Some(1));
+        assert_eq!(Eip712FieldType::FixedBytes(4).expected_value_len(), Some(4));
+        assert_eq!(Eip712FieldType::Uint(8).expected_value_len(), Some(8));
+        assert_eq!(Eip712FieldType::String.expected_value_len(), None);
+        assert_eq!(Eip712FieldType::DynamicBytes.expected_value_len(), None);
+        assert_eq!(
+            Eip712FieldType::Custom("Person".to_string()).expected_value_len(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_field_type_is_dynamic() {
+        assert!(Eip712FieldType::String.is_dynamic());
+        assert!(Eip712FieldType::DynamicBytes.is_dynamic());
+        assert!(!Eip712FieldType::Uint(32).is_dynamic());
+        assert!(!Eip712FieldType::Address.is_dynamic());
+        assert!(!Eip712FieldType::FixedBytes(32).is_dynamic());
+        assert!(!Eip712FieldType::Custom("Person".to_string()).is_dynamic());
+        assert_eq!(Eip712FieldType::String.encoded_head_size(), 32);
+        assert_eq!(Eip712FieldType::Bool.encoded_head_size(), 32);
+
+        let field_def = Eip712FieldDefinition::from_str("wallets", "address[]").unwrap();
+        assert!(field_def.is_dynamic());
+        assert_eq!(field_def.encoded_head_size(), 32);
+        let field_def = Eip712FieldDefinition::from_str("amount", "uint256").unwrap();
+        assert!(!field_def.is_dynamic());
+        let field_def = Eip712FieldDefinition::from_str("data", "bytes").unwrap();
+        assert!(field_def.is_dynamic());
+    }
+
+    #[test]
+    fn test_validate_struct_defs_empty_struct() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        struct_defs.insert(
+            "Mail".to_string(),
+            vec![Eip712FieldDefinition::new(
+                Eip712FieldType::Custom("Empty".to_string()),
+                "empty".to_string(),
+            )],
+        );
+        struct_defs.insert("Empty".to_string(), vec![]);
+
+        let warnings = validate_struct_defs(&struct_defs, &Default::default()).unwrap();
+        assert_eq!(warnings, ["Empty has no fields"]);
+
+        let opts = ValidateOptions {
+            empty_struct: EmptyStructPolicy::Allow,
+        };
+        assert!(
+            validate_struct_defs(&struct_defs, &opts)
+                .unwrap()
+                .is_empty()
+        );
+
+        let opts = ValidateOptions {
+            empty_struct: EmptyStructPolicy::Reject,
+        };
+        assert_eq!(
+            validate_struct_defs(&struct_defs, &opts).unwrap_err(),
+            "Empty has no fields"
+        );
+    }
+
+    #[test]
+    fn test_validate_struct_defs_invalid() {
+        let mut struct_defs: Eip712StructDefinitions = Default::default();
+        struct_defs.insert(
+            "Mail".to_string(),
+            vec![Eip712FieldDefinition::new(
+                Eip712FieldType::Custom("Person".to_string()),
+                "from".to_string(),
+            )],
+        );
+        assert_eq!(
+            validate_struct_defs(&struct_defs, &Default::default()).unwrap_err(),
+            "Mail.from references undefined type Person"
+        );
+
+        struct_defs.insert(
+            "Person".to_string(),
+            vec![
+                Eip712FieldDefinition::new(Eip712FieldType::String, "name".to_string()),
+                Eip712FieldDefinition::new(Eip712FieldType::String, "name".to_string()),
+            ],
+        );
+        assert_eq!(
+            validate_struct_defs(&struct_defs, &Default::default()).unwrap_err(),
+            "Person has duplicate field name"
+        );
+    }
 }
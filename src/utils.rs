@@ -1,10 +1,20 @@
-use alloc::{borrow::ToOwned, string::String, vec};
+use alloc::{borrow::ToOwned, format, string::String, vec, vec::Vec};
 use alloy_primitives::{I256, U256};
 
 pub fn parse_utf8_string(data: &[u8]) -> Result<String, &'static str> {
     String::from_utf8(data.to_owned()).map_err(|_| "Invalid UTF-8 in custom type")
 }
 
+// a bool is exactly one 0x00 or 0x01 byte
+pub fn parse_bool(data: &[u8]) -> Result<bool, &'static str> {
+    match data {
+        [0] => Ok(false),
+        [1] => Ok(true),
+        [_] => Err("invalid bool value"),
+        _ => Err("invalid bool len"),
+    }
+}
+
 pub fn parse_u64(data: &[u8]) -> Result<u64, &'static str> {
     if data.len() > 8 {
         return Err("data len should be <= 8");
@@ -22,9 +32,37 @@ pub fn parse_u16(data: &[u8]) -> Result<u16, &'static str> {
     Ok(u16::from_be_bytes(bytes))
 }
 
-// if value is negative, then it must be 16 bytes with sign extension
+pub fn parse_u32(data: &[u8]) -> Result<u32, &'static str> {
+    let bytes: [u8; 4] = data.try_into().map_err(|_| "data len should be 4")?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+// strip the sign extension of a value encoded in more than its declared `size` bytes, the extra
+// high bytes must be 0xFF for a negative value and 0x00 for a positive one
+pub fn canonical_int_bytes(data: &[u8], size: usize) -> Result<&[u8], &'static str> {
+    if data.len() <= size {
+        return Ok(data);
+    }
+    if data.len() > 32 {
+        return Err("int len should be <= 32");
+    }
+    let (high, significant) = data.split_at(data.len() - size);
+    let sign = match significant.first() {
+        Some(b) if b & 0x80 != 0 => 0xFF,
+        _ => 0x00,
+    };
+    if high.iter().any(|b| *b != sign) {
+        return Err("non-canonical int encoding");
+    }
+    Ok(significant)
+}
+
 pub fn parse_i128(data: &[u8], size: usize) -> Result<i128, &'static str> {
-    if data.len() > size {
+    if size == 0 || size > 16 {
+        return Err("i128 size should be in 1..=16");
+    }
+    let data = canonical_int_bytes(data, size)?;
+    if data.len() > 16 {
         return Err("i128 len should be <= 16");
     }
     let mut pad = vec![0u8; size - data.len()];
@@ -46,9 +84,12 @@ pub fn parse_u128(data: &[u8]) -> Result<u128, &'static str> {
     Ok(u128::from_be_bytes(buf))
 }
 
-// if value is negative, then it must be 32 bytes with sign extension
 pub fn parse_i256(data: &[u8], size: usize) -> Result<I256, &'static str> {
-    if data.len() > size {
+    if size == 0 || size > 32 {
+        return Err("int size should be in 1..=32");
+    }
+    let data = canonical_int_bytes(data, size)?;
+    if data.len() > 32 {
         return Err("i256 len should be <= 32");
     }
     let mut pad = vec![0u8; size - data.len()];
@@ -61,6 +102,11 @@ pub fn parse_i256(data: &[u8], size: usize) -> Result<I256, &'static str> {
     Ok(I256::from_be_bytes(buf))
 }
 
+// parse a signed value of the declared `size` in bytes, sign extended from the top bit
+pub fn parse_int(data: &[u8], size: usize) -> Result<I256, &'static str> {
+    parse_i256(data, size)
+}
+
 pub fn parse_u256(data: &[u8]) -> Result<U256, &'static str> {
     if data.len() > 32 {
         return Err("u256 len should be <= 32");
@@ -70,11 +116,73 @@ pub fn parse_u256(data: &[u8]) -> Result<U256, &'static str> {
     Ok(U256::from_be_bytes(buf))
 }
 
+// number of bytes of a big-endian value once leading zeros are ignored, zero has none
+pub fn significant_bytes(data: &[u8]) -> usize {
+    data.iter()
+        .position(|b| *b != 0)
+        .map_or(0, |start| data.len() - start)
+}
+
+// parse an unsigned value of the declared `size` in bytes, any longer input is rejected
+pub fn parse_u256_checked(data: &[u8], size: usize) -> Result<U256, String> {
+    if size > 32 {
+        return Err(format!("invalid uint size: {}", size));
+    }
+    if data.len() > size {
+        return Err(format!(
+            "uint{} value len {} exceeds {} bytes",
+            size * 8,
+            data.len(),
+            size
+        ));
+    }
+    Ok(parse_u256(data)?)
+}
+
+/// Data item iterator wrapper counting how many items were pulled
+pub struct CountingItems<I> {
+    inner: I,
+    consumed: usize,
+}
+
+impl<I> CountingItems<I> {
+    pub fn new(inner: I) -> Self {
+        CountingItems { inner, consumed: 0 }
+    }
+
+    /// Number of items returned so far
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I: Iterator<Item = Vec<u8>>> Iterator for CountingItems<I> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.inner.next()?;
+        self.consumed += 1;
+        Some(item)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use alloy_primitives::hex;
 
+    #[test]
+    fn test_parse_u32() {
+        assert_eq!(parse_u32(&[0x00, 0x00, 0x04, 0x05]), Ok(1029));
+        assert_eq!(parse_u32(&[0xff; 4]), Ok(u32::MAX));
+        assert_eq!(parse_u32(&[0x04, 0x05]), Err("data len should be 4"));
+        assert_eq!(parse_u32(&[0x00; 5]), Err("data len should be 4"));
+    }
+
     #[test]
     fn test_parse_i128() {
         let data = hex::decode("ffffffffffffffffffffffffffffff80").unwrap();
@@ -101,6 +209,10 @@ mod tests {
         assert_eq!(parse_i128(&data, 1).unwrap(), -8);
         let data = hex::decode("08").unwrap();
         assert_eq!(parse_i128(&data, 1).unwrap(), 8);
+
+        assert!(parse_i128(&data, 0).is_err());
+        assert!(parse_i128(&data, 17).is_err());
+        assert!(parse_i128(&[], 0).is_err());
     }
 
     #[test]
@@ -108,5 +220,87 @@ mod tests {
         let data = hex::decode("ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff00")
             .unwrap();
         assert_eq!(parse_i256(&data, 32).unwrap().as_i64(), -256);
+
+        assert!(parse_i256(&data, 0).is_err());
+        assert!(parse_i256(&data, 33).is_err());
+        assert!(parse_i256(&[0x01], 33).is_err());
+    }
+
+    #[test]
+    fn test_parse_int() {
+        let data = hex::decode("ff").unwrap();
+        assert_eq!(parse_int(&data, 1).unwrap(), I256::MINUS_ONE);
+        let data = hex::decode("ff").unwrap();
+        assert_eq!(parse_int(&data, 2).unwrap(), I256::try_from(255).unwrap());
+        let data = hex::decode("ffffffffffffffffffffffffffffffffffffffffffffff00").unwrap();
+        assert_eq!(parse_int(&data, 24).unwrap(), I256::try_from(-256).unwrap());
+        assert_eq!(parse_int(&[], 4).unwrap(), I256::ZERO);
+
+        assert!(parse_int(&data, 0).is_err());
+        assert!(parse_int(&data, 33).is_err());
+        // sign extended beyond the declared size
+        assert_eq!(parse_int(&data, 16).unwrap(), I256::try_from(-256).unwrap());
+    }
+
+    #[test]
+    fn test_non_canonical_int8() {
+        // -1 and 127 as int8 sign extended to 32 bytes
+        let mut data = [0xffu8; 32];
+        assert_eq!(parse_int(&data, 1).unwrap(), I256::MINUS_ONE);
+        data = [0u8; 32];
+        data[31] = 0x7f;
+        assert_eq!(parse_i128(&data, 1).unwrap(), 127);
+
+        // high bytes do not match the sign of the int8 value
+        assert_eq!(
+            parse_int(&[0x00, 0xff], 1),
+            Err("non-canonical int encoding")
+        );
+        assert_eq!(
+            parse_int(&[0xff, 0x7f], 1),
+            Err("non-canonical int encoding")
+        );
+        assert_eq!(
+            parse_i128(&[0x01, 0x80], 1),
+            Err("non-canonical int encoding")
+        );
+        assert!(parse_i256(&[0xff; 33], 1).is_err());
+    }
+
+    #[test]
+    fn test_parse_bool() {
+        assert_eq!(parse_bool(&[0]), Ok(false));
+        assert_eq!(parse_bool(&[1]), Ok(true));
+        assert_eq!(parse_bool(&[2]), Err("invalid bool value"));
+        assert_eq!(parse_bool(&[]), Err("invalid bool len"));
+        assert_eq!(parse_bool(&[0, 1]), Err("invalid bool len"));
+    }
+
+    #[test]
+    fn test_significant_bytes() {
+        assert_eq!(significant_bytes(&[]), 0);
+        assert_eq!(significant_bytes(&[0u8; 32]), 0);
+        let mut padded = [0u8; 32];
+        padded[31] = 0x2a;
+        assert_eq!(significant_bytes(&padded), 1);
+        padded[30] = 0x01;
+        assert_eq!(significant_bytes(&padded), 2);
+        assert_eq!(significant_bytes(&[0xff; 32]), 32);
+    }
+
+    #[test]
+    fn test_parse_u256_checked() {
+        assert_eq!(parse_u256_checked(&[0xff], 1).unwrap(), U256::from(255));
+        assert_eq!(parse_u256_checked(&[], 1).unwrap(), U256::ZERO);
+        assert_eq!(
+            parse_u256_checked(&[0x0f, 0x42, 0x40], 4).unwrap(),
+            U256::from(1_000_000)
+        );
+
+        let err = parse_u256_checked(&[0x01, 0xff], 1).unwrap_err();
+        assert_eq!(err, "uint8 value len 2 exceeds 1 bytes");
+        assert!(parse_u256_checked(&[0x01; 17], 16).is_err());
+        assert!(parse_u256_checked(&[0x01; 33], 32).is_err());
+        assert!(parse_u256_checked(&[0x01], 33).is_err());
     }
 }